@@ -0,0 +1,197 @@
+//! Opening books in the Polyglot `.bin` format.
+//!
+//! A book is a list of 16 byte entries sorted by the Polyglot hash of the
+//! position, which is computed by [`polyglot_key`]. This hash is defined by the
+//! format and is unrelated to the [`zobrist`](crate::zobrist) hashes used by
+//! the search. Each entry holds a move and a weight, and moves are picked
+//! randomly in proportion to their weights.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::types::{BoardVector, Color, Move, PieceKind, PseudoMove, Square};
+use crate::{MoveGen, Position};
+
+use keys::RANDOM64;
+
+mod keys;
+#[cfg(test)]
+mod tests;
+
+/// The size in bytes of an entry in a book file.
+const ENTRY_SIZE: usize = 16;
+/// The offset in [`RANDOM64`] of the castling keys.
+const CASTLING_OFFSET: usize = 768;
+/// The offset in [`RANDOM64`] of the en passant keys.
+const EN_PASSANT_OFFSET: usize = 772;
+/// The index in [`RANDOM64`] of the key for white to move.
+const TURN_INDEX: usize = 780;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+}
+
+/// A Polyglot opening book.
+pub struct Book {
+    /// The entries sorted by key.
+    entries: Vec<BookEntry>,
+    move_gen: MoveGen,
+}
+
+impl Book {
+    /// Reads the book at `path` into memory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LoadBookError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Parses the contents of a book file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadBookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(LoadBookError::InvalidSize(bytes.len()));
+        }
+
+        let mut entries: Vec<_> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+            })
+            .collect();
+        // Books should already be sorted, but binary search depends on it
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Self {
+            entries,
+            move_gen: MoveGen::init(),
+        })
+    }
+
+    /// Returns the number of entries in the book.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the legal moves in the book for `position` along with their
+    /// weights. Entries with moves that are not legal are skipped.
+    pub fn moves(&self, position: &Position) -> Vec<(Move, u16)> {
+        let key = polyglot_key(position);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal_moves = self.move_gen.gen_all_moves(position);
+
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(entry.mv, &legal_moves)?, entry.weight)))
+            .collect()
+    }
+
+    /// Picks a move for `position` randomly, with each move having a chance
+    /// proportional to its weight. Moves with weight zero are never picked.
+    pub fn pick_move(&self, position: &Position, rng: &mut impl Rng) -> Option<Move> {
+        let moves = self.moves(position);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut choice = rng.gen_range(0..total);
+        for (mv, weight) in moves {
+            if choice < weight as u32 {
+                return Some(mv);
+            }
+            choice -= weight as u32;
+        }
+        unreachable!("choice is less than the total weight")
+    }
+}
+
+/// Returns the Polyglot hash of `position`.
+pub fn polyglot_key(position: &Position) -> u64 {
+    let mut key = 0;
+    for sq in Square::iter() {
+        if let Some(pce) = position.pieces.get(sq) {
+            let kind = match pce.kind() {
+                PieceKind::Pawn => 0,
+                PieceKind::Knight => 1,
+                PieceKind::Bishop => 2,
+                PieceKind::Rook => 3,
+                PieceKind::Queen => 4,
+                PieceKind::King => 5,
+            };
+            let index = 2 * kind + (pce.color() == Color::White) as usize;
+            key ^= RANDOM64[64 * index + sq as usize];
+        }
+    }
+
+    // The castling bits are in the same order as the keys
+    let castling = u8::from(position.castling);
+    for i in 0..4 {
+        if castling & 1 << i != 0 {
+            key ^= RANDOM64[CASTLING_OFFSET + i];
+        }
+    }
+
+    // The en passant square only counts if a pawn can capture on it
+    if let Some(ep_sq) = position.en_passant_sq {
+        let vectors = match position.to_move {
+            Color::White => [BoardVector::SOUTH_WEST, BoardVector::SOUTH_EAST],
+            Color::Black => [BoardVector::NORTH_WEST, BoardVector::NORTH_EAST],
+        };
+        let can_capture = vectors.into_iter().any(|vector| {
+            ep_sq
+                .add_checked(vector)
+                .and_then(|sq| position.pieces.get(sq))
+                .is_some_and(|pce| pce.kind() == PieceKind::Pawn && pce.color() == position.to_move)
+        });
+        if can_capture {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + ep_sq.file() as usize];
+        }
+    }
+
+    if position.to_move == Color::White {
+        key ^= RANDOM64[TURN_INDEX];
+    }
+    key
+}
+
+/// Finds the legal move encoded by `mv`. Castling is encoded as the king
+/// capturing its own rook, like [`Move`] does.
+fn decode_move(mv: u16, legal_moves: &[Move]) -> Option<Move> {
+    let square = |bits: u16| Square::try_from((bits & 0x3f) as u8).ok();
+    let to = square(mv)?;
+    let from = square(mv >> 6)?;
+    let promotion = match (mv >> 12) & 0x7 {
+        0 => None,
+        1 => Some(PieceKind::Knight),
+        2 => Some(PieceKind::Bishop),
+        3 => Some(PieceKind::Rook),
+        4 => Some(PieceKind::Queen),
+        _ => return None,
+    };
+
+    PseudoMove::new(from, to, promotion)
+        .into_move(legal_moves)
+        .ok()
+}
+
+/// An error that can occur when loading a [`Book`].
+#[derive(thiserror::Error, Debug)]
+pub enum LoadBookError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("book size of {0} bytes is not a multiple of 16")]
+    InvalidSize(usize),
+}
@@ -0,0 +1,117 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::types::{MoveKind, Square};
+use crate::Position;
+
+use super::{polyglot_key, Book, LoadBookError};
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Encodes the given entries in the book format.
+fn book_bytes(entries: &[(u64, Square, Square, u16)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for &(key, from, to, weight) in entries {
+        let mv = (from as u16) << 6 | to as u16;
+        bytes.extend_from_slice(&key.to_be_bytes());
+        bytes.extend_from_slice(&mv.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn polyglot_keys_match_reference() {
+    let key = |fen| polyglot_key(&Position::from_fen(fen).unwrap());
+    assert_eq!(key(START_FEN), 0x463b96181691fc9c);
+    assert_eq!(
+        key("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+        0x823c9b50fd114196
+    );
+    assert_eq!(
+        key("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2"),
+        0x0756b94461c50fb0
+    );
+    assert_eq!(
+        key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"),
+        0x22a48b5a8e47ff78
+    );
+    assert_eq!(
+        key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3"),
+        0x652a607ca3f242c1
+    );
+    assert_eq!(
+        key("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3"),
+        0x3c8123ea7b067637
+    );
+}
+
+#[test]
+fn invalid_book_size() {
+    assert!(matches!(
+        Book::from_bytes(&[0; 20]),
+        Err(LoadBookError::InvalidSize(20))
+    ));
+    assert!(Book::from_bytes(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn book_moves_are_weighted() {
+    use Square::*;
+
+    let position = Position::from_fen(START_FEN).unwrap();
+    let key = polyglot_key(&position);
+    let book = Book::from_bytes(&book_bytes(&[
+        (key + 1, G1, F3, 10),
+        (key, D2, D4, 0),
+        (key, E2, E4, 3),
+        (key, C2, C4, 1),
+        // Not a legal move
+        (key, E2, E5, 100),
+    ]))
+    .unwrap();
+    assert_eq!(book.len(), 5);
+
+    let moves = book.moves(&position);
+    assert_eq!(moves.len(), 3);
+    assert!(moves
+        .iter()
+        .any(|&(mv, w)| mv.to_string() == "e2e4" && w == 3));
+
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    let mut counts = [0; 2];
+    for _ in 0..400 {
+        match book
+            .pick_move(&position, &mut rng)
+            .unwrap()
+            .to_string()
+            .as_str()
+        {
+            "e2e4" => counts[0] += 1,
+            "c2c4" => counts[1] += 1,
+            mv => panic!("picked {mv}"),
+        }
+    }
+    assert!(counts[0] > 2 * counts[1]);
+
+    let other = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(book.pick_move(&other, &mut rng), None);
+}
+
+#[test]
+fn castling_is_decoded() {
+    use Square::*;
+
+    let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let key = polyglot_key(&position);
+    let book = Book::from_bytes(&book_bytes(&[(key, E1, H1, 1), (key, E1, A1, 1)])).unwrap();
+
+    let moves = book.moves(&position);
+    assert_eq!(moves.len(), 2);
+    assert!(moves
+        .iter()
+        .all(|(mv, _)| matches!(mv.kind(), MoveKind::Castling)));
+    assert_eq!(moves[0].0.castling_king_dest(), G1);
+    assert_eq!(moves[1].0.castling_king_dest(), C1);
+}
@@ -17,6 +17,10 @@ impl MoveList {
         self.0.push(m);
     }
 
+    /// Pushes a move without checking for capacity.
+    ///
+    /// # Safety
+    /// The list must not be full.
     pub unsafe fn push_unchecked(&mut self, m: Move) {
         self.0.push_unchecked(m);
     }
@@ -33,7 +37,7 @@ impl MoveList {
         self.0.is_empty()
     }
 
-    pub fn iter(&self) -> Iter<Move> {
+    pub fn iter(&self) -> Iter<'_, Move> {
         self.0.iter()
     }
 
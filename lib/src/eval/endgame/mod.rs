@@ -0,0 +1,270 @@
+//! Endgame knowledge based on the material on the board.
+//!
+//! The [`MaterialSignature`] of a position is used to recognize draws by
+//! insufficient material, to scale down the evaluation of drawish endings
+//! and to select specialized evaluators for endings where the general
+//! evaluation is misleading, like KBNvK and KPvK.
+
+use crate::position::Pieces;
+use crate::types::{Bitboard, Color, Piece, PieceKind, Rank, Square};
+use crate::Position;
+
+use super::piece_value;
+
+#[cfg(test)]
+mod tests;
+
+/// The scale factor that leaves an evaluation unchanged.
+pub const SCALE_NORMAL: i32 = 64;
+/// The base value of an ending that is known to be won.
+const KNOWN_WIN: i16 = 1000;
+
+/// The number of pieces of each kind for both colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaterialSignature {
+    /// Indexed by [`Color`] and then [`PieceKind`].
+    counts: [[u8; 6]; 2],
+}
+
+impl MaterialSignature {
+    pub fn new(pieces: &Pieces) -> Self {
+        use PieceKind::*;
+
+        let mut counts = [[0; 6]; 2];
+        for color in [Color::White, Color::Black] {
+            for kind in [Knight, Bishop, Rook, Queen, Pawn, King] {
+                counts[color as usize][kind as usize] =
+                    pieces.get_bb(Piece(kind, color)).len() as u8;
+            }
+        }
+        Self { counts }
+    }
+
+    /// Returns the number of pieces of the given kind and color.
+    #[inline]
+    pub fn count(&self, color: Color, kind: PieceKind) -> u8 {
+        self.counts[color as usize][kind as usize]
+    }
+
+    /// Returns the value of the knights, bishops, rooks and queens of `color`.
+    #[inline]
+    pub fn non_pawn_material(&self, color: Color) -> i16 {
+        use PieceKind::*;
+
+        [Knight, Bishop, Rook, Queen]
+            .into_iter()
+            .map(|kind| self.count(color, kind) as i16 * piece_value(kind))
+            .sum()
+    }
+
+    /// Returns whether `color` has exactly the given pieces besides the king.
+    /// The counts are given in the order knights, bishops, rooks, queens and
+    /// pawns.
+    #[inline]
+    pub fn is(&self, color: Color, counts: [u8; 5]) -> bool {
+        self.counts[color as usize][..5] == counts
+    }
+}
+
+/// Returns whether neither side can possibly checkmate, i.e. there are only
+/// kings, a single minor piece, or bishops all on the same color of squares.
+pub fn is_insufficient_material(pieces: &Pieces) -> bool {
+    use PieceKind::*;
+
+    let both =
+        |kind| pieces.get_bb(Piece(kind, Color::White)) | pieces.get_bb(Piece(kind, Color::Black));
+    if !(both(Pawn) | both(Rook) | both(Queen)).is_empty() {
+        return false;
+    }
+
+    let knights = both(Knight);
+    let bishops = both(Bishop);
+    match (knights.len(), bishops.len()) {
+        (0, 0) | (1, 0) => true,
+        (0, _) => {
+            (bishops & Bitboard::LIGHT_SQUARES).is_empty()
+                || (bishops - Bitboard::LIGHT_SQUARES).is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Adjusts `eval`, the evaluation of `position` from the perspective of the
+/// side to move, using endgame knowledge.
+pub fn evaluate(position: &Position, eval: i16) -> i16 {
+    let signature = MaterialSignature::new(&position.pieces);
+    let strong = if eval >= 0 {
+        position.to_move
+    } else {
+        !position.to_move
+    };
+
+    if let Some(strong_eval) = specialized_eval(position, &signature) {
+        return strong_eval;
+    }
+
+    let scale = scale_factor(&position.pieces, &signature, strong);
+    (eval as i32 * scale / SCALE_NORMAL) as i16
+}
+
+/// Returns whether [`evaluate`] may change an evaluation of `position`,
+/// whichever side is ahead. Otherwise the evaluation is left as it is.
+pub fn adjusts_eval(position: &Position) -> bool {
+    let signature = MaterialSignature::new(&position.pieces);
+    specialized_eval(position, &signature).is_some()
+        || [Color::White, Color::Black]
+            .into_iter()
+            .any(|strong| scale_factor(&position.pieces, &signature, strong) != SCALE_NORMAL)
+}
+
+/// Returns the evaluation of endings with a specialized evaluator from the
+/// perspective of the side to move.
+fn specialized_eval(position: &Position, signature: &MaterialSignature) -> Option<i16> {
+    for strong in [Color::White, Color::Black] {
+        if !signature.is(!strong, [0; 5]) {
+            continue;
+        }
+        let eval = if signature.is(strong, [1, 1, 0, 0, 0]) {
+            kbn_vs_k(&position.pieces, strong)
+        } else if signature.is(strong, [0, 0, 0, 0, 1]) {
+            kp_vs_k(&position.pieces, strong, position.to_move == strong)?
+        } else {
+            continue;
+        };
+        return Some(if position.to_move == strong {
+            eval
+        } else {
+            -eval
+        });
+    }
+    None
+}
+
+/// Returns the factor, out of [`SCALE_NORMAL`], that the evaluation should be
+/// scaled by when `strong` is ahead.
+fn scale_factor(pieces: &Pieces, signature: &MaterialSignature, strong: Color) -> i32 {
+    use PieceKind::*;
+
+    let weak = !strong;
+    let strong_npm = signature.non_pawn_material(strong);
+    let weak_npm = signature.non_pawn_material(weak);
+
+    if signature.count(strong, Pawn) == 0 {
+        // A single minor piece cannot mate
+        if strong_npm < piece_value(Rook) {
+            return 0;
+        }
+        // Two knights cannot force mate
+        if signature.is(strong, [2, 0, 0, 0, 0]) && signature.count(weak, Pawn) == 0 {
+            return 0;
+        }
+        // Being up less than a minor piece, e.g. KRvKB, is usually a draw
+        if strong_npm - weak_npm <= piece_value(Bishop) {
+            return SCALE_NORMAL / 4;
+        }
+    }
+
+    // Opposite colored bishops
+    if signature.is(strong, [0, 1, 0, 0, signature.count(strong, Pawn)])
+        && signature.is(weak, [0, 1, 0, 0, signature.count(weak, Pawn)])
+    {
+        let bishops =
+            pieces.get_bb(Piece(Bishop, Color::White)) | pieces.get_bb(Piece(Bishop, Color::Black));
+        if (bishops & Bitboard::LIGHT_SQUARES).len() == 1 {
+            return SCALE_NORMAL / 2;
+        }
+    }
+
+    SCALE_NORMAL
+}
+
+/// Evaluates KBNvK from the perspective of `strong` by driving the weak king
+/// to a corner of the bishop's color, and bringing the strong king closer.
+fn kbn_vs_k(pieces: &Pieces, strong: Color) -> i16 {
+    let bishop = pieces.get_bb(Piece(PieceKind::Bishop, strong));
+    let corners = if (bishop & Bitboard::LIGHT_SQUARES).is_empty() {
+        [Square::A1, Square::H8]
+    } else {
+        [Square::H1, Square::A8]
+    };
+
+    let strong_king = pieces.king_sq_for(strong);
+    let weak_king = pieces.king_sq_for(!strong);
+    let corner_dist = corners
+        .into_iter()
+        .map(|corner| king_dist(weak_king, corner))
+        .min()
+        .unwrap();
+    KNOWN_WIN + 60 * (7 - corner_dist) + 10 * (7 - king_dist(strong_king, weak_king))
+}
+
+/// Evaluates KPvK from the perspective of `strong`. Returns `None` if it is
+/// not clear whether the position is won or drawn.
+fn kp_vs_k(pieces: &Pieces, strong: Color, strong_to_move: bool) -> Option<i16> {
+    let pawn = pieces
+        .get_bb(Piece(PieceKind::Pawn, strong))
+        .into_iter()
+        .next()
+        .unwrap();
+    // Normalize so that the pawn moves up the board
+    let normalize = |sq: Square| match strong {
+        Color::White => sq,
+        Color::Black => sq.flip_rank(),
+    };
+    let pawn = normalize(pawn);
+    let strong_king = normalize(pieces.king_sq_for(strong));
+    let weak_king = normalize(pieces.king_sq_for(!strong));
+
+    let rank = pawn.rank() as i16;
+    let file = pawn.file() as i16;
+    let promotion_sq = Square::from_rank_file(Rank::Eighth, pawn.file());
+    let win = KNOWN_WIN + 20 * rank;
+
+    // The pawn is lost if the weak king can take it
+    let pawn_hanging =
+        !strong_to_move && king_dist(weak_king, pawn) == 1 && king_dist(strong_king, pawn) > 1;
+    if pawn_hanging {
+        return Some(0);
+    }
+
+    // The weak king reaching the corner in front of a rook pawn is a draw
+    let is_rook_pawn = file == 0 || file == 7;
+    if is_rook_pawn && king_dist(weak_king, promotion_sq) <= 1 {
+        return Some(0);
+    }
+
+    // The weak king cannot catch the pawn
+    let pawn_dist = (7 - rank).min(5);
+    let weak_dist = king_dist(weak_king, promotion_sq) - !strong_to_move as i16;
+    let blocked = strong_king.file() == pawn.file() && strong_king.rank() > pawn.rank();
+    if weak_dist > pawn_dist && !blocked {
+        return Some(win);
+    }
+
+    // Key squares don't win with a rook pawn
+    if is_rook_pawn {
+        return None;
+    }
+
+    // The strong king on a key square wins
+    let key_ranks = match rank {
+        1..=3 => rank + 2..=rank + 2,
+        4..=5 => rank + 1..=rank + 2,
+        _ => rank..=rank + 1,
+    };
+    let king_rank = strong_king.rank() as i16;
+    let king_file = strong_king.file() as i16;
+    if key_ranks.contains(&king_rank) && (king_file - file).abs() <= 1 && strong_king != pawn {
+        return Some(win);
+    }
+
+    None
+}
+
+/// Returns the number of king moves between two squares.
+#[inline]
+fn king_dist(a: Square, b: Square) -> i16 {
+    let dr = (a.rank() as i16 - b.rank() as i16).abs();
+    let df = (a.file() as i16 - b.file() as i16).abs();
+    dr.max(df)
+}
@@ -0,0 +1,102 @@
+use crate::eval::{Eval, StandardEval};
+use crate::Position;
+
+use super::{adjusts_eval, is_insufficient_material, MaterialSignature};
+
+fn eval(fen: &str) -> i16 {
+    StandardEval.eval(&Position::from_fen(fen).unwrap()).into()
+}
+
+#[test]
+fn insufficient_material() {
+    let draws = [
+        "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+        "8/8/4k3/8/8/3K4/8/6N1 w - - 0 1",
+        "8/8/4k3/8/8/3K4/8/5b2 b - - 0 1",
+        "8/8/2b1k3/8/8/3K4/8/5B2 w - - 0 1",
+    ];
+    for fen in draws {
+        let position = Position::from_fen(fen).unwrap();
+        assert!(is_insufficient_material(&position.pieces), "{fen}");
+        assert!(position.is_rule_draw(), "{fen}");
+    }
+
+    let not_draws = [
+        "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+        "8/8/1b2k3/8/8/3K4/8/5B2 w - - 0 1",
+        "8/8/4k3/8/8/3K4/8/5BN1 w - - 0 1",
+        "8/8/4k3/8/8/3K4/8/5NN1 w - - 0 1",
+    ];
+    for fen in not_draws {
+        let position = Position::from_fen(fen).unwrap();
+        assert!(!is_insufficient_material(&position.pieces), "{fen}");
+    }
+}
+
+#[test]
+fn material_signature() {
+    let position = Position::from_fen("8/8/4k3/5r2/8/3K4/4P3/5BN1 w - - 0 1").unwrap();
+    let signature = MaterialSignature::new(&position.pieces);
+    use crate::types::Color::*;
+    assert!(signature.is(White, [1, 1, 0, 0, 1]));
+    assert!(signature.is(Black, [0, 0, 1, 0, 0]));
+    assert_eq!(signature.non_pawn_material(White), 640);
+}
+
+#[test]
+fn drawish_endings_are_scaled_down() {
+    // KNNvK and KRvKB
+    assert_eq!(eval("8/8/4k3/8/8/3K4/8/5NN1 w - - 0 1"), 0);
+    assert!(eval("8/8/4k3/8/2b5/3K4/8/5R2 w - - 0 1") < 100);
+    // Opposite colored bishops are scaled, same colored are not
+    let ocb = eval("8/5p2/4k3/5b2/8/3KB3/5PP1/8 w - - 0 1");
+    let scb = eval("8/5p2/4kb2/8/8/3KB3/5PP1/8 w - - 0 1");
+    assert!(ocb > 0 && ocb * 3 / 2 < scb, "{ocb} {scb}");
+}
+
+#[test]
+fn adjusted_evals_are_recognized() {
+    let adjusted = [
+        // Scaled, also when the other side is ahead
+        "8/8/4k3/8/2b5/3K4/8/5R2 w - - 0 1",
+        "8/5p2/4k3/5b2/8/3KB3/5PP1/8 w - - 0 1",
+        // Specialized evaluators
+        "7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1",
+        "8/8/8/8/8/8/6P1/K6k b - - 0 1",
+    ];
+    for fen in adjusted {
+        assert!(adjusts_eval(&Position::from_fen(fen).unwrap()), "{fen}");
+    }
+    let unchanged = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "8/5p2/4kb2/8/8/3KB3/5PP1/8 w - - 0 1",
+    ];
+    for fen in unchanged {
+        assert!(!adjusts_eval(&Position::from_fen(fen).unwrap()), "{fen}");
+    }
+}
+
+#[test]
+fn kbn_vs_k_drives_king_to_bishop_corner() {
+    // Dark-squared bishop, so A1 and H8 are the right corners
+    let right_corner = eval("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1");
+    let wrong_corner = eval("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1");
+    assert!(right_corner > wrong_corner);
+    assert!(wrong_corner > 900);
+    assert_eq!(eval("7k/8/5K2/8/8/8/8/2B1N3 b - - 0 1"), -right_corner);
+}
+
+#[test]
+fn kp_vs_k() {
+    // The black king is outside the square of the pawn
+    assert!(eval("8/k7/8/8/8/8/6P1/6K1 w - - 0 1") > 900);
+    assert!(eval("8/8/k7/8/6P1/8/8/6K1 b - - 0 1") < -900);
+    assert!(eval("k7/8/8/8/8/8/6P1/6K1 b - - 0 1") > -900);
+    // The white king is on a key square
+    assert!(eval("8/8/4k3/8/3K4/8/4P3/8 b - - 0 1") < -900);
+    assert!(eval("8/8/4p3/8/4k3/8/8/4K3 b - - 0 1") > 900);
+    // The black king takes the pawn, even though the pawn is outside its square
+    assert_eq!(eval("8/8/8/8/8/8/6P1/K6k b - - 0 1"), 0);
+    // Rook pawn with the black king in the corner
+    assert_eq!(eval("k7/8/8/8/P7/8/8/2K5 w - - 0 1"), 0);
+}
@@ -1,15 +1,26 @@
 use crate::types::Value;
 use crate::Position;
 
+pub mod endgame;
 mod material;
+pub mod nnue;
+pub mod params;
 mod piece_square_tables;
+mod wdl;
 
 pub use material::{piece_value, MaterialEval};
+pub use nnue::NnueEval;
+pub use params::{EvalParams, ParamEval};
 pub use piece_square_tables::{piece_value_early, piece_value_endgame};
+pub use wdl::{wdl_material, Wdl, WdlModel, WDL_MODEL};
 
 /// A NegaMax (i.e. positive values represent the side to move) evaluator of
 /// positions.
 pub trait Eval: Clone + Send + 'static {
+    /// Prepares `position` for being evaluated by this evaluator, e.g. by
+    /// attaching incrementally updated state to it. Called before searching.
+    fn prepare(&self, _position: &mut Position) {}
+
     fn eval(&self, position: &Position) -> Value;
 }
 
@@ -27,6 +38,37 @@ impl Eval for StandardEval {
         // Tempo bonus
         val += 25;
 
-        Value::centipawn(val)
+        Value::centipawn(endgame::evaluate(position, val))
+    }
+}
+
+/// An evaluator that can be chosen at runtime, e.g. through a UCI option.
+#[derive(Clone)]
+pub enum RuntimeEval {
+    Standard(StandardEval),
+    Nnue(NnueEval),
+}
+
+impl Default for RuntimeEval {
+    fn default() -> Self {
+        Self::Standard(StandardEval)
+    }
+}
+
+impl Eval for RuntimeEval {
+    #[inline]
+    fn prepare(&self, position: &mut Position) {
+        match self {
+            Self::Standard(eval) => eval.prepare(position),
+            Self::Nnue(eval) => eval.prepare(position),
+        }
+    }
+
+    #[inline]
+    fn eval(&self, position: &Position) -> Value {
+        match self {
+            Self::Standard(eval) => eval.eval(position),
+            Self::Nnue(eval) => eval.eval(position),
+        }
     }
 }
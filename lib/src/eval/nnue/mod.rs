@@ -0,0 +1,284 @@
+//! An NNUE (efficiently updatable neural network) evaluator.
+//!
+//! The network uses the simple 768 input feature set (color, piece kind and
+//! square, relative to the perspective) feeding a single hidden layer, which is
+//! kept for both perspectives in an [`Accumulator`]. The accumulator lives in
+//! the [`Position`] and is updated incrementally by
+//! [`Position::make_move`] and [`Position::unmake_move`], so evaluating a
+//! position only requires running the output layer.
+//!
+//! Networks are stored as little-endian `i16`s in the order feature weights,
+//! feature biases, output weights and output bias, which is the format
+//! produced by e.g. bullet for a `(768 -> 256)x2 -> 1` network.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::position::Pieces;
+use crate::types::{Color, Piece, PieceKind, Square, Value};
+use crate::Position;
+
+use super::{piece_value_early, piece_value_endgame, Eval};
+
+mod simd;
+#[cfg(test)]
+mod tests;
+
+/// The number of neurons in the hidden layer for each perspective.
+pub const HIDDEN_SIZE: usize = 256;
+const INPUT_SIZE: usize = 768;
+/// Quantization of the feature transformer.
+const QA: i32 = 255;
+/// Quantization of the output layer.
+const QB: i32 = 64;
+/// Scales the output of the network to centipawns.
+const SCALE: i32 = 400;
+
+const NETWORK_SIZE: usize = 2 * (INPUT_SIZE * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE + 1);
+
+/// The weights of a network.
+pub struct Network {
+    /// Weights of the feature transformer, `HIDDEN_SIZE` per input feature.
+    feature_weights: Vec<i16>,
+    feature_bias: [i16; HIDDEN_SIZE],
+    /// Weights of the output layer. The first half is applied to the side to
+    /// move, the second half to the other side.
+    output_weights: [i16; 2 * HIDDEN_SIZE],
+    output_bias: i16,
+}
+
+impl Network {
+    /// Loads a network from a file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadNetworkError> {
+        let bytes = fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parses a network from its binary representation. Trailing padding up
+    /// to a multiple of 64 bytes is allowed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadNetworkError> {
+        let padded_size = NETWORK_SIZE.next_multiple_of(64);
+        if bytes.len() != NETWORK_SIZE && bytes.len() != padded_size {
+            return Err(LoadNetworkError::InvalidSize(bytes.len()));
+        }
+
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let feature_weights = values.by_ref().take(INPUT_SIZE * HIDDEN_SIZE).collect();
+        let mut feature_bias = [0; HIDDEN_SIZE];
+        feature_bias.fill_with(|| values.next().unwrap());
+        let mut output_weights = [0; 2 * HIDDEN_SIZE];
+        output_weights.fill_with(|| values.next().unwrap());
+        let output_bias = values.next().unwrap();
+
+        Ok(Self {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    /// Returns the binary representation of the network, see
+    /// [`Network::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.feature_weights
+            .iter()
+            .chain(&self.feature_bias)
+            .chain(&self.output_weights)
+            .chain([&self.output_bias])
+            .flat_map(|v| v.to_le_bytes())
+            .collect()
+    }
+
+    /// Creates the network embedded in the engine. It is built from the
+    /// piece-square tables, with one neuron per square for each side, so it
+    /// evaluates like [`StandardEval`](super::StandardEval) except that the
+    /// king uses the average of its early and endgame tables.
+    pub fn embedded() -> Self {
+        use PieceKind::*;
+
+        // The hidden neurons hold piece-square values in quarter centipawns,
+        // offset by the bias so that they stay inside the activation range.
+        const BIAS: i16 = 16;
+        const OUTPUT_WEIGHT: i16 = (4 * QA * QB / SCALE) as i16;
+        const TEMPO: i16 = (25 * QA * QB / SCALE) as i16;
+
+        let mut feature_weights = vec![0; INPUT_SIZE * HIDDEN_SIZE];
+        for kind in [Pawn, Knight, Bishop, Rook, Queen, King] {
+            let pce = Piece(kind, Color::White);
+            let value = |sq| (piece_value_early(pce, sq) + piece_value_endgame(pce, sq)) / 8;
+            for sq in Square::iter() {
+                // Own pieces are valued from our side of the board, enemy pieces from theirs
+                let own = feature_index(Color::White, pce, sq);
+                feature_weights[own * HIDDEN_SIZE + sq as usize] = value(sq);
+                let their = feature_index(Color::White, Piece(kind, Color::Black), sq);
+                feature_weights[their * HIDDEN_SIZE + 64 + sq as usize] = value(sq.flip_rank());
+            }
+        }
+
+        let mut feature_bias = [0; HIDDEN_SIZE];
+        feature_bias[..128].fill(BIAS);
+        let mut output_weights = [0; 2 * HIDDEN_SIZE];
+        output_weights[..64].fill(OUTPUT_WEIGHT);
+        output_weights[64..128].fill(-OUTPUT_WEIGHT);
+
+        Self {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias: TEMPO,
+        }
+    }
+
+    #[inline]
+    fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * HIDDEN_SIZE..(feature + 1) * HIDDEN_SIZE]
+    }
+}
+
+/// The hidden layer of a [`Network`] for both perspectives, kept up to date
+/// with the pieces on the board.
+#[derive(Clone)]
+pub struct Accumulator {
+    network: Arc<Network>,
+    /// Indexed by the perspective [`Color`].
+    values: Box<[[i16; HIDDEN_SIZE]; 2]>,
+}
+
+impl Accumulator {
+    /// Creates an accumulator for the given pieces from scratch.
+    pub fn new(network: Arc<Network>, pieces: &Pieces) -> Self {
+        let mut acc = Self {
+            values: Box::new([network.feature_bias; 2]),
+            network,
+        };
+        for sq in Square::iter() {
+            if let Some(pce) = pieces.get(sq) {
+                acc.add(pce, sq);
+            }
+        }
+        acc
+    }
+
+    /// Adds the feature of `pce` on `sq`.
+    #[inline]
+    pub(crate) fn add(&mut self, pce: Piece, sq: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = self
+                .network
+                .feature_weights(feature_index(perspective, pce, sq));
+            let values = &mut self.values[perspective as usize];
+            for (v, w) in values.iter_mut().zip(weights) {
+                *v = v.wrapping_add(*w);
+            }
+        }
+    }
+
+    /// Removes the feature of `pce` on `sq`.
+    #[inline]
+    pub(crate) fn remove(&mut self, pce: Piece, sq: Square) {
+        for perspective in [Color::White, Color::Black] {
+            let weights = self
+                .network
+                .feature_weights(feature_index(perspective, pce, sq));
+            let values = &mut self.values[perspective as usize];
+            for (v, w) in values.iter_mut().zip(weights) {
+                *v = v.wrapping_sub(*w);
+            }
+        }
+    }
+
+    /// Runs the output layer and returns the evaluation in centipawns from the
+    /// perspective of `to_move`.
+    #[inline]
+    pub fn evaluate(&self, to_move: Color) -> i32 {
+        let (us, them) = self.network.output_weights.split_at(HIDDEN_SIZE);
+        let output = simd::crelu_dot(&self.values[to_move as usize], us)
+            + simd::crelu_dot(&self.values[!to_move as usize], them)
+            + self.network.output_bias as i32;
+        output * SCALE / (QA * QB)
+    }
+
+    /// Returns whether the accumulator belongs to `network`.
+    #[inline]
+    pub fn uses(&self, network: &Arc<Network>) -> bool {
+        Arc::ptr_eq(&self.network, network)
+    }
+}
+
+/// Returns the input feature of `pce` on `sq` seen from `perspective`.
+#[inline]
+fn feature_index(perspective: Color, pce: Piece, sq: Square) -> usize {
+    let kind = match pce.kind() {
+        PieceKind::Pawn => 0,
+        PieceKind::Knight => 1,
+        PieceKind::Bishop => 2,
+        PieceKind::Rook => 3,
+        PieceKind::Queen => 4,
+        PieceKind::King => 5,
+    };
+    let (side, sq) = match perspective {
+        Color::White => (pce.color() as usize, sq as usize),
+        Color::Black => (!pce.color() as usize, sq.flip_rank() as usize),
+    };
+    side * 384 + kind * 64 + sq
+}
+
+/// An evaluator using an NNUE [`Network`].
+#[derive(Clone)]
+pub struct NnueEval {
+    network: Arc<Network>,
+}
+
+impl NnueEval {
+    /// Creates an evaluator using the given network.
+    pub fn new(network: Network) -> Self {
+        Self {
+            network: Arc::new(network),
+        }
+    }
+
+    /// Creates an evaluator using the network stored at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadNetworkError> {
+        Ok(Self::new(Network::load(path)?))
+    }
+}
+
+impl Default for NnueEval {
+    /// Creates an evaluator using the [embedded](Network::embedded) network.
+    fn default() -> Self {
+        Self::new(Network::embedded())
+    }
+}
+
+impl Eval for NnueEval {
+    fn prepare(&self, position: &mut Position) {
+        position.attach_network(Arc::clone(&self.network));
+    }
+
+    fn eval(&self, position: &Position) -> Value {
+        let score = match position.accumulator() {
+            Some(acc) if acc.uses(&self.network) => acc.evaluate(position.to_move),
+            // The position was not prepared, so refresh from scratch
+            _ => Accumulator::new(Arc::clone(&self.network), &position.pieces)
+                .evaluate(position.to_move),
+        };
+        Value::centipawn(score.clamp(-MAX_EVAL, MAX_EVAL) as i16)
+    }
+}
+
+/// Keeps network outputs well inside the centipawn range of [`Value`].
+const MAX_EVAL: i32 = 20000;
+
+/// An error that can occur when loading a [`Network`].
+#[derive(thiserror::Error, Debug)]
+pub enum LoadNetworkError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("invalid network size: expected {NETWORK_SIZE} bytes, got {0}")]
+    InvalidSize(usize),
+}
@@ -0,0 +1,59 @@
+//! Inference kernels for the output layer. AVX2 is used when the target
+//! supports it, otherwise we fall back to scalar code.
+
+use super::QA;
+
+/// Computes the dot product of `weights` and the clipped ReLU of `values`.
+#[inline]
+pub fn crelu_dot(values: &[i16], weights: &[i16]) -> i32 {
+    debug_assert_eq!(values.len(), weights.len());
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    {
+        // Safety: The target supports AVX2
+        unsafe { crelu_dot_avx2(values, weights) }
+    }
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "avx2")))]
+    {
+        crelu_dot_scalar(values, weights)
+    }
+}
+
+#[cfg_attr(all(target_arch = "x86_64", target_feature = "avx2"), allow(dead_code))]
+pub fn crelu_dot_scalar(values: &[i16], weights: &[i16]) -> i32 {
+    values
+        .iter()
+        .zip(weights)
+        .map(|(&v, &w)| (v as i32).clamp(0, QA) * w as i32)
+        .sum()
+}
+
+/// # Safety
+/// The CPU must support AVX2, and the length of the slices must be a multiple
+/// of 16.
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+pub unsafe fn crelu_dot_avx2(values: &[i16], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+
+    debug_assert_eq!(values.len() % 16, 0);
+
+    let zero = _mm256_setzero_si256();
+    let max = _mm256_set1_epi16(QA as i16);
+    let mut sum = _mm256_setzero_si256();
+    for (v, w) in values.chunks_exact(16).zip(weights.chunks_exact(16)) {
+        let v = _mm256_loadu_si256(v.as_ptr() as *const __m256i);
+        let w = _mm256_loadu_si256(w.as_ptr() as *const __m256i);
+        let clipped = _mm256_min_epi16(_mm256_max_epi16(v, zero), max);
+        // Multiplies to 32 bits and adds adjacent pairs
+        sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, w));
+    }
+
+    // Horizontal sum of the eight 32 bit lanes
+    let sum = _mm_add_epi32(
+        _mm256_castsi256_si128(sum),
+        _mm256_extracti128_si256(sum, 1),
+    );
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+    let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+    _mm_cvtsi128_si32(sum)
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::eval::{Eval, StandardEval};
+use crate::mv;
+use crate::position::Position;
+
+use super::{simd, Accumulator, LoadNetworkError, Network, NnueEval, HIDDEN_SIZE};
+
+fn assert_accumulator_fresh(position: &Position) {
+    let acc = position.accumulator().unwrap();
+    let fresh = Accumulator::new(Arc::clone(&acc.network), &position.pieces);
+    assert_eq!(acc.values, fresh.values, "{position}");
+}
+
+#[test]
+fn accumulator_updated_incrementally() {
+    let fen = "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    NnueEval::default().prepare(&mut position);
+
+    let moves = [
+        mv!(O-O-O w),
+        mv!(A6 x E2),
+        mv!(B7 x A8 q),
+        mv!(E2 x D1),
+        mv!(A2 -> A4),
+        mv!(B4 ep A3),
+        mv!(),
+        mv!(O-O b),
+    ];
+    for mv in moves {
+        position.make_move(mv);
+        assert_accumulator_fresh(&position);
+    }
+    for _ in moves {
+        position.unmake_move();
+        assert_accumulator_fresh(&position);
+    }
+}
+
+#[test]
+fn embedded_network_is_close_to_standard_eval() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+        "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+    ];
+    for fen in fens {
+        let mut position = Position::from_fen(fen).unwrap();
+        let nnue = NnueEval::default();
+        nnue.prepare(&mut position);
+        let nnue_eval = i16::from(nnue.eval(&position));
+        let standard_eval = i16::from(StandardEval.eval(&position));
+        // Only the king tables and rounding differ
+        assert!(
+            (nnue_eval - standard_eval).abs() <= 60,
+            "{fen}: nnue {nnue_eval}, standard {standard_eval}"
+        );
+    }
+}
+
+#[test]
+fn eval_is_symmetric() {
+    let w_fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    let b_fen = "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3";
+    let nnue = NnueEval::default();
+    let w_eval = nnue.eval(&Position::from_fen(w_fen).unwrap());
+    let b_eval = nnue.eval(&Position::from_fen(b_fen).unwrap());
+    assert_eq!(w_eval, b_eval);
+}
+
+#[test]
+fn network_bytes_round_trip() {
+    let network = Network::embedded();
+    let mut bytes = network.to_bytes();
+    let loaded = Network::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    // Padding to a multiple of 64 bytes is accepted
+    bytes.resize(bytes.len().next_multiple_of(64), 0);
+    assert!(Network::from_bytes(&bytes).is_ok());
+
+    bytes.pop();
+    assert!(matches!(
+        Network::from_bytes(&bytes),
+        Err(LoadNetworkError::InvalidSize(_))
+    ));
+}
+
+#[test]
+fn simd_matches_scalar() {
+    let values: Vec<i16> = (0..HIDDEN_SIZE as i16).map(|i| i * 7 - 300).collect();
+    let weights: Vec<i16> = (0..HIDDEN_SIZE as i16).map(|i| 50 - i).collect();
+    let scalar = simd::crelu_dot_scalar(&values, &weights);
+    assert_eq!(simd::crelu_dot(&values, &weights), scalar);
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+    assert_eq!(unsafe { simd::crelu_dot_avx2(&values, &weights) }, scalar);
+}
@@ -0,0 +1,248 @@
+//! Parameters of the piece-square table evaluation.
+//!
+//! [`StandardEval`](super::StandardEval) uses the hard-coded tables, which are
+//! also the [default](EvalParams::default) parameters. [`ParamEval`] evaluates
+//! with any set of parameters, e.g. ones produced by the `tune` tool in
+//! `kingly-tools`, and can be loaded from a weights file at runtime.
+//!
+//! The weights file is plain text. Lines starting with `#` are ignored, the
+//! rest is a list of whitespace separated entries: `tempo <value>` and a
+//! table for each phase (`early` or `endgame`) and piece kind, written as
+//! `<phase> <kind>` followed by 64 values starting from A1.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::num::ParseIntError;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::types::{Color, Piece, PieceKind, Square, Value};
+use crate::Position;
+
+use super::{endgame, piece_value_early, piece_value_endgame, Eval};
+
+#[cfg(test)]
+mod tests;
+
+/// The piece kinds in the order of their discriminants, which is also the
+/// order of the tables in [`EvalParams`].
+const KINDS: [PieceKind; 6] = [
+    PieceKind::Knight,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+    PieceKind::Queen,
+    PieceKind::Pawn,
+    PieceKind::King,
+];
+
+/// The tables of the evaluation, including material, from white's side of the
+/// board. The tables are indexed by [`PieceKind`] and then [`Square`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EvalParams {
+    pub early: [[i16; 64]; 6],
+    pub endgame: [[i16; 64]; 6],
+    /// Bonus for the side to move.
+    pub tempo: i16,
+}
+
+impl EvalParams {
+    /// Loads parameters from a weights file, see the [module](self) docs for
+    /// the format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadEvalParamsError> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    /// Returns the value of `pce` on `sq` in the early game and endgame. Black
+    /// pieces are mirrored and negated like in [`StandardEval`](super::StandardEval).
+    #[inline]
+    pub fn piece_values(&self, pce: Piece, sq: Square) -> (i16, i16) {
+        let kind = pce.kind() as usize;
+        match pce.color() {
+            Color::White => (
+                self.early[kind][sq as usize],
+                self.endgame[kind][sq as usize],
+            ),
+            Color::Black => {
+                let sq = 63 - sq as usize;
+                (-self.early[kind][sq], -self.endgame[kind][sq])
+            }
+        }
+    }
+
+    /// Returns the parameters as a Rust expression, which can be used to
+    /// embed them in the engine.
+    pub fn to_rust(&self) -> String {
+        fn write_tables(out: &mut String, name: &str, tables: &[[i16; 64]; 6]) {
+            *out += &format!("    {name}: [\n");
+            for (kind, table) in KINDS.iter().zip(tables) {
+                *out += &format!("        // {kind:?}\n        [\n");
+                for row in table.chunks(8) {
+                    let row = row.iter().map(|v| format!("{v:4},")).collect::<String>();
+                    *out += &format!("           {row}\n");
+                }
+                *out += "        ],\n";
+            }
+            *out += "    ],\n";
+        }
+
+        let mut out = String::from("EvalParams {\n");
+        write_tables(&mut out, "early", &self.early);
+        write_tables(&mut out, "endgame", &self.endgame);
+        out += &format!("    tempo: {},\n}}", self.tempo);
+        out
+    }
+}
+
+impl Default for EvalParams {
+    /// Returns the parameters used by [`StandardEval`](super::StandardEval).
+    fn default() -> Self {
+        let table = |value: fn(Piece, Square) -> i16| {
+            KINDS.map(|kind| {
+                let mut table = [0; 64];
+                for sq in Square::iter() {
+                    table[sq as usize] = value(Piece(kind, Color::White), sq);
+                }
+                table
+            })
+        };
+        Self {
+            early: table(piece_value_early),
+            endgame: table(piece_value_endgame),
+            tempo: 25,
+        }
+    }
+}
+
+impl Display for EvalParams {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "# Kingly evaluation parameters")?;
+        writeln!(f, "tempo {}", self.tempo)?;
+        for (phase, tables) in [("early", &self.early), ("endgame", &self.endgame)] {
+            for (kind, table) in KINDS.iter().zip(tables) {
+                writeln!(f, "{phase} {kind}")?;
+                for row in table.chunks(8) {
+                    let row = row.iter().map(|v| format!("{v:5}")).collect::<String>();
+                    writeln!(f, "{}", row.trim_start())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EvalParams {
+    type Err = ParseEvalParamsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn next_value<'a>(
+            tokens: &mut impl Iterator<Item = &'a str>,
+        ) -> Result<i16, ParseEvalParamsError> {
+            let token = tokens.next().ok_or(ParseEvalParamsError::UnexpectedEnd)?;
+            Ok(token.parse()?)
+        }
+
+        let mut tokens = s
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(str::split_whitespace);
+
+        let mut params = Self {
+            early: [[0; 64]; 6],
+            endgame: [[0; 64]; 6],
+            tempo: 0,
+        };
+        // One bit for the tempo and each of the 12 tables
+        let mut seen = 0u16;
+        while let Some(entry) = tokens.next() {
+            let (table, bit) = match entry {
+                "tempo" => {
+                    params.tempo = next_value(&mut tokens)?;
+                    seen |= 1;
+                    continue;
+                }
+                "early" => (&mut params.early, 1),
+                "endgame" => (&mut params.endgame, 7),
+                _ => return Err(ParseEvalParamsError::UnknownEntry(entry.into())),
+            };
+            let kind = tokens.next().ok_or(ParseEvalParamsError::UnexpectedEnd)?;
+            let kind = match kind.chars().collect::<Vec<_>>()[..] {
+                [ch] => PieceKind::try_from(ch).ok(),
+                _ => None,
+            }
+            .ok_or_else(|| ParseEvalParamsError::UnknownEntry(format!("{entry} {kind}")))?;
+            for value in &mut table[kind as usize] {
+                *value = next_value(&mut tokens)?;
+            }
+            seen |= 1 << (bit + kind as u16);
+        }
+
+        if seen != (1 << 13) - 1 {
+            return Err(ParseEvalParamsError::MissingEntries);
+        }
+        Ok(params)
+    }
+}
+
+/// An error that can occur when parsing [`EvalParams`].
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseEvalParamsError {
+    #[error("unknown entry: {0}")]
+    UnknownEntry(String),
+    #[error("invalid value: {0}")]
+    InvalidValue(#[from] ParseIntError),
+    #[error("unexpected end of parameters")]
+    UnexpectedEnd,
+    #[error("the tempo and all tables should be given")]
+    MissingEntries,
+}
+
+/// An error that can occur when loading [`EvalParams`] from a file.
+#[derive(thiserror::Error, Debug)]
+pub enum LoadEvalParamsError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Parse(#[from] ParseEvalParamsError),
+}
+
+/// A piece-square table evaluator using the given [`EvalParams`]. With the
+/// default parameters, it evaluates exactly like
+/// [`StandardEval`](super::StandardEval), but it is slower, as the tables are
+/// not kept up to date incrementally by the position.
+#[derive(Clone, Default)]
+pub struct ParamEval {
+    params: Arc<EvalParams>,
+}
+
+impl ParamEval {
+    pub fn new(params: EvalParams) -> Self {
+        Self {
+            params: Arc::new(params),
+        }
+    }
+
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+}
+
+impl Eval for ParamEval {
+    fn eval(&self, position: &Position) -> Value {
+        let (mut early, mut endgame) = (0i32, 0i32);
+        for sq in Square::iter() {
+            if let Some(pce) = position.pieces.get(sq) {
+                let (pce_early, pce_endgame) = self.params.piece_values(pce, sq);
+                early += pce_early as i32;
+                endgame += pce_endgame as i32;
+            }
+        }
+
+        let game_phase = position.game_phase();
+        let sign = position.to_move.sign() as i32;
+        let val = sign * (early * game_phase + endgame * (26 - game_phase)) / 26;
+        let val = val as i16 + self.params.tempo;
+        Value::centipawn(endgame::evaluate(position, val))
+    }
+}
@@ -0,0 +1,38 @@
+use crate::eval::{Eval, StandardEval};
+use crate::Position;
+
+use super::{EvalParams, ParamEval, ParseEvalParamsError};
+
+#[test]
+fn default_params_match_standard_eval() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    ];
+    let eval = ParamEval::default();
+    for fen in fens {
+        let position = Position::from_fen(fen).unwrap();
+        assert_eq!(eval.eval(&position), StandardEval.eval(&position), "{fen}");
+    }
+}
+
+#[test]
+fn params_round_trip() {
+    let mut params = EvalParams::default();
+    params.early[0][17] = -123;
+    params.endgame[5][63] = 42;
+    params.tempo = 11;
+    assert_eq!(params.to_string().parse(), Ok(params));
+}
+
+#[test]
+fn params_missing_table() {
+    let params = EvalParams::default().to_string();
+    let truncated = params.split("endgame k").next().unwrap();
+    assert_eq!(
+        truncated.parse::<EvalParams>(),
+        Err(ParseEvalParamsError::MissingEntries)
+    );
+}
@@ -0,0 +1,123 @@
+use crate::types::{Color, Piece, PieceKind, Value};
+use crate::Position;
+
+/// Win, draw and loss probabilities in per mille.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Wdl {
+    pub win: u16,
+    pub draw: u16,
+    pub loss: u16,
+}
+
+/// A model of the expected outcome of a game given the score of a position and
+/// the material on the board. The probability of winning is a logistic
+/// function of the score, `1 / (1 + exp((a - score) / b))`, where `a` and `b`
+/// are cubic polynomials in the material, so that the same score is more
+/// decisive when there is less material left.
+///
+/// The coefficients can be fitted from `datagen` output with the `fit-wdl`
+/// tool in kingly-tools.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WdlModel {
+    /// The coefficients of `a`, highest degree first.
+    pub a: [f64; 4],
+    /// The coefficients of `b`, highest degree first.
+    pub b: [f64; 4],
+}
+
+/// The material counts are clamped to this range, as there is too little data
+/// outside it.
+const MATERIAL_RANGE: (u32, u32) = (10, 78);
+/// The material of the starting position, which the polynomials are evaluated
+/// relative to.
+const STARTING_MATERIAL: f64 = 78.;
+
+/// The model fitted to self-play games at 5000 nodes per move. It can be
+/// refitted after evaluation changes with the tools in kingly-tools:
+///
+/// ```text
+/// cargo run --release -p kingly-tools --bin datagen -- wdl-data.txt --games 2000
+/// cargo run --release -p kingly-tools --bin fit-wdl -- wdl-data.txt
+/// ```
+///
+/// The fit depends on the evaluation and search at the time the games were
+/// played, so the result will not match these coefficients exactly.
+pub const WDL_MODEL: WdlModel = WdlModel {
+    a: [-908.126, 1895.094, -1258.191, 320.565],
+    b: [-220.999, 399.076, -113.399, 123.266],
+};
+
+impl WdlModel {
+    /// Returns the variable the polynomials are evaluated at for the given
+    /// amount of material.
+    pub fn normalize_material(material: u32) -> f64 {
+        material.clamp(MATERIAL_RANGE.0, MATERIAL_RANGE.1) as f64 / STARTING_MATERIAL
+    }
+
+    /// Returns `a` and `b` for the given amount of material.
+    pub fn params(&self, material: u32) -> (f64, f64) {
+        let x = Self::normalize_material(material);
+        let poly = |c: &[f64; 4]| ((c[0] * x + c[1]) * x + c[2]) * x + c[3];
+        (poly(&self.a), poly(&self.b))
+    }
+
+    /// Returns the probability of winning with a score of `cp` centipawns.
+    pub fn win_rate(&self, cp: f64, material: u32) -> f64 {
+        let (a, b) = self.params(material);
+        1. / (1. + ((a - cp) / b).exp())
+    }
+
+    /// Returns the expected outcome with a score of `value` for the side to
+    /// move. Mate scores are certain wins or losses.
+    ///
+    /// # Example
+    /// ```
+    /// use kingly_lib::eval::WDL_MODEL;
+    /// use kingly_lib::types::Value;
+    ///
+    /// let wdl = WDL_MODEL.wdl(Value::centipawn(150), 40);
+    /// assert!(wdl.win > wdl.loss);
+    /// assert_eq!(wdl.win + wdl.draw + wdl.loss, 1000);
+    /// assert_eq!(WDL_MODEL.wdl(Value::mate_in_ply(3), 40).win, 1000);
+    /// ```
+    pub fn wdl(&self, value: Value, material: u32) -> Wdl {
+        if value.is_mate() {
+            return Wdl {
+                win: 1000,
+                draw: 0,
+                loss: 0,
+            };
+        } else if value.is_neg_mate() {
+            return Wdl {
+                win: 0,
+                draw: 0,
+                loss: 1000,
+            };
+        }
+
+        let cp = value.into_inner() as f64;
+        let win = (1000. * self.win_rate(cp, material)).round() as u16;
+        // Rounding can make the probabilities sum to more than 1000
+        let loss = ((1000. * self.win_rate(-cp, material)).round() as u16).min(1000 - win);
+        Wdl {
+            win,
+            draw: 1000 - win - loss,
+            loss,
+        }
+    }
+}
+
+/// Returns the material on the board counting pawns as 1, minor pieces as 3,
+/// rooks as 5 and queens as 9, which is 78 in the starting position.
+pub fn wdl_material(position: &Position) -> u32 {
+    use PieceKind::*;
+
+    [(Pawn, 1), (Knight, 3), (Bishop, 3), (Rook, 5), (Queen, 9)]
+        .into_iter()
+        .map(|(kind, value)| {
+            let count = position.pieces.get_bb(Piece(kind, Color::White)).len()
+                + position.pieces.get_bb(Piece(kind, Color::Black)).len();
+            value * count as u32
+        })
+        .sum()
+}
@@ -1,8 +1,10 @@
+pub mod book;
 pub mod collections;
 pub mod eval;
 pub mod move_gen;
 pub mod position;
 pub mod search;
+pub mod syzygy;
 pub mod tables;
 pub mod time_mananger;
 pub mod types;
@@ -2,10 +2,13 @@ use crate::collections::MoveList;
 use crate::position::Position;
 use crate::tables::Tables;
 use crate::types::{
-    Bitboard, BoardVector, Color, File, Move, Piece, PieceKind, Rank, Side, Square,
+    Bitboard, BoardVector, Color, File, Move, MoveKind, Piece, PieceKind, Rank, Side, Square,
 };
 use crate::{bb, mv};
 
+mod perft;
+pub use perft::{Perft, PerftStats, DEFAULT_PERFT_HASH_SIZE};
+mod see;
 #[cfg(test)]
 mod tests;
 
@@ -27,7 +30,7 @@ impl MoveGen {
         Self { tables }
     }
 
-    fn gen_moves_and_check<const ONLY_CAPTURES: bool>(
+    fn gen_moves_and_check<const CAPTURES: bool, const QUIETS: bool>(
         &self,
         position: &Position,
     ) -> (MoveList, bool) {
@@ -43,38 +46,38 @@ impl MoveGen {
             if checkers.len() == 2 {
                 // Safety: The move list is freshly initialized
                 unsafe {
-                    state.gen_non_pawn_moves::<ONLY_CAPTURES>(King, !bb!());
+                    state.gen_non_pawn_moves::<CAPTURES, QUIETS>(King, !bb!());
                 }
             } else {
                 state.set_pin_rays();
                 let checking_sq = checkers.into_iter().next().unwrap();
                 // Can't block a check with a capture, unless capturing the checker
-                let blocking_sqs = if ONLY_CAPTURES {
-                    checkers
-                } else {
+                let blocking_sqs = if QUIETS {
                     self.tables.ray_to[state.king_sq][checking_sq] | checkers
+                } else {
+                    checkers
                 };
 
                 // Safety: The move list is freshly initialized
                 unsafe {
-                    state.gen_pawn_moves::<ONLY_CAPTURES>(blocking_sqs);
+                    state.gen_pawn_moves::<CAPTURES, QUIETS>(blocking_sqs);
 
                     for kind in [Knight, Bishop, Rook, Queen] {
-                        state.gen_non_pawn_moves::<ONLY_CAPTURES>(kind, blocking_sqs);
+                        state.gen_non_pawn_moves::<CAPTURES, QUIETS>(kind, blocking_sqs);
                     }
-                    state.gen_non_pawn_moves::<ONLY_CAPTURES>(King, !bb!());
+                    state.gen_non_pawn_moves::<CAPTURES, QUIETS>(King, !bb!());
                 }
             }
         } else {
             state.set_pin_rays();
             // Safety: The move list is freshly initialized
             unsafe {
-                state.gen_pawn_moves::<ONLY_CAPTURES>(!bb!());
+                state.gen_pawn_moves::<CAPTURES, QUIETS>(!bb!());
                 for kind in [Knight, Bishop, Rook, Queen] {
-                    state.gen_non_pawn_moves::<ONLY_CAPTURES>(kind, !bb!());
+                    state.gen_non_pawn_moves::<CAPTURES, QUIETS>(kind, !bb!());
                 }
-                state.gen_non_pawn_moves::<ONLY_CAPTURES>(King, !bb!());
-                if !ONLY_CAPTURES {
+                state.gen_non_pawn_moves::<CAPTURES, QUIETS>(King, !bb!());
+                if QUIETS {
                     state.gen_castling_moves();
                 }
             }
@@ -84,15 +87,24 @@ impl MoveGen {
     }
 
     pub fn gen_all_moves(&self, position: &Position) -> MoveList {
-        self.gen_moves_and_check::<false>(position).0
+        self.gen_moves_and_check::<true, true>(position).0
     }
 
     pub fn gen_all_moves_and_check(&self, position: &Position) -> (MoveList, bool) {
-        self.gen_moves_and_check::<false>(position)
+        self.gen_moves_and_check::<true, true>(position)
     }
 
+    /// Generates all legal captures, including en passant and capturing
+    /// promotions.
     pub fn gen_captures(&self, position: &Position) -> MoveList {
-        self.gen_moves_and_check::<true>(position).0
+        self.gen_moves_and_check::<true, false>(position).0
+    }
+
+    /// Generates all legal moves that are not captures, i.e. the moves not
+    /// generated by [`gen_captures`](Self::gen_captures). This includes
+    /// castling and non-capturing promotions.
+    pub fn gen_quiets(&self, position: &Position) -> MoveList {
+        self.gen_moves_and_check::<false, true>(position).0
     }
 
     pub fn is_check(&self, position: &Position) -> bool {
@@ -101,6 +113,167 @@ impl MoveGen {
         state.danger_sqs.contains(state.king_sq)
     }
 
+    /// Returns whether `mv`, which must be legal in `position`, gives check.
+    /// This is cheaper than making the move and calling
+    /// [`is_check`](Self::is_check).
+    pub fn gives_check(&self, position: &Position, mv: Move) -> bool {
+        let color = position.to_move;
+        let pieces = &position.pieces;
+        let enemy_king_sq = pieces.king_sq_for(!color);
+        let (from, to) = (mv.from(), mv.to());
+
+        // The occupancy after the move and the piece the move places, which
+        // for castling is the rook, as the king can't give check
+        let mut occ = pieces.occupied() - bb!(from);
+        let mut moved_sliders = bb!(from);
+        let (placed, dest) = match mv.kind() {
+            MoveKind::Regular => {
+                let pce = pieces.get(from).expect("there should be a piece to move");
+                (pce.kind(), to)
+            }
+            MoveKind::Castling => {
+                let rook_dest = Square::rook_castling_dest(color, mv.castling_side());
+                let king_dest = Square::king_castling_dest(color, mv.castling_side());
+                occ = (occ - bb!(to)) | bb!(rook_dest) | bb!(king_dest);
+                moved_sliders = moved_sliders.with_sq(to);
+                (PieceKind::Rook, rook_dest)
+            }
+            MoveKind::Promotion(kind) => (kind, to),
+            MoveKind::EnPassant => {
+                let down = match color {
+                    Color::White => BoardVector::SOUTH,
+                    Color::Black => BoardVector::NORTH,
+                };
+                occ -= bb!(to + down);
+                (PieceKind::Pawn, to)
+            }
+        };
+        occ |= bb!(dest);
+
+        let direct = self
+            .tables
+            .gen_attacks_from_sq(occ, Piece(placed, color), dest);
+        if direct.contains(enemy_king_sq) {
+            return true;
+        }
+
+        // Discovered checks by the sliders that didn't move
+        let own = |kind| pieces.get_bb(Piece(kind, color)) - moved_sliders;
+        let diagonals = own(PieceKind::Bishop) | own(PieceKind::Queen);
+        let orthogonals = own(PieceKind::Rook) | own(PieceKind::Queen);
+        !(self.tables.gen_bishop_attacks(occ, enemy_king_sq) & diagonals).is_empty()
+            || !(self.tables.gen_rook_attacks(occ, enemy_king_sq) & orthogonals).is_empty()
+    }
+
+    /// Returns whether the piece on the from square of `mv` can make the move
+    /// in `position`, ignoring whether it leaves the king in check. Any 16-bit
+    /// move is accepted, e.g. one from the transposition table which may have
+    /// been stored for another position.
+    pub fn is_pseudo_legal(&self, position: &Position, mv: Move) -> bool {
+        let (from, to) = (mv.from(), mv.to());
+        let color = position.to_move;
+        let Some(pce) = position.pieces.get(from) else {
+            return false;
+        };
+        // This also rules out the null move
+        if pce.color() != color || from == to || !is_canonical(mv) {
+            return false;
+        }
+
+        let occ = position.pieces.occupied();
+        let attacks = self.tables.gen_attacks_from_sq(occ, pce, from);
+        match mv.kind() {
+            MoveKind::Castling => {
+                let side = mv.castling_side();
+                if pce.kind() != PieceKind::King
+                    || !position.castling.get(color, side)
+                    || position.castling_rook(color, side) != to
+                {
+                    return false;
+                }
+
+                // Apart from the king and rook, the squares passed are empty
+                let king_path = self.tables.ray_to[from][Square::king_castling_dest(color, side)];
+                let rook_path = self.tables.ray_to[to][Square::rook_castling_dest(color, side)];
+                ((king_path | rook_path) & (occ - bb!(from, to))).is_empty()
+            }
+            MoveKind::EnPassant => {
+                pce.kind() == PieceKind::Pawn
+                    && position.en_passant_sq == Some(to)
+                    && attacks.contains(to)
+            }
+            kind => {
+                let valid_target = match position.pieces.get(to) {
+                    Some(target) => {
+                        mv.capture() && target.color() != color && target.kind() != PieceKind::King
+                    }
+                    None => !mv.capture(),
+                };
+                let promotion = matches!(kind, MoveKind::Promotion(_));
+                if !valid_target {
+                    return false;
+                } else if pce.kind() != PieceKind::Pawn {
+                    return !promotion && attacks.contains(to);
+                }
+
+                let (up, second_rank, last_rank) = match color {
+                    Color::White => (BoardVector::NORTH, Rank::Second, Rank::Eighth),
+                    Color::Black => (BoardVector::SOUTH, Rank::Seventh, Rank::First),
+                };
+                if promotion != (to.rank() == last_rank) {
+                    return false;
+                }
+
+                if mv.capture() {
+                    attacks.contains(to)
+                } else {
+                    let fwd = from + up;
+                    to == fwd
+                        || (from.rank() == second_rank && !occ.contains(fwd) && to == fwd + up)
+                }
+            }
+        }
+    }
+
+    /// Returns whether `mv` is legal in `position`. Like
+    /// [`is_pseudo_legal`](Self::is_pseudo_legal), any move is accepted.
+    pub fn is_legal(&self, position: &Position, mv: Move) -> bool {
+        if !self.is_pseudo_legal(position, mv) {
+            return false;
+        }
+
+        let mut state = MoveGenState::new(position, self.tables);
+        state.set_danger_sqs();
+        let (from, to) = (mv.from(), mv.to());
+        if let MoveKind::Castling = mv.kind() {
+            // Also checks that the king doesn't pass through attacked squares
+            return state.can_castle(mv.castling_side());
+        } else if from == state.king_sq {
+            return !state.danger_sqs.contains(to);
+        }
+
+        let blocking_sqs = if state.danger_sqs.contains(state.king_sq) {
+            let checkers = state.checkers();
+            if checkers.len() == 2 {
+                return false;
+            }
+            let checking_sq = checkers.into_iter().next().unwrap();
+            self.tables.ray_to[state.king_sq][checking_sq] | checkers
+        } else {
+            !bb!()
+        };
+
+        state.set_pin_rays();
+        if let MoveKind::EnPassant = mv.kind() {
+            // As in move generation, en passant can only resolve a check by
+            // capturing the checking pawn
+            let ep_pawn_sq = Square::from_rank_file(from.rank(), to.file());
+            blocking_sqs.contains(ep_pawn_sq) && state.is_en_passant_legal(from, to)
+        } else {
+            blocking_sqs.contains(to) && !state.is_prohibited_by_pin(from, to)
+        }
+    }
+
     pub fn perft(&self, mut position: Position, depth: i8) -> u64 {
         if depth == 0 {
             return 1;
@@ -126,6 +299,19 @@ impl MoveGen {
     }
 }
 
+/// Returns whether `mv` is encoded the way the move constructors encode it,
+/// which is not the case for every 16-bit value.
+fn is_canonical(mv: Move) -> bool {
+    let (from, to) = (mv.from(), mv.to());
+    let canonical = match mv.kind() {
+        MoveKind::Regular => Move::new_regular(from, to, mv.capture()),
+        MoveKind::Castling => Move::new_castling(from, to),
+        MoveKind::Promotion(kind) => Move::new_promotion(from, to, kind, mv.capture()),
+        MoveKind::EnPassant => Move::new_en_passant(from, to),
+    };
+    mv == canonical
+}
+
 struct MoveGenState<'p> {
     moves: MoveList,
     king_sq: Square,
@@ -154,7 +340,7 @@ impl<'p> MoveGenState<'p> {
     ///
     /// # Safety
     /// The move list must have space for the added moves.
-    unsafe fn gen_non_pawn_moves<const ONLY_CAPTURES: bool>(
+    unsafe fn gen_non_pawn_moves<const CAPTURES: bool, const QUIETS: bool>(
         &mut self,
         kind: PieceKind,
         blocking_sqs: Bitboard,
@@ -177,11 +363,13 @@ impl<'p> MoveGenState<'p> {
                 legal_atks -= self.danger_sqs;
             }
 
-            for to in legal_atks & opp_occ {
-                self.moves.push_unchecked(mv!(from x to));
+            if CAPTURES {
+                for to in legal_atks & opp_occ {
+                    self.moves.push_unchecked(mv!(from x to));
+                }
             }
 
-            if !ONLY_CAPTURES {
+            if QUIETS {
                 let own_occ = self.position.pieces.occupied_for(self.position.to_move);
                 for to in legal_atks & !own_occ & !opp_occ {
                     self.moves.push_unchecked(mv!(from -> to));
@@ -194,7 +382,10 @@ impl<'p> MoveGenState<'p> {
     ///
     /// # Safety
     /// The move list must have space for the added moves.
-    unsafe fn gen_pawn_moves<const ONLY_CAPTURES: bool>(&mut self, blocking_sqs: Bitboard) {
+    unsafe fn gen_pawn_moves<const CAPTURES: bool, const QUIETS: bool>(
+        &mut self,
+        blocking_sqs: Bitboard,
+    ) {
         let pawns = self
             .position
             .pieces
@@ -262,30 +453,14 @@ impl<'p> MoveGenState<'p> {
         macro_rules! add_en_passant {
             ( $to:expr => $dir:expr ) => {
                 let from = $to - $dir;
-                let ep_pawn_sq = $to - up;
-
-                let fifth_rank = Bitboard::RANKS[from.rank() as usize];
-                let opp_rooks = self.position
-                    .pieces
-                    .get_bb(Piece(PieceKind::Rook, !self.position.to_move));
-                let opp_queens = self.position
-                    .pieces
-                    .get_bb(Piece(PieceKind::Queen, !self.position.to_move));
-                let occ = self.position.pieces.occupied() - bb!(from, ep_pawn_sq);
-                let ep_pinners =
-                    self.tables.gen_rook_attacks(occ, self.king_sq) & fifth_rank & (opp_rooks | opp_queens);
-
-                if self.is_prohibited_by_pin(from, $to) || !ep_pinners.is_empty()
-                {
-                    return;
+                if self.is_en_passant_legal(from, $to) {
+                    self.moves.push_unchecked(mv!(from ep $to));
                 }
-
-                self.moves.push_unchecked(mv!(from ep $to));
             };
         }
 
         // Forward
-        if !ONLY_CAPTURES {
+        if QUIETS {
             let fwd = (pawns >> up) - self.occupied;
             let legal_fwd = fwd & blocking_sqs;
             let fwd_no_promo = legal_fwd - last_rank;
@@ -299,6 +474,10 @@ impl<'p> MoveGenState<'p> {
             });
         }
 
+        if !CAPTURES {
+            return;
+        }
+
         // Attacks
         let legal_atk = self.position.pieces.occupied_for(!self.position.to_move) & blocking_sqs;
         let left = (pawns >> up_left) - right_file;
@@ -334,6 +513,27 @@ impl<'p> MoveGenState<'p> {
         }
     }
 
+    /// Returns whether the en passant capture from `from` to `to` neither
+    /// breaks a pin nor uncovers an attack on the king along the rank of the
+    /// captured pawn. Requires the pin rays to be set.
+    fn is_en_passant_legal(&self, from: Square, to: Square) -> bool {
+        let ep_pawn_sq = Square::from_rank_file(from.rank(), to.file());
+        let opp_rooks = self
+            .position
+            .pieces
+            .get_bb(Piece(PieceKind::Rook, !self.position.to_move));
+        let opp_queens = self
+            .position
+            .pieces
+            .get_bb(Piece(PieceKind::Queen, !self.position.to_move));
+        let occ = self.occupied - bb!(from, ep_pawn_sq);
+        let ep_pinners = self.tables.gen_rook_attacks(occ, self.king_sq)
+            & Bitboard::RANKS[from.rank() as usize]
+            & (opp_rooks | opp_queens);
+
+        !self.is_prohibited_by_pin(from, to) && ep_pinners.is_empty()
+    }
+
     fn is_prohibited_by_pin(&self, from: Square, to: Square) -> bool {
         if self.pin_rays.contains(from) {
             let pin_ray = self.pin_rays & self.tables.line_through[from][self.king_sq];
@@ -343,33 +543,50 @@ impl<'p> MoveGenState<'p> {
         }
     }
 
-    /// Generates castling moves for the current position
+    /// Generates castling moves for the current position. The king and rook
+    /// may start on any squares, as in Chess960.
     ///
     /// # Safety
     /// The move list must have space for the added moves.
     unsafe fn gen_castling_moves(&mut self) {
-        let mut gen_castling_move = |side| {
-            if !self.position.castling.get(self.position.to_move, side) {
-                return;
+        for side in [Side::KingSide, Side::QueenSide] {
+            if self.can_castle(side) {
+                let rook_sq = self.position.castling_rook(self.position.to_move, side);
+                self.moves
+                    .push_unchecked(Move::new_castling(self.king_sq, rook_sq));
             }
+        }
+    }
 
-            let (castling_sqs, no_occ_sqs) = match (self.position.to_move, side) {
-                (Color::White, Side::KingSide) => (bb!(E1, F1, G1), bb!(F1, G1)),
-                (Color::Black, Side::KingSide) => (bb!(E8, F8, G8), bb!(F8, G8)),
-                (Color::White, Side::QueenSide) => (bb!(E1, D1, C1), bb!(D1, C1, B1)),
-                (Color::Black, Side::QueenSide) => (bb!(E8, D8, C8), bb!(D8, C8, B8)),
-            };
+    /// Returns whether the player to move can legally castle to `side`.
+    /// Requires the danger squares to be set.
+    fn can_castle(&self, side: Side) -> bool {
+        let color = self.position.to_move;
+        if !self.position.castling.get(color, side) {
+            return false;
+        }
 
-            if ((castling_sqs & self.danger_sqs) | (no_occ_sqs & self.occupied)).is_empty() {
-                let king_sq = Square::king_starting(self.position.to_move);
-                let castling_sq = Square::king_castling_dest(self.position.to_move, side);
-                self.moves
-                    .push_unchecked(Move::new_castling(king_sq, castling_sq));
-            }
-        };
+        let rook_sq = self.position.castling_rook(color, side);
+        let king_dest = Square::king_castling_dest(color, side);
+        let rook_dest = Square::rook_castling_dest(color, side);
+
+        // Apart from the king and rook, all squares passed are empty, and
+        // the squares passed by the king are not attacked
+        let king_path = self.tables.ray_to[self.king_sq][king_dest];
+        let rook_path = self.tables.ray_to[rook_sq][rook_dest];
+        let occ = self.occupied - bb!(self.king_sq, rook_sq);
+        if !((king_path | rook_path) & occ).is_empty()
+            || !(king_path.with_sq(self.king_sq) & self.danger_sqs).is_empty()
+        {
+            return false;
+        }
 
-        gen_castling_move(Side::KingSide);
-        gen_castling_move(Side::QueenSide);
+        // The rook may have been blocking an attack along the back rank
+        let opp_rooks = self.position.pieces.get_bb(Piece(PieceKind::Rook, !color));
+        let opp_queens = self.position.pieces.get_bb(Piece(PieceKind::Queen, !color));
+        let rank_atks = self.tables.gen_rook_attacks(occ, king_dest)
+            & Bitboard::RANKS[king_dest.rank() as usize];
+        (rank_atks & (opp_rooks | opp_queens)).is_empty()
     }
 
     fn checkers(&self) -> Bitboard {
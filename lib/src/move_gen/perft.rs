@@ -0,0 +1,270 @@
+use std::mem::size_of;
+use std::ops::{Add, AddAssign};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::position::Position;
+use crate::types::{Move, MoveKind};
+
+use super::MoveGen;
+
+/// The default size of the perft hash table in MB.
+pub const DEFAULT_PERFT_HASH_SIZE: usize = 64;
+
+/// The number of leaf nodes of a perft run, broken down by the kind of the
+/// last move like in the tables on the Chess Programming Wiki. Apart from
+/// `nodes`, the counts are only computed by a detailed [`Perft`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PerftStats {
+    pub nodes: u64,
+    pub captures: u64,
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub mates: u64,
+}
+
+impl Add for PerftStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            nodes: self.nodes + rhs.nodes,
+            captures: self.captures + rhs.captures,
+            en_passants: self.en_passants + rhs.en_passants,
+            castles: self.castles + rhs.castles,
+            promotions: self.promotions + rhs.promotions,
+            checks: self.checks + rhs.checks,
+            mates: self.mates + rhs.mates,
+        }
+    }
+}
+
+impl AddAssign for PerftStats {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+/// A perft runner, which counts the leaf nodes of the game tree to a given
+/// depth for validating the move generator. The root moves are split across
+/// threads, and the counts of subtrees are cached in a hash table keyed by
+/// the zobrist hash of the position.
+///
+/// # Example
+/// ```
+/// use kingly_lib::move_gen::Perft;
+/// use kingly_lib::Position;
+///
+/// let stats = Perft::new().detailed(true).run(&Position::new(), 3);
+/// assert_eq!(stats.nodes, 8902);
+/// assert_eq!(stats.checks, 12);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Perft {
+    move_gen: MoveGen,
+    num_threads: usize,
+    hash_size: usize,
+    detailed: bool,
+}
+
+impl Perft {
+    /// Creates a perft runner using all available cores and a hash table of
+    /// [`DEFAULT_PERFT_HASH_SIZE`] MB. This initializes the lookup tables, so it
+    /// can be a slow operation.
+    pub fn new() -> Self {
+        Self {
+            move_gen: MoveGen::init(),
+            num_threads: num_cpus::get(),
+            hash_size: DEFAULT_PERFT_HASH_SIZE,
+            detailed: false,
+        }
+    }
+
+    /// Sets the number of threads to use.
+    pub fn threads(mut self, num_threads: usize) -> Self {
+        assert!(num_threads > 0, "perft needs at least one thread");
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Sets the size of the hash table in MB. A size of 0 disables hashing.
+    pub fn hash_size(mut self, hash_size: usize) -> Self {
+        self.hash_size = hash_size;
+        self
+    }
+
+    /// Sets whether to count captures, checks etc. and not just nodes. This
+    /// requires making every move at the last ply, which is a lot slower.
+    pub fn detailed(mut self, detailed: bool) -> Self {
+        self.detailed = detailed;
+        self
+    }
+
+    /// Counts the leaf nodes of `position` at `depth`.
+    pub fn run(&self, position: &Position, depth: i8) -> PerftStats {
+        if depth <= 0 {
+            return PerftStats {
+                nodes: 1,
+                ..Default::default()
+            };
+        }
+
+        self.divide(position, depth)
+            .into_iter()
+            .fold(PerftStats::default(), |total, (_, stats)| total + stats)
+    }
+
+    /// Counts the leaf nodes of `position` at `depth` for each legal move, in
+    /// the order they are generated. The result is empty if `depth` is not
+    /// positive.
+    pub fn divide(&self, position: &Position, depth: i8) -> Vec<(Move, PerftStats)> {
+        if depth <= 0 {
+            return Vec::new();
+        }
+
+        let moves = self.move_gen.gen_all_moves(position);
+        let table = PerftTable::with_hash_size(self.hash_size);
+        let next_move = AtomicUsize::new(0);
+        let mut results = vec![PerftStats::default(); moves.len()];
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.num_threads.min(moves.len()))
+                .map(|_| {
+                    let mut position = position.clone();
+                    let (moves, table, next_move) = (&moves, &table, &next_move);
+                    scope.spawn(move || {
+                        let mut results = Vec::new();
+                        loop {
+                            let i = next_move.fetch_add(1, Ordering::Relaxed);
+                            let Some(&mv) = moves.get(i) else {
+                                return results;
+                            };
+                            results.push((i, self.move_stats(&mut position, mv, depth, table)));
+                        }
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                let worker_results = worker.join().expect("perft worker shouldn't panic");
+                for (i, stats) in worker_results {
+                    results[i] = stats;
+                }
+            }
+        });
+
+        moves.into_iter().zip(results).collect()
+    }
+
+    /// Counts the leaf nodes after making `mv`, where `depth` includes the
+    /// move itself.
+    fn move_stats(
+        &self,
+        position: &mut Position,
+        mv: Move,
+        depth: i8,
+        table: &PerftTable,
+    ) -> PerftStats {
+        if depth == 1 && !self.detailed {
+            return PerftStats {
+                nodes: 1,
+                ..Default::default()
+            };
+        }
+
+        position.make_move(mv);
+        let stats = if depth == 1 {
+            let (moves, check) = self.move_gen.gen_all_moves_and_check(position);
+            PerftStats {
+                nodes: 1,
+                captures: mv.capture() as u64,
+                en_passants: matches!(mv.kind(), MoveKind::EnPassant) as u64,
+                castles: matches!(mv.kind(), MoveKind::Castling) as u64,
+                promotions: mv.promotion().is_some() as u64,
+                checks: check as u64,
+                mates: (check && moves.is_empty()) as u64,
+            }
+        } else {
+            self.node_stats(position, depth - 1, table)
+        };
+        position.unmake_move();
+
+        stats
+    }
+
+    /// Counts the leaf nodes of `position` at a positive `depth`.
+    fn node_stats(&self, position: &mut Position, depth: i8, table: &PerftTable) -> PerftStats {
+        // The moves at depth 1 are counted without making them
+        if depth == 1 && !self.detailed {
+            return PerftStats {
+                nodes: self.move_gen.gen_all_moves(position).len() as u64,
+                ..Default::default()
+            };
+        }
+
+        if let Some(stats) = table.get(position.zobrist, depth) {
+            return stats;
+        }
+
+        let mut stats = PerftStats::default();
+        for mv in self.move_gen.gen_all_moves(position) {
+            stats += self.move_stats(position, mv, depth, table);
+        }
+        table.insert(position.zobrist, depth, stats);
+
+        stats
+    }
+}
+
+impl Default for Perft {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: i8,
+    stats: PerftStats,
+}
+
+/// A hash table of subtree counts. Each entry is locked separately, since
+/// entries are too large to update atomically, and contention is rare.
+struct PerftTable {
+    entries: Vec<Mutex<PerftEntry>>,
+}
+
+impl PerftTable {
+    fn with_hash_size(hash_size: usize) -> Self {
+        let capacity = hash_size * (1 << 20) / size_of::<Mutex<PerftEntry>>();
+        Self {
+            entries: (0..capacity).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn entry(&self, key: u64, depth: i8) -> Option<&Mutex<PerftEntry>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        // Mix in the depth so that the same position at different depths
+        // doesn't compete for one entry
+        let index = (key ^ (depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)) as usize
+            % self.entries.len();
+        Some(&self.entries[index])
+    }
+
+    fn get(&self, key: u64, depth: i8) -> Option<PerftStats> {
+        let entry = *self.entry(key, depth)?.lock().unwrap();
+        (entry.key == key && entry.depth == depth).then_some(entry.stats)
+    }
+
+    fn insert(&self, key: u64, depth: i8, stats: PerftStats) {
+        if let Some(entry) = self.entry(key, depth) {
+            *entry.lock().unwrap() = PerftEntry { key, depth, stats };
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::bb;
+use crate::eval::piece_value;
+use crate::position::Position;
+use crate::types::{Bitboard, Color, Move, MoveKind, Piece, PieceKind, Square};
+
+use super::MoveGen;
+
+/// The value of the king in exchanges, which is high enough that capturing it
+/// is never worth losing anything else.
+const KING_VALUE: i16 = 10_000;
+
+fn see_value(kind: PieceKind) -> i16 {
+    match kind {
+        PieceKind::King => KING_VALUE,
+        kind => piece_value(kind),
+    }
+}
+
+impl MoveGen {
+    /// Returns the static exchange evaluation of `mv` in centipawns, i.e. the
+    /// material won by the player to move if both players keep capturing on
+    /// the destination square for as long as it is profitable. Non-captures
+    /// are evaluated as if the moving piece may be captured.
+    pub fn see(&self, position: &Position, mv: Move) -> i16 {
+        let (from, to) = (mv.from(), mv.to());
+        if let MoveKind::Castling = mv.kind() {
+            return 0;
+        }
+
+        let Some(mut attacker) = position.pieces.get(from).map(|pce| pce.kind()) else {
+            return 0;
+        };
+        let mut occ = position.pieces.occupied() - bb!(from);
+        let mut gain = [0; 32];
+        gain[0] = match mv.kind() {
+            MoveKind::EnPassant => {
+                occ -= bb!(Square::from_rank_file(from.rank(), to.file()));
+                piece_value(PieceKind::Pawn)
+            }
+            _ => position
+                .pieces
+                .get(to)
+                .map(|pce| piece_value(pce.kind()))
+                .unwrap_or(0),
+        };
+        if let Some(kind) = mv.promotion() {
+            gain[0] += piece_value(kind) - piece_value(PieceKind::Pawn);
+            attacker = kind;
+        }
+
+        let mut color = !position.to_move;
+        let mut depth = 0;
+        loop {
+            depth += 1;
+            // The gain if the last attacker is captured
+            gain[depth] = see_value(attacker) - gain[depth - 1];
+            if (-gain[depth - 1]).max(gain[depth]) < 0 || depth == gain.len() - 1 {
+                break;
+            }
+
+            // Attacks are recomputed from the remaining pieces, which
+            // includes sliders behind the pieces that have already captured
+            let attackers = self.attackers_to(position, to, occ) & occ;
+            let Some((sq, kind)) = self.least_valuable_attacker(position, attackers, color) else {
+                break;
+            };
+            // The king can't capture into a defended square
+            if kind == PieceKind::King
+                && !(attackers & position.pieces.occupied_for(!color)).is_empty()
+            {
+                break;
+            }
+
+            occ -= bb!(sq);
+            attacker = kind;
+            color = !color;
+        }
+
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        }
+        gain[0]
+    }
+
+    /// Returns the pieces of both colors attacking `sq` given the occupancy
+    /// `occ`.
+    fn attackers_to(&self, position: &Position, sq: Square, occ: Bitboard) -> Bitboard {
+        let pieces = &position.pieces;
+        let both_colors = |kind| {
+            pieces.get_bb(Piece(kind, Color::White)) | pieces.get_bb(Piece(kind, Color::Black))
+        };
+        let diagonals = both_colors(PieceKind::Bishop) | both_colors(PieceKind::Queen);
+        let orthogonals = both_colors(PieceKind::Rook) | both_colors(PieceKind::Queen);
+        let white_pawn = Piece(PieceKind::Pawn, Color::White);
+        let black_pawn = Piece(PieceKind::Pawn, Color::Black);
+
+        // A pawn attacks `sq` if a pawn of the other color on `sq` attacks it
+        (self.tables.gen_attacks_from_sq(occ, black_pawn, sq) & pieces.get_bb(white_pawn))
+            | (self.tables.gen_attacks_from_sq(occ, white_pawn, sq) & pieces.get_bb(black_pawn))
+            | (self.tables.knight_attacks[sq] & both_colors(PieceKind::Knight))
+            | (self.tables.king_attacks[sq] & both_colors(PieceKind::King))
+            | (self.tables.gen_bishop_attacks(occ, sq) & diagonals)
+            | (self.tables.gen_rook_attacks(occ, sq) & orthogonals)
+    }
+
+    fn least_valuable_attacker(
+        &self,
+        position: &Position,
+        attackers: Bitboard,
+        color: Color,
+    ) -> Option<(Square, PieceKind)> {
+        use PieceKind::*;
+
+        [Pawn, Knight, Bishop, Rook, Queen, King]
+            .into_iter()
+            .find_map(|kind| {
+                let sq = (attackers & position.pieces.get_bb(Piece(kind, color)))
+                    .into_iter()
+                    .next()?;
+                Some((sq, kind))
+            })
+    }
+}
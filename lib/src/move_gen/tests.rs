@@ -1,15 +1,18 @@
 use std::fs;
 use std::path::PathBuf;
 
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use serde::Deserialize;
 
 use crate::move_gen::MoveGenState;
 use crate::position::Position;
 use crate::tables::Tables;
-use crate::types::{PieceKind, Square};
+use crate::types::{Move, MoveKind, PieceKind, Square};
 use crate::{bb, mv};
 
-use super::MoveGen;
+use super::{MoveGen, Perft, PerftStats};
 
 #[derive(Deserialize)]
 struct PerftPosition {
@@ -18,13 +21,19 @@ struct PerftPosition {
     fen: String,
 }
 
-#[test]
-fn test_perft() {
+/// Loads the perft tests in the given file in `resources/test`.
+fn load_perft_positions(file_name: &str) -> Vec<PerftPosition> {
     let mut test_path = PathBuf::new();
     test_path.push(env!("CARGO_MANIFEST_DIR"));
-    test_path.push("../resources/test/perft_positions.json");
+    test_path.push("../resources/test");
+    test_path.push(file_name);
     let test_file = fs::File::open(test_path).unwrap();
-    let tests: Vec<PerftPosition> = serde_json::from_reader(test_file).unwrap();
+    serde_json::from_reader(test_file).unwrap()
+}
+
+/// Runs the perft tests in the given file in `resources/test`.
+fn run_perft_tests(file_name: &str) {
+    let tests = load_perft_positions(file_name);
 
     let move_gen = MoveGen::init();
     println!("Testing Perft...");
@@ -42,7 +51,66 @@ fn test_perft() {
     println!("All Perft test positions passed")
 }
 
-fn get_and_init_state(position: &Position) -> MoveGenState {
+#[test]
+fn test_perft() {
+    run_perft_tests("perft_positions.json");
+}
+
+#[test]
+fn test_perft_chess960() {
+    run_perft_tests("perft_chess960.json");
+}
+
+#[test]
+fn hashed_perft_matches_perft_positions() {
+    let perft = Perft::new().threads(4).hash_size(4);
+    for test in load_perft_positions("perft_positions.json") {
+        let position = Position::from_fen(&test.fen).unwrap();
+        assert_eq!(
+            perft.run(&position, test.depth).nodes,
+            test.nodes,
+            "{}",
+            test.fen
+        );
+    }
+}
+
+#[test]
+fn detailed_perft_counts_move_kinds() {
+    let perft = Perft::new().threads(2).hash_size(1).detailed(true);
+
+    let stats = perft.run(&Position::new(), 4);
+    let expected = PerftStats {
+        nodes: 197_281,
+        captures: 1_576,
+        en_passants: 0,
+        castles: 0,
+        promotions: 0,
+        checks: 469,
+        mates: 8,
+    };
+    assert_eq!(stats, expected);
+
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    let divide = perft.divide(&position, 3);
+    assert_eq!(divide.len(), 48);
+    let stats = divide
+        .into_iter()
+        .fold(PerftStats::default(), |total, (_, stats)| total + stats);
+    let expected = PerftStats {
+        nodes: 97_862,
+        captures: 17_102,
+        en_passants: 45,
+        castles: 3_162,
+        promotions: 0,
+        checks: 993,
+        mates: 1,
+    };
+    assert_eq!(stats, expected);
+}
+
+fn get_and_init_state(position: &Position) -> MoveGenState<'_> {
     let mut state = MoveGenState::new(position, Tables::get_or_init());
     state.set_pin_rays();
     state.set_danger_sqs();
@@ -55,7 +123,7 @@ fn correct_pawn_moves_in_starting_position() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -73,7 +141,7 @@ fn correct_forward_pawn_moves_for_black() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -91,7 +159,7 @@ fn correct_pawn_captures_for_white() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -108,7 +176,7 @@ fn correct_pawn_captures_for_black() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -125,7 +193,7 @@ fn correct_en_passant() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -141,7 +209,7 @@ fn correct_promotions() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_pawn_moves::<false>(!bb!());
+        state.gen_pawn_moves::<true, true>(!bb!());
     }
     let moves = state.moves;
 
@@ -160,7 +228,7 @@ fn correct_knight_moves_for_white() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::Knight, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::Knight, !bb!());
     }
     let moves = state.moves;
 
@@ -179,7 +247,7 @@ fn correct_knight_moves_for_black() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::Knight, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::Knight, !bb!());
     }
     let moves = state.moves;
 
@@ -198,7 +266,7 @@ fn correct_non_castling_king_moves_for_white() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::King, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::King, !bb!());
     }
     let moves = state.moves;
 
@@ -215,7 +283,7 @@ fn correct_non_castling_king_moves_for_black() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::King, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::King, !bb!());
     }
     let moves = state.moves;
 
@@ -294,6 +362,38 @@ fn no_castling_when_in_check() {
     assert!(!moves.contains(mv!(O-O-O b)));
 }
 
+#[test]
+fn chess960_castling_without_moving_the_king() {
+    let fen = "1r4k1/8/8/8/8/8/8/6KR w H - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    let mut state = get_and_init_state(&position);
+
+    unsafe {
+        state.gen_castling_moves();
+    }
+    let moves = state.moves;
+
+    let castling = moves
+        .iter()
+        .find(|mv| matches!(mv.kind(), MoveKind::Castling))
+        .unwrap();
+    assert_eq!(*castling, Move::new_castling(Square::G1, Square::H1));
+    assert_eq!(castling.castling_king_dest(), Square::G1);
+}
+
+#[test]
+fn no_chess960_castling_when_rook_blocks_attack() {
+    let fen = "2k5/8/8/8/8/8/8/qR1K4 w B - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    let mut state = get_and_init_state(&position);
+
+    unsafe {
+        state.gen_castling_moves();
+    }
+
+    assert!(state.moves.is_empty());
+}
+
 #[test]
 fn correct_bishop_moves() {
     let fen = "rnbqkbnr/1ppp1pp1/p7/4p2p/1PB1P3/8/P1PP1PPP/RNBQK1NR w KQkq - 0 5";
@@ -301,7 +401,7 @@ fn correct_bishop_moves() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::Bishop, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::Bishop, !bb!());
     }
     let moves = state.moves;
 
@@ -321,7 +421,7 @@ fn correct_rook_moves() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::Rook, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::Rook, !bb!());
     }
     let moves = state.moves;
 
@@ -341,7 +441,7 @@ fn correct_queen_moves() {
     let mut state = get_and_init_state(&position);
 
     unsafe {
-        state.gen_non_pawn_moves::<false>(PieceKind::Queen, !bb!());
+        state.gen_non_pawn_moves::<true, true>(PieceKind::Queen, !bb!());
     }
     let moves = state.moves;
 
@@ -542,6 +642,192 @@ fn gen_captures_works() {
     assert_eq!(moves.len(), 9);
 }
 
+#[test]
+fn gen_quiets_complements_gen_captures() {
+    let fens = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "2k5/1p1p2R1/4q3/1QpPn3/8/3N2B1/8/7K w - c6 0 1",
+        "rnbqk1nr/1ppp1p2/p5pp/3Pp3/1b1QP3/P7/1PP2PPP/RNB1KBNR w KQkq - 1 6",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+    ];
+    let move_gen = MoveGen::init();
+    for fen in fens {
+        let position = Position::from_fen(fen).unwrap();
+        let captures = move_gen.gen_captures(&position);
+        let quiets = move_gen.gen_quiets(&position);
+        let all_moves = move_gen.gen_all_moves(&position);
+
+        assert!(captures.iter().all(|mv| mv.capture()), "{fen}");
+        assert!(quiets.iter().all(|mv| !mv.capture()), "{fen}");
+        assert_eq!(captures.len() + quiets.len(), all_moves.len(), "{fen}");
+        assert!(all_moves
+            .iter()
+            .all(|&mv| captures.contains(mv) || quiets.contains(mv)));
+    }
+}
+
+#[test]
+fn is_legal_checks_pins_and_checks() {
+    let move_gen = MoveGen::init();
+
+    let position = Position::from_fen("8/4k3/3nn3/8/1B2R3/8/3K4/8 b - - 0 1").unwrap();
+    assert!(move_gen.is_pseudo_legal(&position, mv!(E6 -> C5)));
+    assert!(!move_gen.is_legal(&position, mv!(E6 -> C5)));
+    assert!(move_gen.is_legal(&position, mv!(E7 -> F7)));
+    assert!(!move_gen.is_legal(&position, mv!(D6 -> F5)));
+
+    let fen = "rnbqk1nr/1ppp1p2/p5pp/3Pp3/1b1QP3/P7/1PP2PPP/RNB1KBNR w KQkq - 1 6";
+    let position = Position::from_fen(fen).unwrap();
+    assert!(move_gen.is_legal(&position, mv!(B1 -> C3)));
+    assert!(!move_gen.is_legal(&position, mv!(G1 -> F3)));
+    // Wrong capture flag
+    assert!(!move_gen.is_pseudo_legal(&position, mv!(A3 -> B4)));
+    // No piece to move
+    assert!(!move_gen.is_pseudo_legal(&position, mv!(E3 -> E4)));
+}
+
+/// Returns every encoding of a move by a piece of the player to move, along
+/// with some random 16-bit moves.
+fn candidate_moves(position: &Position, rng: &mut impl Rng) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for from in position.pieces.occupied_for(position.to_move) {
+        for to in Square::iter() {
+            moves.push(Move::new_regular(from, to, false));
+            moves.push(Move::new_regular(from, to, true));
+            moves.push(Move::new_en_passant(from, to));
+            moves.push(Move::new_castling(from, to));
+            if position.pieces.get(from).unwrap().kind() == PieceKind::Pawn {
+                for kind in [
+                    PieceKind::Knight,
+                    PieceKind::Bishop,
+                    PieceKind::Rook,
+                    PieceKind::Queen,
+                ] {
+                    moves.push(Move::new_promotion(from, to, kind, false));
+                    moves.push(Move::new_promotion(from, to, kind, true));
+                }
+            }
+        }
+    }
+    // Safety: `is_legal` accepts any 16-bit move
+    moves.extend((0..100).map(|_| unsafe { Move::from_inner(rng.gen()) }));
+    moves
+}
+
+/// Plays random games from the perft test positions and some positions with
+/// tricky en passant and castling moves, and checks that `is_legal` agrees
+/// with move generation on all possible moves.
+#[test]
+fn is_legal_agrees_with_move_generation() {
+    let move_gen = MoveGen::init();
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    let mut fens: Vec<_> = load_perft_positions("perft_positions.json")
+        .into_iter()
+        .chain(load_perft_positions("perft_chess960.json"))
+        .map(|perft_position| perft_position.fen)
+        .collect();
+    fens.extend(
+        [
+            // En passant uncovering an attack along the rank
+            "8/8/8/K1pP3r/8/8/8/4k3 w - c6 0 1",
+            // En passant capturing the checking pawn
+            "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+            // Castling through an attacked square
+            "r3k2r/8/8/8/8/5r2/8/R3K2R w KQkq - 0 1",
+            // Chess960 castling where the rook shields the king
+            "8/8/8/8/8/8/8/qRK4k w B - 0 1",
+        ]
+        .map(String::from),
+    );
+
+    for fen in fens {
+        let mut position = Position::from_fen(&fen).unwrap();
+        for _ in 0..8 {
+            let legal_moves = move_gen.gen_all_moves(&position);
+            for mv in candidate_moves(&position, &mut rng) {
+                let legal = legal_moves.contains(mv);
+                assert_eq!(
+                    move_gen.is_legal(&position, mv),
+                    legal,
+                    "{mv:?} in {}",
+                    position.to_fen()
+                );
+                assert!(!legal || move_gen.is_pseudo_legal(&position, mv));
+            }
+
+            let Some(&mv) = legal_moves.choose(&mut rng) else {
+                break;
+            };
+            position.make_move(mv);
+        }
+    }
+}
+
+/// Plays random games from the perft test positions and checks that
+/// `gives_check` agrees with making each legal move.
+#[test]
+fn gives_check_agrees_with_making_moves() {
+    let move_gen = MoveGen::init();
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    let mut fens: Vec<_> = load_perft_positions("perft_positions.json")
+        .into_iter()
+        .chain(load_perft_positions("perft_chess960.json"))
+        .map(|perft_position| perft_position.fen)
+        .collect();
+    fens.extend(
+        [
+            // En passant uncovering an attack on the king
+            "8/8/8/K1pP3k/8/8/8/8 w - c6 0 1",
+            // Castling with the rook giving check
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            // Promotions giving direct and discovered checks
+            "3k4/1P6/8/8/8/8/8/1R2K3 w - - 0 1",
+        ]
+        .map(String::from),
+    );
+
+    for fen in fens {
+        let mut position = Position::from_fen(&fen).unwrap();
+        for _ in 0..16 {
+            let legal_moves = move_gen.gen_all_moves(&position);
+            for &mv in &legal_moves {
+                let gives_check = move_gen.gives_check(&position, mv);
+                position.make_move(mv);
+                let check = move_gen.is_check(&position);
+                position.unmake_move();
+                assert_eq!(gives_check, check, "{mv:?} in {}", position.to_fen());
+            }
+
+            let Some(&mv) = legal_moves.choose(&mut rng) else {
+                break;
+            };
+            position.make_move(mv);
+        }
+    }
+}
+
+#[test]
+fn see_evaluates_exchanges() {
+    let move_gen = MoveGen::init();
+    let see = |fen, mv| move_gen.see(&Position::from_fen(fen).unwrap(), mv);
+
+    assert_eq!(
+        see(
+            "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+            mv!(E1 x E5)
+        ),
+        100
+    );
+    // Sliders behind the capturing pieces join the exchange
+    let fen = "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1";
+    assert_eq!(see(fen, mv!(D3 x E5)), -220);
+    assert_eq!(
+        see("4k3/8/3p4/8/4N3/8/8/4K3 w - - 0 1", mv!(E4 -> C5)),
+        -320
+    );
+    assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", mv!(E5 ep D6)), 100);
+}
+
 // Test positions added to fix bugs in the move generator
 
 #[test]
@@ -1,19 +1,18 @@
 use std::num::ParseIntError;
 use std::str::FromStr;
 
-use intmap::IntMap;
 use strum::IntoEnumIterator;
 
 use crate::eval::{piece_value_early, piece_value_endgame};
 use crate::tables::Tables;
 use crate::types::{
-    CastlingRights, Color, File, ParseSquareError, Piece, PieceFromCharError, PieceKind, Rank,
-    Square,
+    Bitboard, CastlingRights, Color, File, ParseSquareError, Piece, PieceFromCharError, PieceKind,
+    Rank, Side, Square,
 };
 use crate::zobrist::ZobristKey;
 
 use super::pieces::Pieces;
-use super::Position;
+use super::{castling_index, Position};
 
 pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
@@ -98,22 +97,46 @@ impl Position {
             _ => return Err(ParseFenError::InvalidColor(to_move_str.to_string())),
         };
 
-        // Castling rights
+        // Castling rights, either as KQkq, meaning the outermost rook on that
+        // side (X-FEN), or as the files of the rooks (Shredder-FEN)
         let castling_str = fields[2];
         let mut castling = CastlingRights::new(false, false, false, false);
+        let mut castling_rooks = [Square::H1, Square::A1, Square::H8, Square::A8];
         if castling_str != "-" {
+            let invalid = || ParseFenError::InvalidCastlingRights(castling_str.to_string());
             for right in castling_str.chars() {
-                match right {
-                    'K' => castling.set(Color::White, 0b01),
-                    'Q' => castling.set(Color::White, 0b10),
-                    'k' => castling.set(Color::Black, 0b01),
-                    'q' => castling.set(Color::Black, 0b10),
-                    _ => {
-                        return Err(ParseFenError::InvalidCastlingRights(
-                            castling_str.to_string(),
-                        ))
-                    }
+                let (color, back_rank) = if right.is_ascii_uppercase() {
+                    (Color::White, Rank::First)
+                } else {
+                    (Color::Black, Rank::Eighth)
+                };
+                let king_sq = pieces.king_sq_for(color);
+                if king_sq.rank() != back_rank {
+                    return Err(invalid());
+                }
+
+                let rooks =
+                    pieces.get_bb(Piece(PieceKind::Rook, color)) & Bitboard::from(back_rank);
+                let rook_sq = match right.to_ascii_lowercase() {
+                    'k' => rooks
+                        .into_iter()
+                        .filter(|&sq| sq as u8 > king_sq as u8)
+                        .last(),
+                    'q' => rooks.into_iter().find(|&sq| (sq as u8) < king_sq as u8),
+                    file @ 'a'..='h' => File::from_repr(file as u8 - b'a')
+                        .map(|file| Square::from_rank_file(back_rank, file))
+                        .filter(|&sq| rooks.contains(sq)),
+                    _ => None,
                 }
+                .ok_or_else(invalid)?;
+
+                let side = if rook_sq as u8 > king_sq as u8 {
+                    Side::KingSide
+                } else {
+                    Side::QueenSide
+                };
+                castling.set(color, side as u8);
+                castling_rooks[castling_index(color, side)] = rook_sq;
             }
             if u8::from(castling).count_ones() != castling_str.len() as u32 {
                 return Err(ParseFenError::InvalidCastlingRights(
@@ -148,10 +171,6 @@ impl Position {
         zobrist ^= castling.key(tables);
         zobrist ^= en_passant_sq.key(tables);
 
-        // Repetition table
-        let mut repetitions = IntMap::new();
-        repetitions.insert(zobrist, 1);
-
         // Evaluation of the position
         let mut eval_early_game = 0;
         let mut eval_endgame = 0;
@@ -166,15 +185,130 @@ impl Position {
             pieces,
             to_move,
             castling,
+            castling_rooks,
             en_passant_sq,
             ply_clock,
             move_number,
-            repetitions,
             history: Vec::new(),
             zobrist,
             tables,
             eval_early_game,
             eval_endgame,
+            nnue: None,
         })
     }
+
+    /// Returns the FEN string of the position.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in Rank::iter().rev() {
+            let mut empty = 0;
+            for file in File::iter() {
+                match self.pieces.get(Square::from_rank_file(rank, file)) {
+                    Some(pce) => {
+                        if empty > 0 {
+                            fen += &empty.to_string();
+                            empty = 0;
+                        }
+                        fen += &pce.to_string();
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen += &empty.to_string();
+            }
+            if rank != Rank::First {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.to_move {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        // Rooks that are not the outermost on their side are given by file
+        let castling: String = [
+            (Color::White, Side::KingSide),
+            (Color::White, Side::QueenSide),
+            (Color::Black, Side::KingSide),
+            (Color::Black, Side::QueenSide),
+        ]
+        .into_iter()
+        .filter(|&(color, side)| self.castling.get(color, side))
+        .map(|(color, side)| {
+            let rook_sq = self.castling_rook(color, side);
+            let rooks =
+                self.pieces.get_bb(Piece(PieceKind::Rook, color)) & Bitboard::from(rook_sq.rank());
+            let is_outermost = match side {
+                Side::KingSide => rooks.into_iter().last() == Some(rook_sq),
+                Side::QueenSide => rooks.into_iter().next() == Some(rook_sq),
+            };
+            let ch = match (is_outermost, side) {
+                (true, Side::KingSide) => 'k',
+                (true, Side::QueenSide) => 'q',
+                (false, _) => (b'a' + rook_sq.file() as u8) as char,
+            };
+            match color {
+                Color::White => ch.to_ascii_uppercase(),
+                Color::Black => ch,
+            }
+        })
+        .collect();
+        if castling.is_empty() {
+            fen.push('-');
+        } else {
+            fen += &castling;
+        }
+
+        match self.en_passant_sq {
+            Some(sq) => fen += &format!(" {sq}"),
+            None => fen += " -",
+        }
+        fen += &format!(" {} {}", self.ply_clock, self.move_number);
+        fen
+    }
+
+    /// Returns the position with the board mirrored vertically and the colors
+    /// swapped, which should be equivalent for the side to move. The history
+    /// of the position is not kept.
+    pub fn flipped(&self) -> Self {
+        let fen = self.to_fen();
+        let fields: Vec<_> = fen.split(' ').collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|ch| match ch.is_ascii_uppercase() {
+                    true => ch.to_ascii_lowercase(),
+                    false => ch.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+
+        let board = swap_case(&fields[0].split('/').rev().collect::<Vec<_>>().join("/"));
+        let to_move = match fields[1] {
+            "w" => "b",
+            _ => "w",
+        };
+        // The white castling rights should come first
+        let castling = match fields[2] {
+            "-" => "-".to_string(),
+            castling => {
+                let (white, black): (String, String) = swap_case(castling)
+                    .chars()
+                    .partition(char::is_ascii_uppercase);
+                white + &black
+            }
+        };
+        let en_passant = match self.en_passant_sq {
+            Some(sq) => sq.flip_rank().to_string(),
+            None => "-".to_string(),
+        };
+
+        let fen = format!(
+            "{board} {to_move} {castling} {en_passant} {} {}",
+            fields[4], fields[5]
+        );
+        Self::from_fen(&fen).expect("flipped FEN should be valid")
+    }
 }
@@ -1,20 +1,20 @@
 use std::convert::TryFrom;
 use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
-use intmap::IntMap;
-
-use crate::eval::{piece_value_early, piece_value_endgame};
+use crate::eval::nnue::{Accumulator, Network};
+use crate::eval::{endgame, piece_value_early, piece_value_endgame};
 use crate::tables::Tables;
 use crate::types::{
-    BoardVector, CastlingRights, Color, Move, MoveKind, Piece, PieceKind, Rank, Side, Square,
+    Bitboard, BoardVector, CastlingRights, Color, Move, MoveKind, Piece, PieceKind, Rank, Side,
+    Square,
 };
 use crate::zobrist::ZobristKey;
 
-use pieces::Pieces;
-
 mod fen;
 pub use fen::*;
 mod pieces;
+pub use pieces::Pieces;
 #[cfg(test)]
 mod tests;
 
@@ -24,15 +24,21 @@ pub struct Position {
     pub pieces: Pieces,
     pub to_move: Color,
     pub castling: CastlingRights,
+    /// The starting squares of the castling rooks, indexed by
+    /// [`castling_index`].
+    castling_rooks: [Square; 4],
     pub en_passant_sq: Option<Square>,
     ply_clock: u8,
     pub move_number: u32,
-    repetitions: IntMap<u8>,
+    /// The moves made since the position was set up, along with the zobrist
+    /// keys of the positions they were made in, which are scanned for
+    /// repetitions.
     history: Vec<Unmake>,
     pub zobrist: u64,
     tables: &'static Tables,
     pub eval_early_game: i16,
     pub eval_endgame: i16,
+    nnue: Option<Accumulator>,
 }
 
 impl Position {
@@ -58,6 +64,7 @@ impl Position {
             castling: self.castling,
             en_passant_sq: self.en_passant_sq,
             ply_clock: self.ply_clock,
+            zobrist: self.zobrist,
         };
 
         self.toggle_zobrist(self.en_passant_sq);
@@ -98,38 +105,28 @@ impl Position {
                             self.en_passant_sq = Some(from + up);
                             self.toggle_zobrist(Some(from));
                         }
-                    } else {
-                        let (king_sq, king_rook_sq, queen_rook_sq) = match self.to_move {
-                            Color::White => (Square::E1, Square::H1, Square::A1),
-                            Color::Black => (Square::E8, Square::H8, Square::A8),
-                        };
-
-                        if from == king_rook_sq {
-                            self.remove_castling_rights(self.to_move, 0b01);
-                        } else if from == queen_rook_sq {
-                            self.remove_castling_rights(self.to_move, 0b10);
-                        } else if from == king_sq {
-                            self.remove_castling_rights(self.to_move, 0b11);
-                        }
+                    } else if pce.kind() == PieceKind::King {
+                        self.remove_castling_rights(self.to_move, 0b11);
+                    } else if from == self.castling_rook(self.to_move, Side::KingSide) {
+                        self.remove_castling_rights(self.to_move, 0b01);
+                    } else if from == self.castling_rook(self.to_move, Side::QueenSide) {
+                        self.remove_castling_rights(self.to_move, 0b10);
                     }
                 }
                 MoveKind::Castling => {
-                    let side = match to {
-                        Square::G1 | Square::G8 => Side::KingSide,
-                        _ => {
-                            debug_assert!(to == Square::C1 || to == Square::C8);
-                            Side::QueenSide
-                        }
-                    };
-                    let rook_sq = Square::rook_starting(self.to_move, side);
-                    let castling_rook_sq = Square::rook_castling_dest(self.to_move, side);
+                    // The king captures its own rook, so `to` is the square of the rook
+                    let side = mv.castling_side();
+                    let king_dest = Square::king_castling_dest(self.to_move, side);
+                    let rook_dest = Square::rook_castling_dest(self.to_move, side);
 
+                    // In Chess960, the destinations may be the starting squares
+                    // of the king and rook, so both are removed first
                     let rook_pce = Piece(PieceKind::Rook, self.to_move);
                     let king_pce = Piece(PieceKind::King, self.to_move);
                     self.unset_sq(from, king_pce);
-                    self.unset_sq(rook_sq, rook_pce);
-                    self.set_sq(to, king_pce);
-                    self.set_sq(castling_rook_sq, rook_pce);
+                    self.unset_sq(to, rook_pce);
+                    self.set_sq(king_dest, king_pce);
+                    self.set_sq(rook_dest, rook_pce);
 
                     self.remove_castling_rights(self.to_move, 0b11);
 
@@ -176,21 +173,14 @@ impl Position {
         }
         self.toggle_zobrist(Color::White);
 
-        match self.repetitions.get_mut(self.zobrist) {
-            Some(count) => *count += 1,
-            None => {
-                self.repetitions.insert(self.zobrist, 1);
-            }
-        }
-
         self.history.push(unmake);
     }
 
     fn remove_castling_on_rook_capture(&mut self, to: Square) {
         let opp = !self.to_move;
-        if to == Square::rook_starting(opp, Side::KingSide) {
+        if to == self.castling_rook(opp, Side::KingSide) {
             self.remove_castling_rights(opp, 0b01);
-        } else if to == Square::rook_starting(opp, Side::QueenSide) {
+        } else if to == self.castling_rook(opp, Side::QueenSide) {
             self.remove_castling_rights(opp, 0b10);
         }
     }
@@ -205,14 +195,6 @@ impl Position {
             .history
             .pop()
             .expect("there should be a move to unmake");
-        let count = self
-            .repetitions
-            .get_mut(self.zobrist)
-            .expect("the position we are unmaking is in the repetitions table");
-        *count -= 1;
-        if *count == 0 {
-            self.repetitions.remove(self.zobrist);
-        }
 
         self.toggle_zobrist(self.en_passant_sq);
         self.en_passant_sq = unmake.en_passant_sq;
@@ -250,22 +232,16 @@ impl Position {
                 self.set_castling(unmake.castling);
             }
             MoveKind::Castling => {
-                let side = match to {
-                    Square::G1 | Square::G8 => Side::KingSide,
-                    _ => {
-                        debug_assert!(to == Square::C1 || to == Square::C8);
-                        Side::QueenSide
-                    }
-                };
-                let rook_sq = Square::rook_starting(self.to_move, side);
-                let castling_rook_sq = Square::rook_castling_dest(self.to_move, side);
+                let side = unmake.mv.castling_side();
+                let king_dest = Square::king_castling_dest(self.to_move, side);
+                let rook_dest = Square::rook_castling_dest(self.to_move, side);
 
                 let rook_pce = Piece(PieceKind::Rook, self.to_move);
                 let king_pce = Piece(PieceKind::King, self.to_move);
+                self.unset_sq(king_dest, king_pce);
+                self.unset_sq(rook_dest, rook_pce);
                 self.set_sq(from, king_pce);
-                self.set_sq(rook_sq, rook_pce);
-                self.unset_sq(to, king_pce);
-                self.unset_sq(castling_rook_sq, rook_pce);
+                self.set_sq(to, rook_pce);
 
                 self.set_castling(unmake.castling);
             }
@@ -302,6 +278,9 @@ impl Position {
         self.toggle_zobrist((pce, sq));
         self.eval_early_game += piece_value_early(pce, sq);
         self.eval_endgame += piece_value_endgame(pce, sq);
+        if let Some(acc) = &mut self.nnue {
+            acc.add(pce, sq);
+        }
     }
 
     fn unset_sq(&mut self, sq: Square, pce: Piece) {
@@ -309,6 +288,9 @@ impl Position {
         self.toggle_zobrist((pce, sq));
         self.eval_early_game -= piece_value_early(pce, sq);
         self.eval_endgame -= piece_value_endgame(pce, sq);
+        if let Some(acc) = &mut self.nnue {
+            acc.remove(pce, sq);
+        }
     }
 
     fn set_castling(&mut self, castling: CastlingRights) {
@@ -327,20 +309,113 @@ impl Position {
         self.zobrist ^= key.key(self.tables);
     }
 
+    /// Attaches an NNUE accumulator for `network` to the position, which is
+    /// then kept up to date as moves are made and unmade.
+    pub fn attach_network(&mut self, network: Arc<Network>) {
+        if self.nnue.as_ref().is_some_and(|acc| acc.uses(&network)) {
+            return;
+        }
+        self.nnue = Some(Accumulator::new(network, &self.pieces));
+    }
+
+    /// Returns the NNUE accumulator of the position, if a network is attached.
+    #[inline]
+    pub fn accumulator(&self) -> Option<&Accumulator> {
+        self.nnue.as_ref()
+    }
+
+    /// Returns the starting square of the rook that `color` castles with on
+    /// `side`. This is only meaningful if `color` has the castling right.
+    #[inline]
+    pub fn castling_rook(&self, color: Color, side: Side) -> Square {
+        self.castling_rooks[castling_index(color, side)]
+    }
+
+    /// Returns the number of plies since the last capture or pawn move.
+    #[inline]
+    pub fn ply_clock(&self) -> u8 {
+        self.ply_clock
+    }
+
     /// Returns the last move made in the position.
     #[inline]
     pub fn last_move(&self) -> Option<Move> {
         self.history.last().map(|um| um.mv)
     }
 
-    /// Returns whether the position is a draw by threefold repetition or the
-    /// fifty-move rule.
+    /// Returns the number of moves made since the position was set up.
+    #[inline]
+    pub(crate) fn moves_made(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns whether the position is a draw by threefold repetition, the
+    /// fifty-move rule or insufficient material.
     #[inline]
     pub fn is_rule_draw(&self) -> bool {
-        debug_assert!(self.repetitions.contains_key(self.zobrist));
-        let count = *self.repetitions.get(self.zobrist).unwrap_or(&0);
-        let threefold = count >= 3;
-        threefold || self.ply_clock >= 100
+        let threefold = self.count_repetitions(self.zobrist) >= 2;
+        threefold || self.ply_clock >= 100 || endgame::is_insufficient_material(&self.pieces)
+    }
+
+    /// Returns whether the position occurred after the position `plies` plies
+    /// back, such as the root of a search `plies` plies deep. Positions before
+    /// the last irreversible move or null move are not considered, as they
+    /// can't be repeated.
+    pub fn repeats_within(&self, plies: usize) -> bool {
+        // The same side must be to move, and it takes at least four plies to
+        // get back to the same position
+        self.reversible_keys()
+            .take(plies.saturating_sub(1))
+            .skip(3)
+            .step_by(2)
+            .any(|key| key == self.zobrist)
+    }
+
+    /// Returns whether the side to move has a reversible move to a position
+    /// that occurred after the position `plies` plies back, or to an earlier
+    /// position that has then occurred three times. Like [`Position::repeats_within`],
+    /// positions before the last irreversible move or null move are not
+    /// considered.
+    pub fn has_upcoming_repetition(&self, plies: usize) -> bool {
+        let occupied = self.pieces.occupied();
+        // Only positions an odd number of plies back have the other side to
+        // move, as they would after the move
+        self.reversible_keys()
+            .enumerate()
+            .skip(2)
+            .step_by(2)
+            .any(|(i, key)| {
+                let Some((sq1, sq2)) = self.tables.cuckoo.get(self.zobrist ^ key) else {
+                    return false;
+                };
+                // The piece is on one of the squares, and must be able to
+                // move to the other
+                let sq = if occupied.contains(sq1) { sq1 } else { sq2 };
+                let path = self.tables.ray_to[sq1][sq2] - Bitboard::from(sq2);
+                (path & occupied).is_empty()
+                    && self
+                        .pieces
+                        .get(sq)
+                        .is_some_and(|pce| pce.color() == self.to_move)
+                    && (i + 1 < plies || self.count_repetitions(key) >= 2)
+            })
+    }
+
+    /// Returns the zobrist keys of the positions before the current one, most
+    /// recent first, back to the last irreversible move or null move.
+    fn reversible_keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.history
+            .iter()
+            .rev()
+            .take(self.ply_clock as usize)
+            .take_while(|um| !um.mv.is_null())
+            .map(|um| um.zobrist)
+    }
+
+    /// Returns the number of earlier positions since the last irreversible
+    /// move or null move with zobrist key `key`.
+    fn count_repetitions(&self, key: u64) -> usize {
+        self.reversible_keys().filter(|&k| k == key).count()
     }
 
     /// Returns a heuristic of whether a null move can be made
@@ -384,15 +459,16 @@ impl PartialEq for Position {
         self.zobrist == other.zobrist
             && self.ply_clock == other.ply_clock
             && self.move_number == other.move_number
-            && self.repetitions == other.repetitions
+            // The history includes the zobrist key of every earlier position,
+            // so positions with the same history have the same repetitions
             && self.history == other.history
     }
 }
 
 impl Debug for Position {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        writeln!(f, "{}to_move: {:?}\ncastling: {:?}\nen_passant_sq: {:?}\nply_clock: {:?}\nmove_number: {:?}\nrepetitions: {:?}\nhistory: {:?}",
-               self.pieces, self.to_move, self.castling, self.en_passant_sq, self.ply_clock, self.move_number, self.repetitions, self.history)
+        writeln!(f, "{}to_move: {:?}\ncastling: {:?}\nen_passant_sq: {:?}\nply_clock: {:?}\nmove_number: {:?}\nhistory: {:?}",
+               self.pieces, self.to_move, self.castling, self.en_passant_sq, self.ply_clock, self.move_number, self.history)
     }
 }
 
@@ -412,6 +488,13 @@ impl Display for Position {
     }
 }
 
+/// Returns the index of the castling right of `color` on `side`, which is also
+/// the index of its bit in [`CastlingRights`].
+#[inline]
+fn castling_index(color: Color, side: Side) -> usize {
+    2 * color as usize + (side as usize >> 1)
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 struct Unmake {
     mv: Move,
@@ -419,4 +502,6 @@ struct Unmake {
     castling: CastlingRights,
     en_passant_sq: Option<Square>,
     ply_clock: u8,
+    /// The zobrist key of the position before the move.
+    zobrist: u64,
 }
@@ -1,5 +1,5 @@
 use crate::mv;
-use crate::types::{Color, Piece, PieceKind, Side, Square};
+use crate::types::{Color, Move, Piece, PieceKind, Side, Square};
 
 use super::Position;
 use super::STARTING_FEN;
@@ -51,6 +51,33 @@ fn castling_rights_parsed_correctly_from_fen() {
     assert!(!position.castling.get(Black, QueenSide));
 }
 
+#[test]
+fn chess960_castling_rights_parsed_correctly_from_fen() {
+    use Color::*;
+    use Side::*;
+
+    let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+    let position = Position::from_fen(fen).unwrap();
+    assert_eq!(position.castling_rook(White, KingSide), Square::H1);
+    assert_eq!(position.castling_rook(White, QueenSide), Square::F1);
+    assert_eq!(position.castling_rook(Black, KingSide), Square::H8);
+    assert_eq!(position.castling_rook(Black, QueenSide), Square::F8);
+    assert_eq!(
+        position.to_fen(),
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+    );
+
+    // Rooks that are not the outermost need to be given by file
+    let fen = "4k1rr/8/8/8/8/8/8/4K1RR w Gg - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    assert_eq!(position.castling_rook(White, KingSide), Square::G1);
+    assert_eq!(position.castling_rook(Black, KingSide), Square::G8);
+    assert_eq!(position.to_fen(), fen);
+
+    assert!(Position::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1").is_err());
+    assert!(Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w B - 0 1").is_err());
+}
+
 #[test]
 fn en_passant_parsed_correctly_from_fen() {
     let position = Position::from_fen(STARTING_FEN).unwrap();
@@ -164,6 +191,22 @@ fn castling_moves_unmade_correctly() {
     assert!(position.matches_fen(fen).unwrap());
 }
 
+#[test]
+fn chess960_castling_moves_made_and_unmade_correctly() {
+    let fen = "1r3k2/8/8/8/8/8/8/1R3K2 w Bb - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+
+    position.make_move(Move::new_castling(Square::F1, Square::B1));
+    position.make_move(Move::new_castling(Square::F8, Square::B8));
+
+    let res_fen = "2kr4/8/8/8/8/8/8/2KR4 w - - 2 2";
+    assert!(position.matches_fen(res_fen).unwrap());
+
+    position.unmake_move();
+    position.unmake_move();
+    assert!(position.matches_fen(fen).unwrap());
+}
+
 #[test]
 fn promotion_moves_unmade_correctly() {
     let fen = "rnbqkbnr/2ppppPP/8/8/8/8/PppPPP2/RNBQKBNR w KQkq - 0 9";
@@ -206,3 +249,138 @@ fn null_moves_made_and_unmade_correctly() {
 
     assert!(position.matches_fen(fen).unwrap());
 }
+
+#[test]
+fn to_fen_round_trips() {
+    let fens = [
+        STARTING_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3",
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 17 42",
+    ];
+    for fen in fens {
+        assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+    }
+}
+
+#[test]
+fn flipped_mirrors_position() {
+    let position =
+        Position::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 3").unwrap();
+    let flipped = position.flipped();
+    assert_eq!(
+        flipped.to_fen(),
+        "rnbqkbnr/pppp1ppp/8/8/3PpP2/8/PPP1P1PP/RNBQKBNR b Qk f3 0 3"
+    );
+    assert_eq!(flipped.flipped().to_fen(), position.to_fen());
+
+    let fen = "rr2k3/8/8/8/8/8/8/1R2K2R w Bb - 0 1";
+    let flipped = Position::from_fen(fen).unwrap().flipped();
+    assert_eq!(flipped.to_fen(), "1r2k2r/8/8/8/8/8/8/RR2K3 b Bq - 0 1");
+}
+
+#[test]
+fn repetitions_found_since_last_irreversible_move() {
+    let mut position = Position::new();
+    for mv in [mv!(G1 -> F3), mv!(G8 -> F6), mv!(F3 -> G1), mv!(F6 -> G8)] {
+        assert!(!position.repeats_within(usize::MAX));
+        position.make_move(mv);
+    }
+    // Only positions after the one `plies` plies back count, so a repetition
+    // of the root of a search isn't a draw until it occurs again
+    assert!(position.repeats_within(5));
+    assert!(!position.repeats_within(4));
+    assert!(!position.is_rule_draw());
+
+    // The position right after a pawn move can be repeated
+    position.make_move(mv!(E2 -> E3));
+    for mv in [mv!(G8 -> F6), mv!(G1 -> F3), mv!(F6 -> G8), mv!(F3 -> G1)] {
+        assert!(!position.repeats_within(usize::MAX));
+        position.make_move(mv);
+    }
+    assert!(position.repeats_within(usize::MAX));
+
+    // But not across null moves
+    position.make_move(mv!(G8 -> F6));
+    position.make_move(mv!());
+    position.make_move(mv!(F6 -> G8));
+    position.make_move(mv!());
+    assert!(!position.repeats_within(usize::MAX));
+}
+
+#[test]
+fn upcoming_repetitions_found_through_reversible_moves() {
+    let mut position = Position::new();
+    position.make_move(mv!(G1 -> F3));
+    position.make_move(mv!(G8 -> F6));
+    position.make_move(mv!(F3 -> G1));
+    // Black can go back to the starting position, which is only repeated if
+    // the search started before it
+    assert!(position.has_upcoming_repetition(usize::MAX));
+    assert!(position.has_upcoming_repetition(4));
+    assert!(!position.has_upcoming_repetition(3));
+    position.make_move(mv!(B8 -> C6));
+    assert!(!position.has_upcoming_repetition(usize::MAX));
+
+    // The rook can't get back to A1 past the bishop
+    for (first_rank, repeats) in [("3B4", false), ("7B", true)] {
+        let fen = format!("7k/8/8/8/8/7K/R7/{first_rank} w - - 0 1");
+        let mut position = Position::from_fen(&fen).unwrap();
+        for mv in [
+            mv!(A2 -> A1),
+            mv!(H8 -> G8),
+            mv!(A1 -> A2),
+            mv!(G8 -> F8),
+            mv!(A2 -> F2),
+            mv!(F8 -> G8),
+            mv!(F2 -> F1),
+            mv!(G8 -> H8),
+        ] {
+            position.make_move(mv);
+        }
+        assert_eq!(position.has_upcoming_repetition(usize::MAX), repeats);
+    }
+}
+
+#[test]
+fn threefold_repetition_is_rule_draw() {
+    let mut position = Position::new();
+    let shuffle = [mv!(G1 -> F3), mv!(G8 -> F6), mv!(F3 -> G1), mv!(F6 -> G8)];
+    for mv in shuffle.into_iter().chain(shuffle) {
+        assert!(!position.is_rule_draw());
+        position.make_move(mv);
+    }
+    assert!(position.is_rule_draw());
+
+    position.unmake_move();
+    position.make_move(mv!(F6 -> G8));
+    assert!(position.is_rule_draw());
+    position.unmake_move();
+    position.unmake_move();
+    assert!(!position.is_rule_draw());
+}
+
+#[test]
+fn positions_with_different_histories_are_not_equal() {
+    let mut position = Position::new();
+    let mut other = Position::new();
+    for mv in [mv!(G1 -> F3), mv!(G8 -> F6), mv!(F3 -> G1), mv!(F6 -> G8)] {
+        position.make_move(mv);
+    }
+    assert_ne!(position, other);
+
+    for mv in [mv!(B1 -> C3), mv!(B8 -> C6), mv!(C3 -> B1), mv!(C6 -> B8)] {
+        other.make_move(mv);
+    }
+    assert!(position.matches_fen(&other.to_fen()).unwrap());
+    assert_ne!(position, other);
+
+    for _ in 0..4 {
+        other.unmake_move();
+    }
+    for mv in [mv!(G1 -> F3), mv!(G8 -> F6), mv!(F3 -> G1), mv!(F6 -> G8)] {
+        other.make_move(mv);
+    }
+    assert_eq!(position, other);
+}
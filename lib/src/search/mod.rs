@@ -13,10 +13,18 @@ use transposition_table::Bound;
 
 use crate::collections::MoveList;
 use crate::eval::{piece_value, Eval, StandardEval};
+use crate::syzygy::{Tablebase, Wdl};
 use crate::types::{value, IllegalMoveError, PseudoMove, Value};
 use crate::MoveGen;
 use crate::{types::Move, Position};
 
+mod move_picker;
+use move_picker::{last_piece_to, MovePicker, OrderingTables};
+mod skill;
+use skill::SKILL_CANDIDATES;
+pub use skill::{Skill, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO};
+mod stack;
+use stack::SearchStack;
 mod thread;
 pub use thread::{info_channel, InfoReceiver, InfoSender, SearchInfo, ThreadPool, DEFAULT_THREADS};
 #[cfg(test)]
@@ -43,7 +51,12 @@ pub struct SearchJob<E = StandardEval, O = EmptyObserver> {
     move_gen: MoveGen,
     eval: E,
     observer: O,
+    tablebase: Option<Arc<Tablebase>>,
+    skill: Option<Skill>,
+    seed: Option<u64>,
     worker_id: usize,
+    /// Whether to use internal iterative reductions.
+    iir: bool,
 }
 
 impl<E: Eval> SearchJob<E> {
@@ -56,6 +69,10 @@ impl<E: Eval> SearchJob<E> {
             move_gen: MoveGen::init(),
             eval,
             observer: EmptyObserver,
+            tablebase: None,
+            skill: None,
+            seed: None,
+            iir: true,
         }
     }
 }
@@ -71,6 +88,7 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
         search_start: Instant,
         kill_switch: Arc<AtomicBool>,
         t_table: Arc<TranspositionTable>,
+        tables: &mut OrderingTables,
     ) -> SearchResult {
         let depth = self.limits.depth.expect("depth should be set");
         assert!(depth > 0, "search depth should be positive");
@@ -78,11 +96,16 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
             stats: SearchStats {
                 sel_depth: depth,
                 nodes: 0,
+                tb_hits: 0,
             },
             search_start,
             kill_switch,
             t_table,
             start_depth: depth,
+            root_ply: self.position.moves_made(),
+            stack: SearchStack::default(),
+            tables,
+            skill_candidates: Vec::new(),
         };
 
         self.observer
@@ -92,7 +115,11 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
             self.observer
                 .on_node_exit::<Root>(self.worker_id, None, ret_kind, Some(score));
             let pv = self.primary_variation(depth, &params.t_table);
-            let result = SearchEvaluation { score, pv };
+            let result = SearchEvaluation {
+                score,
+                pv,
+                skill_candidates: params.skill_candidates,
+            };
             SearchResult {
                 evaluation: Some(result),
                 stats: params.stats,
@@ -115,40 +142,54 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
         &mut self,
         depth: i8,
         mut alpha: Value,
-        beta: Value,
+        mut beta: Value,
         params: &mut SearchParams,
     ) -> Option<(Value, O::ReturnKind)> {
         if self.should_stop(params) {
             return None;
         }
 
-        let (mut moves, check) = if N::IS_ROOT {
-            if let Some(moves) = self.limits.moves.take() {
-                (moves, self.move_gen.is_check(&self.position))
-            } else {
-                self.gen_moves_and_check()
-            }
-        } else {
-            self.gen_moves_and_check()
-        };
+        let ply = self.position.moves_made() - params.root_ply;
+        params.stats.reach_ply(ply);
+        // The move left out by a singular extension search of this position
+        let excluded = params.stack[ply].excluded_move;
+        let check = self.move_gen.is_check(&self.position);
+        params.stack[ply].in_check = check;
 
-        if moves.is_empty() {
-            // Checkmate
-            return if check {
-                Some((Value::neg_mate_in_ply(0), ReturnKind::Checkmate.into()))
-            // Stalemate
-            } else {
-                Some((Value::centipawn(0), ReturnKind::Stalemate.into()))
-            };
+        // Draw by threefold repetition or fifty-move rule, unless checkmated
+        if self.position.is_rule_draw()
+            && !(check && self.move_gen.gen_all_moves(&self.position).is_empty())
+        {
+            return Some((Value::centipawn(0), ReturnKind::RuleDraw.into()));
+        }
+        // A position repeated since the root can be repeated again, so it is
+        // treated as a draw without waiting for the third occurrence
+        if self.position.repeats_within(ply) {
+            return Some((Value::centipawn(0), ReturnKind::Repetition.into()));
+        }
+        // If a move can repeat a position, the draw score is a lower bound
+        if !N::IS_ROOT && alpha < Value::centipawn(0) && self.position.has_upcoming_repetition(ply)
+        {
+            alpha = Value::centipawn(0);
+            if alpha >= beta {
+                return Some((alpha, ReturnKind::Repetition.into()));
+            }
         }
 
-        // Draw by threefold repetition or fifty-move rule
-        if self.position.is_rule_draw() {
-            return Some((Value::centipawn(0), ReturnKind::RuleDraw.into()));
+        // Mate distance pruning. Scores are relative to the current node, so
+        // being mated here is the worst possible outcome and mating with the
+        // next move the best. If a shorter mate has already been found closer
+        // to the root, the window shifted down to this node is empty
+        if !N::IS_ROOT {
+            alpha = alpha.max(Value::neg_mate_in_ply(0));
+            beta = beta.min(Value::mate_in_ply(1));
+            if alpha >= beta {
+                return Some((alpha, ReturnKind::MateDistancePruning.into()));
+            }
         }
 
-        let mut best_move = None;
-        if let Some(entry) = params.t_table.get(&self.position) {
+        let tt_entry = params.t_table.get_excluding(&self.position, excluded);
+        if let Some(entry) = tt_entry {
             // Don't use ttable move in PV nodes, as e.g. 50 move rule might
             // not be detected
             if !N::IS_PV && entry.depth >= depth {
@@ -165,36 +206,104 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
                     _ => {}
                 }
             }
-
-            best_move = Some(entry.best_move);
+        }
+        let tt_move = tt_entry.map(|entry| entry.best_move);
+
+        // Tablebase probe. The tables assume that the fifty-move counter is
+        // zero, so only probe right after captures and pawn moves
+        if !N::IS_ROOT && excluded.is_none() && self.position.ply_clock() == 0 {
+            if let Some(wdl) = self.probe_wdl() {
+                params.stats.tb_hits += 1;
+                let ply = ply as i16;
+                let (score, bound) = match wdl {
+                    Wdl::Win => (TB_WIN - Value::centipawn(ply), Bound::Lower),
+                    Wdl::Loss => (-TB_WIN + Value::centipawn(ply), Bound::Upper),
+                    // Cursed wins and blessed losses are draws
+                    wdl => (Value::centipawn(2 * wdl as i16), Bound::Exact),
+                };
+                let cutoff = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    return Some((score, ReturnKind::Tablebase.into()));
+                }
+            }
         }
 
+        let can_extend = ply < MAX_EXTENSION_RATIO * params.start_depth as usize;
+
+        // Check extension. Positions in check are never left to quiescence
+        // search, which only searches captures. The singular verification
+        // search was already extended by the search of the same position
+        let depth = if check && can_extend && excluded.is_none() {
+            self.on_extension(ply, None);
+            depth + 1
+        } else {
+            depth
+        };
         if !check && depth <= 0 {
-            let score = self.quiesce(alpha, beta, params.start_depth - depth, params)?;
+            let score = self.quiesce(alpha, beta, ply, params)?;
             return Some((score, ReturnKind::Quiesce.into()));
         }
 
+        // Internal iterative reduction. Without a TT move, the first move
+        // searched is often bad, so the node is searched to a lower depth,
+        // which is cheaper and leaves a TT move for the next iteration
+        let depth = if self.iir && !N::IS_ROOT && tt_move.is_none() && depth >= MIN_IIR_DEPTH {
+            depth - 1
+        } else {
+            depth
+        };
+
         let static_eval = self.static_eval();
+        params.stack[ply].static_eval = (!check).then_some(static_eval);
+        let improving = params.stack.improving(ply);
 
-        // Reverse futility pruning
+        // Reverse futility pruning. The margin is smaller when the position
+        // is improving, as the static evaluation is then less likely to drop
+        // below beta
         const MAX_RFP_DEPTH: i8 = 3;
         const RFP_MARGIN: Value = Value::centipawn(150);
         if !check
             && depth <= MAX_RFP_DEPTH
             && !N::IS_PV
-            && static_eval >= beta + RFP_MARGIN * depth as i16
+            && excluded.is_none()
+            && static_eval >= beta + RFP_MARGIN * (depth - improving as i8) as i16
         {
             return Some((static_eval, ReturnKind::ReverseFutilityPruning.into()));
         }
 
+        // Razoring. When the static evaluation is far below alpha, quiet moves
+        // are unlikely to raise alpha, so the node is resolved by quiescence
+        // search if it also fails low. Quiescence search doesn't see mates, so
+        // the margin grows quickly with depth
+        const MAX_RAZOR_DEPTH: i8 = 2;
+        const RAZOR_BASE_MARGIN: Value = Value::centipawn(300);
+        const RAZOR_MARGIN: Value = Value::centipawn(250);
+        if !check
+            && depth <= MAX_RAZOR_DEPTH
+            && !N::IS_PV
+            && excluded.is_none()
+            && alpha > static_eval + RAZOR_BASE_MARGIN + RAZOR_MARGIN * (depth as i16).pow(2)
+        {
+            let score = self.quiesce(alpha, beta, ply, params)?;
+            if score <= alpha {
+                return Some((score, ReturnKind::Razoring.into()));
+            }
+        }
+
         // Null move pruning
         const NULL_MOVE_DEPTH: i8 = 3;
         if !check
             && depth >= NULL_MOVE_DEPTH
             && !N::IS_PV
+            && excluded.is_none()
             && static_eval >= beta
             && self.position.null_move_heuristic()
         {
+            params.stack[ply].current_move = Some(Move::NULL);
             self.position.make_move(Move::NULL);
             params.stats.nodes += 1;
             // TODO: dec mate or not?
@@ -211,82 +320,241 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
             }
         }
 
-        self.reorder_moves(&mut moves, best_move);
+        // ProbCut. If a capture winning material beats beta by a margin in a
+        // shallow search, the full search is likely to beat beta as well
+        const MIN_PROBCUT_DEPTH: i8 = 5;
+        const PROBCUT_REDUCTION: i8 = 4;
+        const PROBCUT_MARGIN: Value = Value::centipawn(200);
+        let probcut_beta = beta + PROBCUT_MARGIN;
+        if !check
+            && depth >= MIN_PROBCUT_DEPTH
+            && !N::IS_PV
+            && excluded.is_none()
+            && beta > -TB_WIN
+            && beta < TB_WIN
+            // Unless a TT entry from a similar depth shows that it won't
+            && !tt_entry.is_some_and(|entry| {
+                entry.depth > depth - PROBCUT_REDUCTION && entry.score < probcut_beta
+            })
+        {
+            let mut captures = self.move_gen.gen_captures(&self.position);
+            self.reorder_moves(&mut captures, tt_move);
+            for mv in captures {
+                if self.move_gen.see(&self.position, mv) <= 0 {
+                    continue;
+                }
 
-        let original_alpha = alpha;
-        let mut best_move = *moves.first()?;
-        let mut best_score = value::NEG_INF;
+                params.stack[ply].current_move = Some(mv);
+                self.position.make_move(mv);
+                params.stats.nodes += 1;
+                let new_alpha = -probcut_beta;
+                let new_beta = -probcut_beta + Value::centipawn(1);
+                // Quiescence search is cheaper, and rules out most captures
+                let mut score = self
+                    .quiesce(new_alpha, new_beta, ply + 1, params)
+                    .map(|s| -s);
+                if score.is_some_and(|s| s >= probcut_beta) {
+                    self.on_node_enter::<NonPv>(new_alpha, new_beta, mv, false);
+                    let res =
+                        self.pvs::<NonPv>(depth - PROBCUT_REDUCTION, new_alpha, new_beta, params);
+                    self.on_node_exit::<NonPv>(mv, res.clone());
+                    score = res.map(|(s, _)| -s.inc_mate());
+                }
+                self.position.unmake_move();
+                let score = score?;
 
-        // Search first move with full window
-        self.position.make_move(best_move);
-        params.stats.nodes += 1;
-        self.on_node_enter::<N::FirstChild>(-beta.dec_mate(), -alpha.dec_mate(), best_move, false);
-        let res = self.pvs::<N::FirstChild>(depth - 1, -beta.dec_mate(), -alpha.dec_mate(), params);
-        self.on_node_exit::<N::FirstChild>(best_move, res.clone());
-        self.position.unmake_move();
-        let score = -res?.0.inc_mate();
-
-        if score >= beta {
-            let entry = Entry::new(score, best_move, Bound::Lower, depth);
-            params.t_table.insert(&self.position, entry);
-            return Some((score, ReturnKind::FailHigh(best_move).into()));
+                if score >= probcut_beta {
+                    let entry = Entry::new(score, mv, Bound::Lower, depth - PROBCUT_REDUCTION + 1);
+                    params.t_table.insert(&self.position, entry);
+                    return Some((score, ReturnKind::ProbCut(mv).into()));
+                }
+            }
         }
 
-        if score > best_score {
-            best_score = score;
-            if score > alpha {
-                alpha = score;
+        // Singular extension. If all other moves fail low against a bound
+        // somewhat below the score of the TT move, the TT move is extended.
+        // If they instead fail high against beta, several moves refute the
+        // previous move, and the node is cut off (multi-cut)
+        let mut singular_move = None;
+        if let Some(entry) = tt_entry.filter(|entry| {
+            !N::IS_ROOT
+                && can_extend
+                && excluded.is_none()
+                && depth >= MIN_SINGULAR_DEPTH
+                && entry.bound != Bound::Upper
+                && entry.depth >= depth - 3
+                && entry.score > -TB_WIN
+                && entry.score < TB_WIN
+        }) {
+            let singular_beta = entry.score - SINGULAR_MARGIN * depth as i16;
+            let singular_alpha = singular_beta - Value::centipawn(1);
+            params.stack[ply].excluded_move = Some(entry.best_move);
+            self.on_node_enter::<NonPv>(singular_alpha, singular_beta, entry.best_move, false);
+            let res = self.pvs::<NonPv>((depth - 1) / 2, singular_alpha, singular_beta, params);
+            self.on_node_exit::<NonPv>(entry.best_move, res.clone());
+            params.stack[ply].excluded_move = None;
+            let score = res?.0;
+
+            if score < singular_beta {
+                singular_move = Some(entry.best_move);
+            } else if singular_beta >= beta {
+                return Some((singular_beta, ReturnKind::MultiCut.into()));
             }
         }
 
-        // Search remaining moves with null window
-        for &mv in &moves[1..] {
+        let root_moves = if N::IS_ROOT {
+            self.limits.moves.take()
+        } else {
+            None
+        };
+        let prev = last_piece_to(&self.position);
+        let mut move_picker = match root_moves {
+            Some(mut moves) => {
+                self.reorder_moves(&mut moves, tt_move);
+                MovePicker::from_moves(moves)
+            }
+            None => MovePicker::new(tt_move, params.stack[ply].killers, prev, params.tables),
+        };
+
+        let original_alpha = alpha;
+        let mut best_move = Move::NULL;
+        let mut best_score = value::NEG_INF;
+        let mut move_count = 0;
+        let mut quiets_tried = MoveList::new();
+        // When playing with a skill, the root moves are searched against the
+        // score of the worst candidate move instead of alpha, so that the best
+        // few moves get exact scores to choose between
+        let find_candidates = N::IS_ROOT && self.skill.is_some();
+
+        while let Some(mv) = move_picker.next(&self.move_gen, &self.position, params.tables) {
+            if Some(mv) == excluded {
+                continue;
+            }
+            move_count += 1;
+
+            // Quiet moves are only pruned once a move has been found that
+            // doesn't lose, and never when they give check
+            let is_quiet = !mv.capture() && mv.promotion().is_none();
+            let can_prune = !N::IS_PV
+                && !check
+                && is_quiet
+                && best_score > -TB_WIN
+                && depth <= MAX_FUTILITY_DEPTH.max(MAX_LMP_DEPTH)
+                && !self.move_gen.gives_check(&self.position, mv);
+
+            // Late move pruning
+            if can_prune
+                && depth <= MAX_LMP_DEPTH
+                && move_count > late_move_threshold(depth, improving)
+            {
+                self.on_pruned_move(mv, alpha, alpha, ReturnKind::LateMovePruning);
+                continue;
+            }
+
+            // Futility pruning
+            let futility_score = static_eval + FUTILITY_MARGIN * depth as i16;
+            if can_prune && depth <= MAX_FUTILITY_DEPTH && futility_score <= alpha {
+                self.on_pruned_move(mv, alpha, futility_score, ReturnKind::FutilityPruning);
+                continue;
+            }
+
+            let extension = if Some(mv) == singular_move {
+                self.on_extension(ply, Some(mv));
+                1
+            } else {
+                0
+            };
+            let new_depth = depth - 1 + extension;
+
+            let window_alpha = if find_candidates {
+                params
+                    .skill_candidates
+                    .get(SKILL_CANDIDATES - 1)
+                    .map_or(original_alpha, |&(_, score)| score)
+            } else {
+                alpha
+            };
+
+            params.stack[ply].current_move = Some(mv);
             self.position.make_move(mv);
             params.stats.nodes += 1;
-            let new_alpha = -alpha.dec_mate() - Value::centipawn(1);
-            let new_beta = -alpha.dec_mate();
-            self.on_node_enter::<NonPv>(new_alpha, new_beta, mv, false);
-            let res = self.pvs::<NonPv>(depth - 1, new_alpha, new_beta, params);
-            self.on_node_exit::<NonPv>(mv, res.clone());
 
-            let Some(score) = res.map(|(s, _)| -s.inc_mate()) else {
-                self.position.unmake_move();
-                return None;
+            let score = if move_count == 1 {
+                // Search first move with full window
+                self.on_node_enter::<N::FirstChild>(-beta.dec_mate(), -alpha.dec_mate(), mv, false);
+                let res = self.pvs::<N::FirstChild>(
+                    new_depth,
+                    -beta.dec_mate(),
+                    -alpha.dec_mate(),
+                    params,
+                );
+                self.on_node_exit::<N::FirstChild>(mv, res.clone());
+                res.map(|(s, _)| -s.inc_mate())
+            } else {
+                // Search remaining moves with null window
+                let new_alpha = -window_alpha.dec_mate() - Value::centipawn(1);
+                let new_beta = -window_alpha.dec_mate();
+                self.on_node_enter::<NonPv>(new_alpha, new_beta, mv, false);
+                let res = self.pvs::<NonPv>(new_depth, new_alpha, new_beta, params);
+                self.on_node_exit::<NonPv>(mv, res.clone());
+
+                match res.map(|(s, _)| -s.inc_mate()) {
+                    Some(score) if N::IS_PV && score > window_alpha && score < beta => {
+                        self.on_node_enter::<Pv>(-beta.dec_mate(), new_beta, mv, true);
+                        let res = self.pvs::<Pv>(new_depth, -beta.dec_mate(), new_beta, params);
+                        self.on_node_exit::<Pv>(mv, res.clone());
+                        res.map(|(s, _)| -s.inc_mate())
+                    }
+                    score => score,
+                }
             };
+            self.position.unmake_move();
+            let score = score?;
+
+            if find_candidates && score > window_alpha {
+                let candidates = &mut params.skill_candidates;
+                let i = candidates.partition_point(|&(_, other)| other >= score);
+                candidates.insert(i, (mv, score));
+                candidates.truncate(SKILL_CANDIDATES);
+            }
 
             if score >= beta {
-                self.position.unmake_move();
+                if !mv.capture() {
+                    params.stack[ply].insert_killer(mv);
+                    params
+                        .tables
+                        .update(&self.position, prev, mv, &quiets_tried, depth);
+                }
                 let entry = Entry::new(score, mv, Bound::Lower, depth);
-                params.t_table.insert(&self.position, entry);
+                params
+                    .t_table
+                    .insert_excluding(&self.position, excluded, entry);
                 return Some((score, ReturnKind::FailHigh(mv).into()));
             }
 
-            if N::IS_PV && score > alpha {
-                self.on_node_enter::<Pv>(-beta.dec_mate(), -alpha.dec_mate(), mv, true);
-                let res = self.pvs::<Pv>(depth - 1, -beta.dec_mate(), -alpha.dec_mate(), params);
-                self.on_node_exit::<Pv>(mv, res.clone());
-                self.position.unmake_move();
-                let score = -res?.0.inc_mate();
-
-                if score >= beta {
-                    let entry = Entry::new(score, mv, Bound::Lower, depth);
-                    params.t_table.insert(&self.position, entry);
-                    return Some((score, ReturnKind::FailHigh(mv).into()));
-                }
-
+            if move_count == 1 || score > best_score {
                 best_score = score;
-                alpha = score;
                 best_move = mv;
-            } else {
-                self.position.unmake_move();
-                if score > best_score {
-                    best_score = score;
-                    best_move = mv;
-                    if score > alpha {
-                        alpha = score;
-                    }
+                if score > alpha {
+                    alpha = score;
                 }
             }
+            if !mv.capture() {
+                quiets_tried.push(mv);
+            }
+        }
+
+        if move_count == 0 {
+            // The excluded move was the only move
+            return if excluded.is_some() {
+                Some((alpha, ReturnKind::FailLow(Move::NULL).into()))
+            // Checkmate
+            } else if check {
+                Some((Value::neg_mate_in_ply(0), ReturnKind::Checkmate.into()))
+            // Stalemate
+            } else {
+                Some((Value::centipawn(0), ReturnKind::Stalemate.into()))
+            };
         }
 
         let (bound, ret) = if best_score <= original_alpha {
@@ -295,7 +563,9 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
             (Bound::Exact, ReturnKind::Pv(best_move))
         };
         let entry = Entry::new(best_score, best_move, bound, depth);
-        params.t_table.insert(&self.position, entry);
+        params
+            .t_table
+            .insert_excluding(&self.position, excluded, entry);
 
         Some((best_score, ret.into()))
     }
@@ -317,18 +587,21 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
         &mut self,
         mut alpha: Value,
         beta: Value,
-        sel_depth: i8,
+        ply: usize,
         params: &mut SearchParams,
     ) -> Option<Value> {
         if self.should_stop(params) {
             return None;
         }
 
-        params.stats.sel_depth = sel_depth.max(params.stats.sel_depth);
+        params.stats.reach_ply(ply);
 
         // We assume that we can do at least as well as the static
         // eval of the current position, i.e. we don't consider zugzwang
         let static_eval = self.static_eval();
+        let entry = &mut params.stack[ply];
+        entry.static_eval = Some(static_eval);
+        entry.in_check = false;
         if static_eval >= beta {
             return Some(static_eval);
         } else if static_eval > alpha {
@@ -340,9 +613,10 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
         let mut moves = self.move_gen.gen_captures(&self.position);
         self.reorder_moves(&mut moves, None);
         for mv in moves {
+            params.stack[ply].current_move = Some(mv);
             self.position.make_move(mv);
             params.stats.nodes += 1;
-            let res = self.quiesce(-beta, -alpha, sel_depth + 1, params);
+            let res = self.quiesce(-beta, -alpha, ply + 1, params);
             self.position.unmake_move();
             let score = -res?;
 
@@ -362,27 +636,26 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
     }
 
     fn reorder_moves(&self, mut moves: &mut [Move], best_move: Option<Move>) {
-        if let Some(best_move) = best_move {
-            if let Some(i) = moves.iter().position(|&mv| mv == best_move) {
-                moves.swap(0, i);
-                moves = &mut moves[1..];
-            } else {
-                log::warn!("ttable move not found in move list");
-            }
+        // The move is not in the list if it is illegal due to a hash
+        // collision, or if it was excluded from the search
+        if let Some(i) =
+            best_move.and_then(|best_move| moves.iter().position(|&mv| mv == best_move))
+        {
+            moves.swap(0, i);
+            moves = &mut moves[1..];
         }
 
         // MVV-LVA ordering
-        moves.sort_by_key(|mv| {
-            if let Some(victim) = self.position.pieces.get(mv.to()) {
-                -piece_value(victim.kind())
-            } else {
-                0
-            }
+        moves.sort_by_key(|mv| match self.position.pieces.get(mv.to()) {
+            // Castling moves capture their own rook
+            Some(victim) if mv.capture() => -piece_value(victim.kind()),
+            _ => 0,
         });
     }
 
-    fn gen_moves_and_check(&self) -> (MoveList, bool) {
-        self.move_gen.gen_all_moves_and_check(&self.position)
+    fn probe_wdl(&mut self) -> Option<Wdl> {
+        let tablebase = self.tablebase.as_ref()?;
+        tablebase.probe_wdl(&mut self.position)
     }
 
     fn static_eval(&self) -> Value {
@@ -399,8 +672,7 @@ impl<E: Eval, O: SearchObserver> SearchJob<E, O> {
             };
 
             // Sanity checking in case of hash collision
-            let moves = self.move_gen.gen_all_moves(&self.position);
-            if !moves.contains(entry.best_move) {
+            if !self.move_gen.is_legal(&self.position, entry.best_move) {
                 log::warn!(
                     "hash collision detected, move: {}, position:\n{}",
                     entry.best_move,
@@ -431,6 +703,10 @@ impl SearchJob {
             move_gen: MoveGen::init(),
             eval: StandardEval,
             observer: EmptyObserver,
+            tablebase: None,
+            skill: None,
+            seed: None,
+            iir: true,
         }
     }
 }
@@ -462,6 +738,36 @@ impl NodeType for Root {
     type FirstChild = Pv;
 }
 
+/// The score of a position won according to the tablebases, which is above
+/// any static evaluation, but below mate scores.
+const TB_WIN: Value = Value::centipawn(25_000);
+
+/// Extensions are only made in the first `MAX_EXTENSION_RATIO` times the
+/// depth of the search plies, so that e.g. series of checks can't extend the
+/// search indefinitely.
+const MAX_EXTENSION_RATIO: usize = 2;
+/// The minimum depth at which nodes without a TT move are reduced.
+const MIN_IIR_DEPTH: i8 = 4;
+/// The minimum depth at which the TT move is tested for being singular.
+const MIN_SINGULAR_DEPTH: i8 = 7;
+/// The margin per depth below the score of the TT move that the other moves
+/// must fail low against for it to be singular.
+const SINGULAR_MARGIN: Value = Value::centipawn(2);
+
+/// The maximum depth at which quiet moves are pruned when the static
+/// evaluation plus a margin per depth can't raise alpha.
+const MAX_FUTILITY_DEPTH: i8 = 3;
+const FUTILITY_MARGIN: Value = Value::centipawn(120);
+/// The maximum depth at which quiet moves are pruned after a number of moves
+/// have been searched.
+const MAX_LMP_DEPTH: i8 = 3;
+
+/// Returns the number of moves after which the remaining quiet moves are
+/// pruned at `depth`, which is halved when the position is not improving.
+fn late_move_threshold(depth: i8, improving: bool) -> usize {
+    (3 + (depth as usize).pow(2)) / (2 - improving as usize)
+}
+
 #[derive(Default, Clone, Debug)]
 struct Limits {
     moves: Option<MoveList>,
@@ -479,6 +785,9 @@ pub struct SearchEvaluation {
     pub score: Value,
     /// The principal variation of the search.
     pub pv: Vec<Move>,
+    /// The best root moves with their scores, best first, when playing with a
+    /// [`Skill`].
+    skill_candidates: Vec<(Move, Value)>,
 }
 
 /// Statistics about a search, including the depth and number of nodes searched.
@@ -488,23 +797,36 @@ pub struct SearchStats {
     pub sel_depth: i8,
     /// The number of nodes searched.
     pub nodes: u64,
+    /// The number of successful tablebase probes.
+    pub tb_hits: u64,
 }
 
 impl SearchStats {
+    /// Records that a node `ply` plies from the root was searched.
+    fn reach_ply(&mut self, ply: usize) {
+        let ply = ply.try_into().unwrap_or(i8::MAX);
+        self.sel_depth = self.sel_depth.max(ply);
+    }
+
     pub fn combine(self, other: Self) -> Self {
         Self {
             sel_depth: self.sel_depth.max(other.sel_depth),
             nodes: self.nodes + other.nodes,
+            tb_hits: self.tb_hits + other.tb_hits,
         }
     }
 }
 
-struct SearchParams {
+struct SearchParams<'a> {
     stats: SearchStats,
     search_start: Instant,
     kill_switch: Arc<AtomicBool>,
     t_table: Arc<TranspositionTable>,
     start_depth: i8,
+    root_ply: usize,
+    stack: SearchStack,
+    tables: &'a mut OrderingTables,
+    skill_candidates: Vec<(Move, Value)>,
 }
 
 /// A builder for a [`SearchJob`].
@@ -514,6 +836,10 @@ pub struct SearchJobBuilder<S, E = StandardEval, O = EmptyObserver> {
     move_gen: MoveGen,
     eval: E,
     observer: O,
+    tablebase: Option<Arc<Tablebase>>,
+    skill: Option<Skill>,
+    seed: Option<u64>,
+    iir: bool,
 }
 
 impl<E, O> SearchJobBuilder<BuilderStateUninit, E, O> {
@@ -525,6 +851,10 @@ impl<E, O> SearchJobBuilder<BuilderStateUninit, E, O> {
             move_gen: self.move_gen,
             eval: self.eval,
             observer: self.observer,
+            tablebase: self.tablebase,
+            skill: self.skill,
+            seed: self.seed,
+            iir: self.iir,
         }
     }
 }
@@ -569,6 +899,35 @@ impl<E: Eval, O: SearchObserver> SearchJobBuilder<BuilderStateInit, E, O> {
         self
     }
 
+    /// Sets the tablebases to probe during the search. At the root, only the
+    /// moves ranked best by the tablebases are searched.
+    pub fn tablebase(mut self, tablebase: Arc<Tablebase>) -> Self {
+        self.tablebase = Some(tablebase);
+        self
+    }
+
+    /// Plays weaker than full strength. This limits the number of nodes
+    /// searched, and picks the move at random among the best root moves.
+    pub fn skill(mut self, skill: Skill) -> Self {
+        self.skill = Some(skill);
+        self
+    }
+
+    /// Sets the seed used for picking moves when playing with a [`Skill`], so
+    /// that the same search picks the same move. Without a seed, the move is
+    /// picked using entropy from the system.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets whether to use internal iterative reductions, which are used by
+    /// default. Disabling them is only useful to measure their effect.
+    pub fn internal_iterative_reductions(mut self, enabled: bool) -> Self {
+        self.iir = enabled;
+        self
+    }
+
     pub fn observer<O2: SearchObserver>(
         self,
         observer: O2,
@@ -579,18 +938,48 @@ impl<E: Eval, O: SearchObserver> SearchJobBuilder<BuilderStateInit, E, O> {
             move_gen: self.move_gen,
             eval: self.eval,
             observer,
+            tablebase: self.tablebase,
+            skill: self.skill,
+            seed: self.seed,
+            iir: self.iir,
         }
     }
 
     /// Builds the search job.
     pub fn build(self) -> SearchJob<E, O> {
+        let mut position = self.state.position;
+        let mut limits = self.limits;
+        if let Some(tablebase) = &self.tablebase {
+            // Without a tablebase result, the search keeps the staged move
+            // picker unless the caller restricted the root moves
+            let all_moves;
+            let moves = match &limits.moves {
+                Some(moves) => moves,
+                None => {
+                    all_moves = self.move_gen.gen_all_moves(&position);
+                    &all_moves
+                }
+            };
+            if let Some(tb_moves) = tablebase.root_moves(&mut position, moves) {
+                limits.moves = Some(tb_moves);
+            }
+        }
+        if let Some(skill) = self.skill {
+            let node_limit = skill.node_limit();
+            limits.nodes = Some(limits.nodes.map_or(node_limit, |n| n.min(node_limit)));
+        }
+        self.eval.prepare(&mut position);
         SearchJob {
-            position: self.state.position,
-            limits: self.limits,
+            position,
+            limits,
             move_gen: self.move_gen,
             eval: self.eval,
             observer: self.observer,
+            tablebase: self.tablebase,
+            skill: self.skill,
+            seed: self.seed,
             worker_id: 0,
+            iir: self.iir,
         }
     }
 }
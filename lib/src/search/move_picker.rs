@@ -0,0 +1,434 @@
+use std::iter;
+
+use arrayvec::ArrayVec;
+
+use crate::collections::MoveList;
+use crate::eval::piece_value;
+use crate::types::{Color, Move, MoveKind, Piece, PieceKind, Square};
+use crate::{MoveGen, Position};
+
+/// The maximum absolute value of a history score.
+const MAX_HISTORY: i32 = 16_384;
+/// The maximum history bonus given for a single cutoff.
+const MAX_HISTORY_BONUS: i32 = 1_200;
+
+/// Scores for quiet moves indexed by color and from and to square. A move's
+/// score is increased when it causes a beta cutoff, and decreased when it was
+/// searched before another move caused a cutoff.
+pub(super) struct History(Box<[[[i32; 64]; 64]; 2]>);
+
+impl History {
+    pub(super) fn new() -> Self {
+        Self(Box::new([[[0; 64]; 64]; 2]))
+    }
+
+    pub(super) fn get(&self, color: Color, mv: Move) -> i32 {
+        self.0[color as usize][mv.from() as usize][mv.to() as usize]
+    }
+
+    /// Rewards `best_move` for causing a cutoff at `depth`, and punishes the
+    /// quiet moves tried before it.
+    pub(super) fn update(&mut self, color: Color, best_move: Move, tried: &[Move], depth: i8) {
+        let bonus = history_bonus(depth);
+        self.add(color, best_move, bonus);
+        for &mv in tried {
+            self.add(color, mv, -bonus);
+        }
+    }
+
+    fn add(&mut self, color: Color, mv: Move, bonus: i32) {
+        add_bonus(
+            &mut self.0[color as usize][mv.from() as usize][mv.to() as usize],
+            bonus,
+        );
+    }
+
+    fn age(&mut self) {
+        self.0.iter_mut().flatten().flatten().for_each(age_score);
+    }
+}
+
+/// A piece and the square it moved to, which identifies a move across
+/// positions.
+pub(super) type PieceTo = (Piece, Square);
+
+fn piece_index(pce: Piece) -> usize {
+    6 * pce.color() as usize + pce.kind() as usize
+}
+
+/// Returns the piece that made the last move in `position` and the square it
+/// moved to, or `None` if no move has been made or it was the null move.
+pub(super) fn last_piece_to(position: &Position) -> Option<PieceTo> {
+    let mv = position.last_move().filter(|mv| !mv.is_null())?;
+    let to = match mv.kind() {
+        // The king ends up on its castling square rather than the square of
+        // the rook
+        MoveKind::Castling => Square::king_castling_dest(!position.to_move, mv.castling_side()),
+        _ => mv.to(),
+    };
+    Some((position.pieces.get(to)?, to))
+}
+
+/// The quiet move that last caused a beta cutoff in reply to each move, indexed
+/// by the piece and destination of the move replied to.
+pub(super) struct CounterMoves(Box<[[Option<Move>; 64]; 12]>);
+
+impl CounterMoves {
+    pub(super) fn new() -> Self {
+        Self(Box::new([[None; 64]; 12]))
+    }
+
+    pub(super) fn get(&self, (pce, to): PieceTo) -> Option<Move> {
+        self.0[piece_index(pce)][to as usize]
+    }
+
+    fn insert(&mut self, (pce, to): PieceTo, mv: Move) {
+        self.0[piece_index(pce)][to as usize] = Some(mv);
+    }
+}
+
+/// Scores for quiet moves in reply to the previous move, indexed by the piece
+/// and destination of both moves. Updated like [`History`].
+pub(super) struct ContinuationHistory(Box<[i32]>);
+
+impl ContinuationHistory {
+    pub(super) fn new() -> Self {
+        Self(vec![0; 12 * 64 * 12 * 64].into_boxed_slice())
+    }
+
+    fn index((prev_pce, prev_to): PieceTo, (pce, to): PieceTo) -> usize {
+        ((piece_index(prev_pce) * 64 + prev_to as usize) * 12 + piece_index(pce)) * 64 + to as usize
+    }
+
+    pub(super) fn get(&self, prev: PieceTo, piece_to: PieceTo) -> i32 {
+        self.0[Self::index(prev, piece_to)]
+    }
+
+    fn add(&mut self, prev: PieceTo, piece_to: PieceTo, bonus: i32) {
+        add_bonus(&mut self.0[Self::index(prev, piece_to)], bonus);
+    }
+
+    fn age(&mut self) {
+        self.0.iter_mut().for_each(age_score);
+    }
+}
+
+/// The tables used for ordering quiet moves. These are kept by each search
+/// thread between searches, and are aged at the start of each search.
+pub(super) struct OrderingTables {
+    pub(super) history: History,
+    pub(super) counter_moves: CounterMoves,
+    pub(super) continuation: ContinuationHistory,
+}
+
+impl OrderingTables {
+    pub(super) fn new() -> Self {
+        Self {
+            history: History::new(),
+            counter_moves: CounterMoves::new(),
+            continuation: ContinuationHistory::new(),
+        }
+    }
+
+    /// Rewards `best_move` for causing a cutoff at `depth` in reply to `prev`,
+    /// and punishes the quiet moves tried before it.
+    pub(super) fn update(
+        &mut self,
+        position: &Position,
+        prev: Option<PieceTo>,
+        best_move: Move,
+        tried: &[Move],
+        depth: i8,
+    ) {
+        let color = position.to_move;
+        self.history.update(color, best_move, tried, depth);
+
+        let Some(prev) = prev else {
+            return;
+        };
+        self.counter_moves.insert(prev, best_move);
+        let bonus = history_bonus(depth);
+        for (mv, bonus) in
+            iter::once((best_move, bonus)).chain(tried.iter().map(|&mv| (mv, -bonus)))
+        {
+            if let Some(pce) = position.pieces.get(mv.from()) {
+                self.continuation.add(prev, (pce, mv.to()), bonus);
+            }
+        }
+    }
+
+    /// Halves the history scores, so that scores from earlier searches weigh
+    /// less than new ones.
+    pub(super) fn age(&mut self) {
+        self.history.age();
+        self.continuation.age();
+    }
+
+    pub(super) fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+fn history_bonus(depth: i8) -> i32 {
+    (depth.max(1) as i32).pow(2).min(MAX_HISTORY_BONUS)
+}
+
+fn add_bonus(entry: &mut i32, bonus: i32) {
+    // Scale the bonus down as the score approaches the maximum
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+fn age_score(score: &mut i32) {
+    *score /= 2;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    TTMove,
+    GenCaptures,
+    GoodCaptures,
+    Killers,
+    CounterMove,
+    GenQuiets,
+    Quiets,
+    BadCaptures,
+    /// Returns the moves of a given list in order.
+    List,
+    Done,
+}
+
+#[derive(Clone, Copy)]
+struct ScoredMove {
+    mv: Move,
+    score: i32,
+}
+
+/// Picks moves for the search in stages, generating moves only when needed.
+/// The order is: the transposition table move, captures not losing material,
+/// killer moves, the counter move, quiet moves ordered by history and finally
+/// captures losing material. Since a cutoff often happens on the first few
+/// moves, most nodes never need to generate quiet moves.
+pub(super) struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    /// The piece and destination of the previous move.
+    prev: Option<PieceTo>,
+    counter_move: Option<Move>,
+    moves: ArrayVec<ScoredMove, 256>,
+    bad_captures: MoveList,
+    index: usize,
+}
+
+impl MovePicker {
+    pub(super) fn new(
+        tt_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        prev: Option<PieceTo>,
+        tables: &OrderingTables,
+    ) -> Self {
+        Self {
+            stage: Stage::TTMove,
+            tt_move,
+            killers,
+            prev,
+            counter_move: prev.and_then(|prev| tables.counter_moves.get(prev)),
+            moves: ArrayVec::new(),
+            bad_captures: MoveList::new(),
+            index: 0,
+        }
+    }
+
+    /// Creates a move picker that returns the legal moves in `moves` in order.
+    pub(super) fn from_moves(moves: MoveList) -> Self {
+        let mut picker = Self {
+            stage: Stage::List,
+            tt_move: None,
+            killers: [None; 2],
+            prev: None,
+            counter_move: None,
+            moves: ArrayVec::new(),
+            bad_captures: MoveList::new(),
+            index: 0,
+        };
+        picker.moves = moves
+            .into_iter()
+            .map(|mv| ScoredMove { mv, score: 0 })
+            .collect();
+        picker
+    }
+
+    /// Returns the next legal move, or `None` if all moves have been picked.
+    pub(super) fn next(
+        &mut self,
+        move_gen: &MoveGen,
+        position: &Position,
+        tables: &OrderingTables,
+    ) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TTMove => {
+                    self.stage = Stage::GenCaptures;
+                    // The move may be from another position in case of a hash
+                    // collision
+                    match self.tt_move {
+                        Some(mv) if move_gen.is_legal(position, mv) => return Some(mv),
+                        _ => self.tt_move = None,
+                    }
+                }
+                Stage::GenCaptures => {
+                    self.moves = move_gen
+                        .gen_captures(position)
+                        .into_iter()
+                        .map(|mv| ScoredMove {
+                            mv,
+                            score: capture_score(position, mv),
+                        })
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    while let Some(mv) = self.pick_best() {
+                        if Some(mv) == self.tt_move {
+                            continue;
+                        } else if move_gen.see(position, mv) < 0 {
+                            self.bad_captures.push(mv);
+                            continue;
+                        }
+                        return Some(mv);
+                    }
+                    self.index = 0;
+                    self.stage = Stage::Killers;
+                }
+                Stage::Killers => {
+                    while self.index < self.killers.len() {
+                        let killer = self.killers[self.index];
+                        self.index += 1;
+                        match killer {
+                            Some(mv)
+                                if killer != self.tt_move && move_gen.is_legal(position, mv) =>
+                            {
+                                return Some(mv);
+                            }
+                            _ => {}
+                        }
+                    }
+                    self.stage = Stage::CounterMove;
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::GenQuiets;
+                    match self.counter_move {
+                        Some(mv)
+                            if self.counter_move != self.tt_move
+                                && !self.killers.contains(&self.counter_move)
+                                && move_gen.is_legal(position, mv) =>
+                        {
+                            return Some(mv);
+                        }
+                        _ => self.counter_move = None,
+                    }
+                }
+                Stage::GenQuiets => {
+                    self.moves = move_gen
+                        .gen_quiets(position)
+                        .into_iter()
+                        .map(|mv| ScoredMove {
+                            mv,
+                            score: quiet_score(tables, position, self.prev, mv),
+                        })
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    while let Some(mv) = self.pick_best() {
+                        if Some(mv) == self.tt_move
+                            || self.killers.contains(&Some(mv))
+                            || Some(mv) == self.counter_move
+                        {
+                            continue;
+                        }
+                        return Some(mv);
+                    }
+                    self.index = 0;
+                    self.stage = Stage::BadCaptures;
+                }
+                Stage::BadCaptures => {
+                    let mv = self.bad_captures.get(self.index).copied();
+                    self.index += 1;
+                    if mv.is_none() {
+                        self.stage = Stage::Done;
+                    }
+                    return mv;
+                }
+                Stage::List => {
+                    let mv = self.moves.get(self.index).map(|scored| scored.mv);
+                    self.index += 1;
+                    if mv.is_none() {
+                        self.stage = Stage::Done;
+                    }
+                    return mv;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Moves the highest scoring of the remaining moves to the front and
+    /// returns it.
+    fn pick_best(&mut self) -> Option<Move> {
+        let remaining = &mut self.moves[self.index..];
+        let best = remaining
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, scored)| scored.score)?
+            .0;
+        remaining.swap(0, best);
+        let mv = remaining[0].mv;
+        self.index += 1;
+        Some(mv)
+    }
+}
+
+/// Orders captures by most valuable victim, least valuable attacker.
+fn capture_score(position: &Position, mv: Move) -> i32 {
+    let victim = match mv.kind() {
+        MoveKind::EnPassant => PieceKind::Pawn,
+        _ => position
+            .pieces
+            .get(mv.to())
+            .expect("capture should have a victim")
+            .kind(),
+    };
+    let attacker = position
+        .pieces
+        .get(mv.from())
+        .expect("move should have a piece")
+        .kind();
+    let promotion = mv.promotion().map(piece_value).unwrap_or(0);
+    8 * (piece_value(victim) + promotion) as i32 - piece_value(attacker) as i32
+}
+
+/// Orders quiet moves by history and continuation history, except that queen
+/// promotions come first and underpromotions last.
+fn quiet_score(
+    tables: &OrderingTables,
+    position: &Position,
+    prev: Option<PieceTo>,
+    mv: Move,
+) -> i32 {
+    match mv.promotion() {
+        Some(PieceKind::Queen) => 4 * MAX_HISTORY,
+        Some(_) => -4 * MAX_HISTORY,
+        None => {
+            let history = tables.history.get(position.to_move, mv);
+            let continuation = prev
+                .zip(position.pieces.get(mv.from()))
+                .map_or(0, |(prev, pce)| {
+                    tables.continuation.get(prev, (pce, mv.to()))
+                });
+            history + continuation
+        }
+    }
+}
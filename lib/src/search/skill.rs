@@ -0,0 +1,71 @@
+use rand::Rng;
+
+use crate::types::{Move, Value};
+
+/// The skill level of full strength play.
+pub const MAX_SKILL_LEVEL: u8 = 20;
+/// The lowest Elo accepted by [`Skill::from_elo`].
+pub const MIN_ELO: u32 = 800;
+/// The highest Elo accepted by [`Skill::from_elo`], which is full strength.
+pub const MAX_ELO: u32 = 2800;
+/// The number of best root moves to choose between.
+pub(super) const SKILL_CANDIDATES: usize = 4;
+
+/// A handicap for playing weaker than full strength. The search is limited to
+/// a number of nodes, and the move played is picked at random among the best
+/// root moves, with worse moves being more likely the lower the level.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Skill {
+    level: u8,
+}
+
+impl Skill {
+    /// Creates a skill of the given level. Returns `None` for levels of at
+    /// least [`MAX_SKILL_LEVEL`], which is full strength.
+    pub fn new(level: u8) -> Option<Self> {
+        (level < MAX_SKILL_LEVEL).then_some(Self { level })
+    }
+
+    /// Creates a skill for roughly the given Elo. The levels are mapped
+    /// linearly to the range `MIN_ELO..=MAX_ELO`, which is not calibrated
+    /// against other engines. Returns `None` for full strength.
+    pub fn from_elo(elo: u32) -> Option<Self> {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let level = (elo - MIN_ELO) * MAX_SKILL_LEVEL as u32 / (MAX_ELO - MIN_ELO);
+        Self::new(level as u8)
+    }
+
+    /// Returns the skill level.
+    pub fn level(self) -> u8 {
+        self.level
+    }
+
+    /// Returns the number of nodes to search, which grows by half for each
+    /// level.
+    pub fn node_limit(self) -> u64 {
+        (100. * 1.5f64.powi(self.level as i32)) as u64
+    }
+
+    /// Picks a move among `candidates`, which are root moves with their scores
+    /// ordered from best to worst.
+    pub(super) fn pick_move(self, candidates: &[(Move, Value)], rng: &mut impl Rng) -> Move {
+        let score = |i: usize| candidates[i].1.into_inner() as i32;
+        let top_score = score(0);
+        // How much the scores are disturbed, in units of 1/128
+        let weakness = 120 - 2 * self.level as i32;
+        let delta = (top_score - score(candidates.len() - 1)).min(100);
+
+        let mut best = candidates[0].0;
+        let mut best_score = i32::MIN;
+        for (i, &(mv, _)) in candidates.iter().enumerate() {
+            // Pull the worse moves towards the best one, and add noise
+            let push =
+                (weakness * (top_score - score(i)) + delta * rng.gen_range(0..weakness)) / 128;
+            if score(i) + push >= best_score {
+                best_score = score(i) + push;
+                best = mv;
+            }
+        }
+        best
+    }
+}
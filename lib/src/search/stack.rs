@@ -0,0 +1,74 @@
+use std::ops::{Index, IndexMut};
+
+use crate::types::{Move, Value};
+
+/// The state of the search at a ply from the root.
+#[derive(Clone, Copy, Default)]
+pub(super) struct StackEntry {
+    /// The static evaluation of the position, or `None` when in check.
+    pub(super) static_eval: Option<Value>,
+    /// The move currently being searched, which is the null move during null
+    /// move pruning.
+    pub(super) current_move: Option<Move>,
+    /// Quiet moves that caused a beta cutoff at this ply. These are likely to
+    /// cause a cutoff in sibling nodes as well.
+    pub(super) killers: [Option<Move>; 2],
+    /// A move to leave out of the search of the position, set while testing
+    /// whether it is singular.
+    pub(super) excluded_move: Option<Move>,
+    pub(super) in_check: bool,
+}
+
+impl StackEntry {
+    pub(super) fn insert_killer(&mut self, mv: Move) {
+        if self.killers[0] != Some(mv) {
+            self.killers[1] = self.killers[0];
+            self.killers[0] = Some(mv);
+        }
+    }
+}
+
+/// The search state of each ply from the root to the current node. The stack
+/// grows as deeper plies are reached.
+#[derive(Default)]
+pub(super) struct SearchStack(Vec<StackEntry>);
+
+impl SearchStack {
+    /// Returns whether the static evaluation at `ply` is better than the last
+    /// time the same side was to move and not in check. If there is no such
+    /// position, the evaluation is considered to be improving.
+    pub(super) fn improving(&self, ply: usize) -> bool {
+        let Some(static_eval) = self[ply].static_eval else {
+            return false;
+        };
+        [2, 4]
+            .into_iter()
+            .filter_map(|back| ply.checked_sub(back))
+            .find_map(|prev_ply| self[prev_ply].static_eval)
+            .is_none_or(|prev_eval| static_eval > prev_eval)
+    }
+}
+
+impl Index<usize> for SearchStack {
+    type Output = StackEntry;
+
+    fn index(&self, ply: usize) -> &StackEntry {
+        const EMPTY: StackEntry = StackEntry {
+            static_eval: None,
+            current_move: None,
+            killers: [None; 2],
+            excluded_move: None,
+            in_check: false,
+        };
+        self.0.get(ply).unwrap_or(&EMPTY)
+    }
+}
+
+impl IndexMut<usize> for SearchStack {
+    fn index_mut(&mut self, ply: usize) -> &mut StackEntry {
+        if ply >= self.0.len() {
+            self.0.resize(ply + 1, StackEntry::default());
+        }
+        &mut self.0[ply]
+    }
+}
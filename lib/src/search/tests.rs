@@ -1,50 +1,55 @@
 use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use crate::eval::MaterialEval;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::eval::{Eval, MaterialEval};
 use crate::mv;
 use crate::position::Position;
 use crate::search::thread::SearchInfo;
 use crate::search::ThreadPool;
-use crate::types::{value, Value};
+use crate::syzygy::Tablebase;
+use crate::types::{value, Color, Move, Piece, PieceKind, PseudoMove, Square, Value};
+use crate::MoveGen;
+
+use super::move_picker::{last_piece_to, MovePicker, OrderingTables};
+use super::stack::SearchStack;
+use super::trace::{ReturnKind, SearchObserver};
+use super::transposition_table::Bound;
+use super::{
+    Entry, NodeType, SearchEvaluation, SearchJob, SearchResult, Skill, TranspositionTable,
+    MAX_EXTENSION_RATIO,
+};
 
-use super::{SearchEvaluation, SearchJob, TranspositionTable};
+/// Runs `job` to its depth on the current thread with a fresh transposition
+/// table and move ordering tables.
+fn run_job<E: Eval, O: SearchObserver>(job: SearchJob<E, O>) -> SearchResult {
+    job.search(
+        value::NEG_INF,
+        value::INF,
+        Instant::now(),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(TranspositionTable::with_hash_size(1)),
+        &mut OrderingTables::new(),
+    )
+}
 
 fn search(position: Position, depth: i8) -> SearchEvaluation {
-    let kill_switch = Arc::new(AtomicBool::new(false));
-    let t_table = Arc::new(TranspositionTable::with_hash_size(1));
-    SearchJob::default_builder()
+    let job = SearchJob::default_builder()
         .position(position)
         .depth(depth)
-        .build()
-        .search(
-            value::NEG_INF,
-            value::INF,
-            Instant::now(),
-            kill_switch,
-            t_table,
-        )
-        .evaluation
-        .unwrap()
+        .build();
+    run_job(job).evaluation.unwrap()
 }
 
 fn search_material(position: Position, depth: i8) -> SearchEvaluation {
-    let kill_switch = Arc::new(AtomicBool::new(false));
-    let t_table = Arc::new(TranspositionTable::with_hash_size(1));
-    SearchJob::builder(MaterialEval)
+    let job = SearchJob::builder(MaterialEval)
         .position(position)
         .depth(depth)
-        .build()
-        .search(
-            value::NEG_INF,
-            value::INF,
-            Instant::now(),
-            kill_switch,
-            t_table,
-        )
-        .evaluation
-        .unwrap()
+        .build();
+    run_job(job).evaluation.unwrap()
 }
 
 fn search_threaded(position: Position, depth: i8) -> SearchEvaluation {
@@ -116,6 +121,209 @@ fn stops_early_on_1_legal_move() {
     assert_eq!(new_depth_count, 5);
 }
 
+#[test]
+fn tablebase_without_result_keeps_root_moves() {
+    // No tables are found, so the tablebase can't rank any root moves
+    let dir = std::env::temp_dir().join(format!("kingly-no-tables-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let tablebase = Arc::new(Tablebase::open(&dir).unwrap());
+
+    let job = SearchJob::default_builder()
+        .position(Position::new())
+        .tablebase(Arc::clone(&tablebase))
+        .build();
+    assert!(job.limits.moves.is_none());
+
+    let job = SearchJob::default_builder()
+        .position(Position::new())
+        .moves(["e2e4".parse::<PseudoMove>().unwrap()])
+        .unwrap()
+        .tablebase(tablebase)
+        .build();
+    assert_eq!(job.limits.moves.unwrap().len(), 1);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn move_picker_returns_legal_moves_in_stages() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+    let move_gen = MoveGen::init();
+    let legal_moves = move_gen.gen_all_moves(&position);
+
+    let tt_move = mv!(E1 -> D1);
+    // The second killer is not legal, as there is a piece on H3
+    let killers = [Some(mv!(A2 -> A3)), Some(mv!(G2 -> H3))];
+    let tables = OrderingTables::new();
+    let mut move_picker = MovePicker::new(Some(tt_move), killers, None, &tables);
+    let mut moves = Vec::new();
+    while let Some(mv) = move_picker.next(&move_gen, &position, &tables) {
+        moves.push(mv);
+    }
+
+    assert_eq!(moves.len(), legal_moves.len());
+    assert!(legal_moves.iter().all(|mv| moves.contains(mv)));
+    assert_eq!(moves[0], tt_move);
+    let killer_idx = moves.iter().position(|&mv| mv == mv!(A2 -> A3)).unwrap();
+    assert!(moves[1..killer_idx]
+        .iter()
+        .all(|&mv| mv.capture() && move_gen.see(&position, mv) >= 0));
+    // Captures losing material come last
+    let is_bad_capture = |&mv: &Move| mv.capture() && move_gen.see(&position, mv) < 0;
+    let bad_idx = moves.iter().position(is_bad_capture).unwrap();
+    assert!(bad_idx > killer_idx);
+    assert!(moves[bad_idx..].iter().all(is_bad_capture));
+    assert!(moves[bad_idx..].contains(&mv!(F3 x F6)));
+}
+
+#[test]
+fn move_picker_orders_quiets_by_reply_to_previous_move() {
+    let mut position = Position::new();
+    position.make_move(mv!(E2 -> E4));
+    let move_gen = MoveGen::init();
+    let prev = last_piece_to(&position);
+    assert_eq!(
+        prev,
+        Some((Piece(PieceKind::Pawn, Color::White), Square::E4))
+    );
+
+    let mut tables = OrderingTables::new();
+    tables.update(&position, prev, mv!(G8 -> F6), &[mv!(A7 -> A6)], 4);
+    let mut move_picker = MovePicker::new(None, [None; 2], prev, &tables);
+    let mut moves = Vec::new();
+    while let Some(mv) = move_picker.next(&move_gen, &position, &tables) {
+        moves.push(mv);
+    }
+
+    // The counter move comes first, as there are no captures, and the move
+    // tried before it comes last
+    assert_eq!(moves.len(), 20);
+    assert_eq!(moves[0], mv!(G8 -> F6));
+    assert_eq!(moves[19], mv!(A7 -> A6));
+
+    // Without the previous move, there is no counter move, but the history
+    // still prefers the move
+    let mut move_picker = MovePicker::new(None, [None; 2], None, &tables);
+    assert_eq!(
+        move_picker.next(&move_gen, &position, &tables),
+        Some(mv!(G8 -> F6))
+    );
+}
+
+#[test]
+fn skill_picks_moves_deterministically_per_seed() {
+    let mut thread_pool = ThreadPool::new();
+    let mut best_move = |seed| {
+        // The search is only reproducible from an empty transposition table
+        // and history
+        thread_pool.clear_t_table().unwrap();
+        thread_pool.clear_history().unwrap();
+        let job = SearchJob::default_builder()
+            .position(Position::new())
+            .skill(Skill::new(0).unwrap())
+            .seed(seed)
+            .build();
+        let rx = thread_pool.run(job).unwrap();
+        let SearchInfo::Finished(best_mv) = rx.iter().last().unwrap() else {
+            panic!("Last search info was not Finished");
+        };
+        thread_pool.wait().unwrap();
+        best_mv
+    };
+
+    let moves: Vec<_> = (0..8).map(&mut best_move).collect();
+    for (seed, &mv) in moves.iter().enumerate() {
+        assert_eq!(best_move(seed as u64), mv);
+    }
+    assert!(moves.iter().any(|&mv| mv != moves[0]));
+
+    // Clearly worse moves are not played at high levels
+    let candidates = [
+        (mv!(E2 -> E4), Value::centipawn(300)),
+        (mv!(D2 -> D4), Value::centipawn(-600)),
+    ];
+    let skill = Skill::new(19).unwrap();
+    for seed in 0..100 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        assert_eq!(skill.pick_move(&candidates, &mut rng), mv!(E2 -> E4));
+    }
+}
+
+#[test]
+fn skill_stays_within_node_limit() {
+    let mut thread_pool = ThreadPool::new();
+    let skill = Skill::new(10).unwrap();
+    let job = SearchJob::default_builder()
+        .position(Position::new())
+        .skill(skill)
+        .seed(0)
+        .build();
+    let rx = thread_pool.run(job).unwrap();
+    let SearchInfo::Finished(_) = rx.iter().last().unwrap() else {
+        panic!("Last search info was not Finished");
+    };
+    let result = thread_pool.wait().unwrap();
+    // The node limit is only checked every 2048 nodes
+    assert!(result.stats.nodes <= skill.node_limit() + 2048);
+}
+
+#[derive(Default)]
+struct PruningCounter {
+    futility: usize,
+    late_move: usize,
+    razoring: usize,
+    probcut: usize,
+}
+
+impl SearchObserver for PruningCounter {
+    type ReturnKind = ReturnKind;
+
+    fn on_node_exit<N: NodeType>(
+        &mut self,
+        _worker_id: usize,
+        _mv: Option<Move>,
+        ret: ReturnKind,
+        _score: Option<Value>,
+    ) {
+        match ret {
+            ReturnKind::FutilityPruning => self.futility += 1,
+            ReturnKind::LateMovePruning => self.late_move += 1,
+            ReturnKind::Razoring => self.razoring += 1,
+            ReturnKind::ProbCut(_) => self.probcut += 1,
+            _ => {}
+        }
+    }
+}
+
+fn count_pruning(fen: &str, depth: i8) -> PruningCounter {
+    let position = Position::from_fen(fen).unwrap();
+    let counter = Arc::new(Mutex::new(PruningCounter::default()));
+    let job = SearchJob::default_builder()
+        .position(position)
+        .depth(depth)
+        .observer(Arc::clone(&counter))
+        .build();
+    run_job(job);
+    Arc::into_inner(counter).unwrap().into_inner().unwrap()
+}
+
+#[test]
+fn quiet_moves_are_pruned_and_traced() {
+    let fen = "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 10";
+    let counter = count_pruning(fen, 6);
+    assert!(counter.futility > 0);
+    assert!(counter.late_move > 0);
+}
+
+#[test]
+fn razoring_and_probcut_cut_off_and_are_traced() {
+    let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    let counter = count_pruning(fen, 7);
+    assert!(counter.razoring > 0);
+    assert!(counter.probcut > 0);
+}
+
 #[test]
 fn queen_standoff_should_give_advantage_to_player_to_move() {
     let w_to_move_fen = "4k3/8/8/3q4/3Q4/8/8/4K3 w - - 0 1";
@@ -140,6 +348,43 @@ fn finds_mate_in_two() {
     assert_eq!(res.score, Value::mate_in_ply(3));
 }
 
+#[test]
+fn reports_exact_mate_distances() {
+    // Positions with the number of moves until the side to move mates, or is
+    // mated if negative
+    let positions: [(&str, i16); 9] = [
+        ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 1),
+        (
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            1,
+        ),
+        ("3r2k1/8/8/8/8/8/5PPP/6K1 b - - 0 1", 1),
+        ("k7/8/2K5/8/8/8/8/1R6 w - - 0 1", 2),
+        (
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10",
+            2,
+        ),
+        ("2k5/8/1K6/8/8/8/8/3R4 w - - 0 1", 2),
+        ("6k1/8/8/5K2/8/8/8/R7 w - - 0 1", 3),
+        ("k7/8/1K6/8/8/8/8/7R b - - 0 1", -1),
+        ("6k1/8/5K2/8/8/8/8/R7 b - - 0 1", -2),
+    ];
+
+    for (fen, moves) in positions {
+        let plies = 2 * moves.unsigned_abs();
+        let expected = if moves > 0 {
+            Value::mate_in_ply(plies - 1)
+        } else {
+            Value::neg_mate_in_ply(plies)
+        };
+        let position = Position::from_fen(fen).unwrap();
+        let res = search(position.clone(), plies as i8);
+        assert_eq!(res.score, expected, "{fen}");
+        let res = search_threaded(position, 8);
+        assert_eq!(res.score, expected, "{fen}");
+    }
+}
+
 #[test]
 fn finds_threefold_repetition() {
     let fen = "6kq/6p1/6Q1/8/8/8/1q6/6K1 w - - 0 1";
@@ -155,6 +400,33 @@ fn finds_threefold_repetition() {
     assert_eq!(res.score, Value::centipawn(0));
 }
 
+#[test]
+fn finds_repetition_within_search() {
+    // White can only hold the draw by perpetual check, which repeats the
+    // position after four plies
+    let fen = "6kq/6p1/6Q1/8/8/8/1q6/6K1 w - - 0 1";
+    let position = Position::from_fen(fen).unwrap();
+
+    let res = search(position.clone(), 5);
+    assert_eq!(res.score, Value::centipawn(0));
+    assert_eq!(res.pv[0], mv!(G6 -> E8));
+    let res = search_threaded(position, 8);
+    assert_eq!(res.score, Value::centipawn(0));
+}
+
+#[test]
+fn repetition_before_root_is_not_a_draw() {
+    let fen = "6k1/8/8/8/8/8/8/R5K1 w - - 0 1";
+    let mut position = Position::from_fen(fen).unwrap();
+    position.make_move(mv!(G1 -> H1));
+    position.make_move(mv!(G8 -> H8));
+    position.make_move(mv!(H1 -> G1));
+    position.make_move(mv!(H8 -> G8));
+
+    let res = search(position, 4);
+    assert!(res.score > Value::centipawn(0));
+}
+
 #[test]
 fn finds_fifty_move_draw() {
     let fen = "6kq/8/8/8/5K2/8/8/8 b - - 98 4";
@@ -200,3 +472,231 @@ fn zugzwang_test_position_2() {
     let res = search_threaded(position, 8);
     assert_eq!(res.pv[0], mv!(F5 -> E5));
 }
+
+#[test]
+fn excluded_move_entries_are_kept_apart() {
+    let t_table = TranspositionTable::with_hash_size(1);
+    let position = Position::new();
+    let excluded = Some(mv!(E2 -> E4));
+
+    let entry = Entry::new(Value::centipawn(30), mv!(D2 -> D4), Bound::Lower, 8);
+    t_table.insert_excluding(&position, excluded, entry);
+    assert!(t_table.get(&position).is_none());
+    assert!(t_table
+        .get_excluding(&position, Some(mv!(D2 -> D4)))
+        .is_none());
+    let stored = t_table.get_excluding(&position, excluded).unwrap();
+    assert_eq!(stored.best_move, mv!(D2 -> D4));
+
+    let entry = Entry::new(Value::centipawn(40), mv!(E2 -> E4), Bound::Exact, 8);
+    t_table.insert(&position, entry);
+    assert_eq!(
+        t_table.get_excluding(&position, None).unwrap().best_move,
+        mv!(E2 -> E4)
+    );
+}
+
+#[derive(Default)]
+struct ExtensionCounter {
+    depth: i8,
+    multi_cuts: usize,
+    singular_extensions: usize,
+    /// Extensions made deeper than the depth of the iteration
+    deep_extensions: usize,
+    /// Extensions made outside the extension budget
+    excess_extensions: usize,
+    /// The ply of the check extension of each node being searched, if any
+    check_extensions: Vec<Option<usize>>,
+    /// Singular extensions in positions in check
+    singular_extensions_in_check: usize,
+    /// Check extensions made again by the singular verification search of
+    /// the same position
+    repeated_check_extensions: usize,
+}
+
+impl SearchObserver for ExtensionCounter {
+    type ReturnKind = ReturnKind;
+
+    fn on_depth(&mut self, depth: i8) {
+        self.depth = depth;
+    }
+
+    fn on_node_enter<N: NodeType>(
+        &mut self,
+        _worker_id: usize,
+        _alpha: Value,
+        _beta: Value,
+        _mv: Option<Move>,
+        _pvs_re_search: bool,
+    ) {
+        self.check_extensions.push(None);
+    }
+
+    fn on_node_exit<N: NodeType>(
+        &mut self,
+        _worker_id: usize,
+        _mv: Option<Move>,
+        ret: ReturnKind,
+        _score: Option<Value>,
+    ) {
+        self.check_extensions.pop();
+        if let ReturnKind::MultiCut = ret {
+            self.multi_cuts += 1;
+        }
+    }
+
+    fn on_extension(&mut self, _worker_id: usize, ply: usize, mv: Option<Move>) {
+        // The singular verification search is entered as a child node, but
+        // searches the same position at the same ply
+        let len = self.check_extensions.len();
+        if mv.is_some() {
+            self.singular_extensions += 1;
+            if self.check_extensions[len - 1] == Some(ply) {
+                self.singular_extensions_in_check += 1;
+            }
+        } else {
+            if len >= 2 && self.check_extensions[len - 2] == Some(ply) {
+                self.repeated_check_extensions += 1;
+            }
+            self.check_extensions[len - 1] = Some(ply);
+        }
+        if ply >= self.depth as usize {
+            self.deep_extensions += 1;
+        }
+        if ply >= MAX_EXTENSION_RATIO * self.depth as usize {
+            self.excess_extensions += 1;
+        }
+    }
+}
+
+#[test]
+fn singular_extensions_and_multi_cuts_are_traced() {
+    // Singular extensions need TT entries from earlier iterations
+    let mut thread_pool = ThreadPool::new();
+    let counter = Arc::new(Mutex::new(ExtensionCounter::default()));
+    let job = SearchJob::default_builder()
+        .position(
+            Position::from_fen("2r3k1/pp3ppp/2n1b3/3p4/3P4/2PB1N2/P4PPP/4R1K1 w - - 0 20").unwrap(),
+        )
+        .depth(9)
+        .observer(Arc::clone(&counter))
+        .build();
+    let rx = thread_pool.run(job).unwrap();
+    rx.iter().last().unwrap();
+    thread_pool.wait().unwrap();
+
+    let counter = counter.lock().unwrap();
+    assert!(counter.singular_extensions > 0);
+    assert!(counter.multi_cuts > 0);
+    assert_eq!(counter.excess_extensions, 0);
+}
+
+#[test]
+fn singular_verification_does_not_extend_checks_again() {
+    // Positions in check where the TT move is singular occur in this search
+    let mut thread_pool = ThreadPool::new();
+    let counter = Arc::new(Mutex::new(ExtensionCounter::default()));
+    let job = SearchJob::default_builder()
+        .position(
+            Position::from_fen(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            )
+            .unwrap(),
+        )
+        .depth(9)
+        .observer(Arc::clone(&counter))
+        .build();
+    let rx = thread_pool.run(job).unwrap();
+    rx.iter().last().unwrap();
+    thread_pool.wait().unwrap();
+
+    let counter = counter.lock().unwrap();
+    assert!(counter.singular_extensions_in_check > 0);
+    assert_eq!(counter.repeated_check_extensions, 0);
+}
+
+#[test]
+fn check_extensions_stay_within_budget() {
+    // The queen can keep checking, so without the budget the check
+    // extensions would only be stopped by repetitions
+    let depth = 8;
+    let counter = Arc::new(Mutex::new(ExtensionCounter {
+        depth,
+        ..Default::default()
+    }));
+    let job = SearchJob::default_builder()
+        .position(Position::from_fen("6k1/5ppp/8/8/8/8/Q4PPP/6K1 w - - 0 1").unwrap())
+        .depth(depth)
+        .observer(Arc::clone(&counter))
+        .build();
+    run_job(job);
+
+    let counter = counter.lock().unwrap();
+    assert!(counter.deep_extensions > 0);
+    assert_eq!(counter.excess_extensions, 0);
+}
+
+#[test]
+fn improving_compares_with_last_eval_of_same_side() {
+    let mut stack = SearchStack::default();
+    let evals = [Some(20), Some(-10), None, Some(0), Some(10)];
+    for (ply, eval) in evals.into_iter().enumerate() {
+        stack[ply].static_eval = eval.map(Value::centipawn);
+    }
+
+    // There is nothing to compare with at the first plies
+    assert!(stack.improving(0));
+    assert!(stack.improving(1));
+    // Positions in check are never improving, and are skipped when comparing
+    assert!(!stack.improving(2));
+    assert!(stack.improving(3));
+    assert!(!stack.improving(4));
+}
+
+/// Searches `fen` with iterative deepening up to `depth`, returning the last
+/// evaluation and the total number of nodes searched.
+fn search_nodes(fen: &str, depth: i8, iir: bool) -> (SearchEvaluation, u64) {
+    let mut thread_pool = ThreadPool::new();
+    let job = SearchJob::default_builder()
+        .position(Position::from_fen(fen).unwrap())
+        .depth(depth)
+        .internal_iterative_reductions(iir)
+        .build();
+    let rx = thread_pool.run(job).unwrap();
+    let res = rx
+        .iter()
+        .filter_map(|info| match info {
+            SearchInfo::NewDepth {
+                evaluation, stats, ..
+            } => Some((evaluation, stats.nodes)),
+            SearchInfo::Finished(_) => None,
+        })
+        .last()
+        .unwrap();
+    thread_pool.wait().unwrap();
+    res
+}
+
+#[test]
+fn iir_searches_fewer_nodes_than_baseline() {
+    // The reductions pay off over the iterations, as the reduced searches
+    // leave TT moves for the next ones
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "2r3k1/pp3ppp/2n1b3/3p4/3P4/2PB1N2/P4PPP/4R1K1 w - - 0 20",
+    ];
+    let mut nodes = 0;
+    let mut baseline_nodes = 0;
+    for fen in fens {
+        nodes += search_nodes(fen, 9, true).1;
+        baseline_nodes += search_nodes(fen, 9, false).1;
+    }
+    assert!(nodes < baseline_nodes);
+
+    // Tactics are still found
+    let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+    let (evaluation, _) = search_nodes(fen, 6, true);
+    assert_eq!(evaluation.pv[0], mv!(D1 -> D8));
+    assert_eq!(evaluation.score, Value::mate_in_ply(1));
+}
@@ -4,12 +4,15 @@ use std::time::{Duration, Instant};
 
 use arc_swap::ArcSwap;
 use crossbeam::channel::{self, Receiver, Sender};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 use crate::eval::{Eval, StandardEval};
 use crate::search::SearchStats;
 use crate::types::{value, Move, Value};
 use crate::MoveGen;
 
+use super::move_picker::OrderingTables;
 use super::trace::{EmptyObserver, SearchObserver};
 use super::{SearchEvaluation, SearchJob, SearchResult, TranspositionTable};
 
@@ -47,7 +50,7 @@ pub const DEFAULT_THREADS: usize = 1;
 pub struct ThreadPool<E = StandardEval, O = EmptyObserver> {
     runner_thread: Option<std::thread::JoinHandle<SearchResult>>,
     worker_threads: Vec<std::thread::JoinHandle<()>>,
-    worker_txs: Arc<[Sender<WorkerJob<E, O>>]>,
+    worker_txs: Arc<[Sender<WorkerMessage<E, O>>]>,
     result_rx: Receiver<SearchResult>,
     result_tx: Sender<SearchResult>,
     kill_switch: Arc<AtomicBool>,
@@ -128,6 +131,7 @@ where
             return Err(SearchRunningError);
         }
 
+        self.send_to_workers(|| WorkerMessage::AgeTables);
         let runner = ThreadedRunner::new(
             job,
             info_tx,
@@ -165,6 +169,18 @@ where
         Ok(())
     }
 
+    /// Clears the move ordering tables of all threads, such as the history
+    /// and counter move tables. This can only be done when no search is
+    /// running. Returns an error if a search is running.
+    pub fn clear_history(&mut self) -> Result<(), SearchRunningError> {
+        if self.is_running() {
+            return Err(SearchRunningError);
+        }
+
+        self.send_to_workers(|| WorkerMessage::ClearTables);
+        Ok(())
+    }
+
     /// Sets the number of threads to use for the search. This can only be done
     /// when no search is running. Returns an error if a search is running.
     pub fn set_num_threads(&mut self, num_threads: usize) -> Result<(), SearchRunningError> {
@@ -202,6 +218,12 @@ where
             .map(|h| !h.is_finished())
             .unwrap_or(false)
     }
+
+    fn send_to_workers(&self, message: impl Fn() -> WorkerMessage<E, O>) {
+        for tx in self.worker_txs.iter() {
+            tx.send(message()).expect("worker channel shouldn't close");
+        }
+    }
 }
 
 impl<E, O> Drop for ThreadPool<E, O> {
@@ -219,7 +241,7 @@ impl Default for ThreadPool {
 struct ThreadedRunner<E, O> {
     job: SearchJob<E, O>,
     info_tx: InfoSender,
-    worker_txs: Arc<[Sender<WorkerJob<E, O>>]>,
+    worker_txs: Arc<[Sender<WorkerMessage<E, O>>]>,
     result_rx: Receiver<SearchResult>,
     kill_switch: Arc<AtomicBool>,
     t_table: Arc<ArcSwap<TranspositionTable>>,
@@ -237,7 +259,7 @@ where
     fn new(
         job: SearchJob<E, O>,
         info_tx: InfoSender,
-        worker_txs: Arc<[Sender<WorkerJob<E, O>>]>,
+        worker_txs: Arc<[Sender<WorkerMessage<E, O>>]>,
         result_rx: Receiver<SearchResult>,
         kill_switch: Arc<AtomicBool>,
         t_table: Arc<ArcSwap<TranspositionTable>>,
@@ -269,8 +291,13 @@ where
             let iteration_start = Instant::now();
             self.job.observer.on_depth(depth);
 
-            // Aspiration window search
-            let evaluation = if let Some(entry) = t_table.get(&self.job.position) {
+            // Aspiration window search. When playing with a skill, the root
+            // is searched with a full window to get exact scores for the best
+            // root moves
+            let aspiration_entry = t_table
+                .get(&self.job.position)
+                .filter(|_| self.job.skill.is_none());
+            let evaluation = if let Some(entry) = aspiration_entry {
                 let mut delta = 25i32;
                 let mut alpha = entry.score - Value::centipawn(delta as i16);
                 let mut beta = entry.score + Value::centipawn(delta as i16);
@@ -345,15 +372,20 @@ where
         }
 
         log::info!("Search finished, clearing t-table.");
-        let best_move = self
-            .result
-            .evaluation
-            .as_ref()
-            .map(|r| r.pv[0])
-            .unwrap_or_else(|| {
+        let best_move = match (self.job.skill, self.result.evaluation.clone()) {
+            (Some(skill), Some(evaluation)) if !evaluation.skill_candidates.is_empty() => {
+                let mut rng = match self.job.seed {
+                    Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+                    None => ChaCha8Rng::from_entropy(),
+                };
+                skill.pick_move(&evaluation.skill_candidates, &mut rng)
+            }
+            (_, Some(evaluation)) => evaluation.pv[0],
+            (_, None) => {
                 log::warn!("No best move found, returning first move.");
                 MoveGen::init().gen_all_moves(&self.job.position)[0]
-            });
+            }
+        };
         let info = SearchInfo::Finished(best_move);
         if self.info_tx.send(info).is_err() {
             log::warn!("Info channel closed.");
@@ -371,9 +403,13 @@ where
         for (i, tx) in self.worker_txs.iter().enumerate() {
             let mut search_job = self.job.clone();
             search_job.limits.depth = Some(depth);
+            // When playing with a skill, the first iteration is always
+            // completed to have candidate moves to pick from
+            let complete_iteration = depth == 1 && search_job.skill.is_some();
             search_job.limits.nodes = search_job
                 .limits
                 .nodes
+                .filter(|_| !complete_iteration)
                 .map(|n| n.saturating_sub(self.nodes[i]));
             search_job.worker_id = i;
             let job = WorkerJob {
@@ -382,7 +418,8 @@ where
                 search_job,
                 search_start: self.search_start,
             };
-            tx.send(job).expect("worker channel shouldn't close");
+            tx.send(WorkerMessage::Search(Box::new(job)))
+                .expect("worker channel shouldn't close");
         }
 
         let mut iter_evaluation = None;
@@ -410,8 +447,15 @@ struct WorkerJob<E, O> {
     search_start: Instant,
 }
 
+enum WorkerMessage<E, O> {
+    Search(Box<WorkerJob<E, O>>),
+    /// Ages the move ordering tables of the worker before a new search.
+    AgeTables,
+    ClearTables,
+}
+
 fn worker<E, O>(
-    job_rx: Receiver<WorkerJob<E, O>>,
+    job_rx: Receiver<WorkerMessage<E, O>>,
     result_tx: Sender<SearchResult>,
     kill_switch: Arc<AtomicBool>,
     t_table: Arc<ArcSwap<TranspositionTable>>,
@@ -420,13 +464,26 @@ fn worker<E, O>(
     E: Eval,
     O: SearchObserver,
 {
-    while let Ok(job) = job_rx.recv() {
+    let mut tables = OrderingTables::new();
+    while let Ok(message) = job_rx.recv() {
+        let job = match message {
+            WorkerMessage::Search(job) => job,
+            WorkerMessage::AgeTables => {
+                tables.age();
+                continue;
+            }
+            WorkerMessage::ClearTables => {
+                tables.clear();
+                continue;
+            }
+        };
         let res = job.search_job.search(
             job.alpha,
             job.beta,
             job.search_start,
             Arc::clone(&kill_switch),
             t_table.load_full(),
+            &mut tables,
         );
         let Ok(()) = result_tx.send(res) else {
             log::info!("Result channel closed, stopping worker {id}.");
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 
 use crate::types::{Move, Value};
 
-use super::{NodeType, SearchJob};
+use super::{NodeType, NonPv, SearchJob};
 
 impl<E, O: SearchObserver> SearchJob<E, O> {
     pub(super) fn on_node_enter<N: NodeType>(
@@ -26,6 +26,19 @@ impl<E, O: SearchObserver> SearchJob<E, O> {
         self.observer
             .on_node_exit::<N>(self.worker_id, Some(mv), ret.into(), score);
     }
+
+    pub(super) fn on_extension(&mut self, ply: usize, mv: Option<Move>) {
+        self.observer.on_extension(self.worker_id, ply, mv);
+    }
+
+    /// Records a move that was pruned without being searched, where `score`
+    /// is an upper bound on its score.
+    pub(super) fn on_pruned_move(&mut self, mv: Move, alpha: Value, score: Value, ret: ReturnKind) {
+        let new_alpha = -alpha.dec_mate() - Value::centipawn(1);
+        let new_beta = -alpha.dec_mate();
+        self.on_node_enter::<NonPv>(new_alpha, new_beta, mv, false);
+        self.on_node_exit::<NonPv>(mv, Some(((-score).dec_mate(), ret.into())));
+    }
 }
 
 pub trait SearchObserver {
@@ -50,6 +63,10 @@ pub trait SearchObserver {
         _score: Option<Value>,
     ) {
     }
+    /// Called when the search is extended by a ply at `ply` plies from the
+    /// root. `mv` is the singular move extended, or `None` if the side to
+    /// move is in check.
+    fn on_extension(&mut self, _worker_id: usize, _ply: usize, _mv: Option<Move>) {}
 }
 
 impl<T: SearchObserver> SearchObserver for Arc<Mutex<T>> {
@@ -87,6 +104,10 @@ impl<T: SearchObserver> SearchObserver for Arc<Mutex<T>> {
             .unwrap()
             .on_node_exit::<N>(worker_id, mv, ret, score)
     }
+
+    fn on_extension(&mut self, worker_id: usize, ply: usize, mv: Option<Move>) {
+        self.lock().unwrap().on_extension(worker_id, ply, mv)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -110,10 +131,18 @@ pub enum ReturnKind {
     TTLower(Move),
     Quiesce,
     NullMove,
+    MateDistancePruning,
+    Razoring,
+    ProbCut(Move),
+    MultiCut,
     ReverseFutilityPruning,
+    FutilityPruning,
+    LateMovePruning,
     Checkmate,
     Stalemate,
     RuleDraw,
+    Repetition,
+    Tablebase,
     Stopped,
 }
 
@@ -137,9 +166,17 @@ impl Display for ReturnKind {
             ReturnKind::Quiesce => write!(f, "Quiesce"),
             ReturnKind::ReverseFutilityPruning => write!(f, "RFP"),
             ReturnKind::NullMove => write!(f, "Null"),
+            ReturnKind::MateDistancePruning => write!(f, "MDP"),
+            ReturnKind::Razoring => write!(f, "Razoring"),
+            ReturnKind::ProbCut(mv) => write!(f, "ProbCut: {mv}"),
+            ReturnKind::MultiCut => write!(f, "Multi-Cut"),
+            ReturnKind::FutilityPruning => write!(f, "FP"),
+            ReturnKind::LateMovePruning => write!(f, "LMP"),
             ReturnKind::Checkmate => write!(f, "Checkmate"),
             ReturnKind::Stalemate => write!(f, "Stalemate"),
             ReturnKind::RuleDraw => write!(f, "Rule Draw"),
+            ReturnKind::Repetition => write!(f, "Repetition"),
+            ReturnKind::Tablebase => write!(f, "Tablebase"),
             ReturnKind::Stopped => write!(f, "Stopped"),
         }
     }
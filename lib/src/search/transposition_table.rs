@@ -52,7 +52,19 @@ impl TranspositionTable {
     /// entry.
     #[inline]
     pub fn insert(&self, position: &Position, entry: Entry) {
-        let key = position.zobrist;
+        self.insert_key(position.zobrist, entry);
+    }
+
+    /// Inserts `entry` for the search of `position` where `excluded` is not
+    /// searched. These entries are kept apart from the entries of the full
+    /// position, as the score may be worse when a move is left out.
+    #[inline]
+    pub fn insert_excluding(&self, position: &Position, excluded: Option<Move>, entry: Entry) {
+        self.insert_key(excluded_key(position, excluded), entry);
+    }
+
+    #[inline]
+    fn insert_key(&self, key: u64, entry: Entry) {
         let index = key as usize % self.capacity;
 
         // Safety: Modulo ensures that index is in bounds
@@ -84,7 +96,18 @@ impl TranspositionTable {
     /// Retrieves the entry for a given position.
     #[inline]
     pub fn get(&self, position: &Position) -> Option<Entry> {
-        let key = position.zobrist;
+        self.get_key(position.zobrist)
+    }
+
+    /// Retrieves the entry for the search of `position` where `excluded` is
+    /// not searched. See [`insert_excluding`](Self::insert_excluding).
+    #[inline]
+    pub fn get_excluding(&self, position: &Position, excluded: Option<Move>) -> Option<Entry> {
+        self.get_key(excluded_key(position, excluded))
+    }
+
+    #[inline]
+    fn get_key(&self, key: u64) -> Option<Entry> {
         let index = key as usize % self.capacity;
 
         // Safety: Modulo ensures that index is in bounds
@@ -124,6 +147,17 @@ impl TranspositionTable {
     }
 }
 
+/// Returns the key of `position` when `excluded` is not searched, which is the
+/// zobrist key if no move is excluded.
+#[inline]
+fn excluded_key(position: &Position, excluded: Option<Move>) -> u64 {
+    match excluded {
+        // Spread the bits of the move over the key
+        Some(mv) => position.zobrist ^ (mv.into_inner() as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        None => position.zobrist,
+    }
+}
+
 impl Default for TranspositionTable {
     fn default() -> Self {
         Self::new()
@@ -168,7 +202,7 @@ impl Entry {
         Some(Entry::new(score, best_move, bound, depth))
     }
 
-    fn to_u64(&self) -> u64 {
+    fn to_u64(self) -> u64 {
         let mut res = self.score.into_inner() as u16 as u64;
         res |= (self.best_move.into_inner() as u64) << 16;
         res |= (self.bound as u64) << 32;
@@ -0,0 +1,490 @@
+//! Probing of Syzygy endgame tablebases.
+//!
+//! A [`Tablebase`] is opened from one or more directories containing `.rtbw`
+//! (win/draw/loss) and `.rtbz` (distance to zeroing) files. Only the names of
+//! the files are read when opening, the files themselves are memory mapped the
+//! first time a position with their material is probed.
+//!
+//! WDL values take the fifty-move rule into account: a cursed win is a win
+//! that is a draw under the fifty-move rule, and a blessed loss similarly. The
+//! tables assume that the fifty-move counter is zero and that there are no
+//! castling rights.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use table::{Table, TableBoard, TableKind, MAX_PIECES};
+
+use crate::collections::MoveList;
+use crate::position::Pieces;
+use crate::types::{Color, Move, Piece, PieceKind};
+use crate::{MoveGen, Position};
+
+mod table;
+#[cfg(test)]
+mod tests;
+
+/// The number of pieces of each kind, indexed by [`Color`] and then
+/// [`PieceKind`].
+type MaterialKey = [[u8; 6]; 2];
+
+/// The result of a position assuming optimal play from both sides.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss = -2,
+    /// A loss that is a draw under the fifty-move rule.
+    BlessedLoss = -1,
+    Draw = 0,
+    /// A win that is a draw under the fifty-move rule.
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Option<Self> {
+        match value {
+            -2 => Some(Self::Loss),
+            -1 => Some(Self::BlessedLoss),
+            0 => Some(Self::Draw),
+            1 => Some(Self::CursedWin),
+            2 => Some(Self::Win),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn signum(self) -> i32 {
+        (self as i32).signum()
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::from_value(-(self as i32)).unwrap()
+    }
+}
+
+/// The reason a table lookup failed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ProbeFail {
+    /// The table is missing or invalid.
+    Missing,
+    /// The DTZ table only stores the other side to move.
+    WrongSide,
+}
+
+/// The tables for one material signature.
+struct TableEntry {
+    /// The material with the first side of the file name as white.
+    key: MaterialKey,
+    /// The material with the first side of the file name as black.
+    key2: MaterialKey,
+    piece_count: usize,
+    has_pawns: bool,
+    /// Whether any side has a single piece of a kind other than the king.
+    has_unique_pieces: bool,
+    /// The number of pawns of the leading color and the other color.
+    pawn_count: [u8; 2],
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+impl TableEntry {
+    fn new(key: MaterialKey, wdl_path: PathBuf, dtz_path: Option<PathBuf>) -> Self {
+        let [white, black] = key;
+        let pawns = PieceKind::Pawn as usize;
+        // The leading color is the one with fewer pawns, if both have pawns
+        let white_leads = black[pawns] == 0 || (white[pawns] > 0 && black[pawns] >= white[pawns]);
+        let pawn_count = if white_leads {
+            [white[pawns], black[pawns]]
+        } else {
+            [black[pawns], white[pawns]]
+        };
+        let has_unique_pieces = key.iter().any(|counts| {
+            counts
+                .iter()
+                .enumerate()
+                .any(|(kind, &count)| kind != PieceKind::King as usize && count == 1)
+        });
+
+        Self {
+            key,
+            key2: [black, white],
+            piece_count: key.iter().flatten().map(|&n| n as usize).sum(),
+            has_pawns: white[pawns] + black[pawns] > 0,
+            has_unique_pieces,
+            pawn_count,
+            wdl_path,
+            dtz_path,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        }
+    }
+
+    /// Returns the table of the given kind, memory mapping it on first use.
+    fn table(&self, kind: TableKind) -> Option<&Table> {
+        let (cell, path) = match kind {
+            TableKind::Wdl => (&self.wdl, Some(&self.wdl_path)),
+            TableKind::Dtz => (&self.dtz, self.dtz_path.as_ref()),
+        };
+        cell.get_or_init(|| {
+            let path = path?;
+            Table::open(path, kind, self)
+                .map_err(|err| log::warn!("failed to open {}: {err}", path.display()))
+                .ok()
+        })
+        .as_ref()
+    }
+}
+
+/// A set of Syzygy tablebases.
+pub struct Tablebase {
+    /// The tables indexed by both their keys.
+    entries: HashMap<MaterialKey, Arc<TableEntry>>,
+    max_pieces: usize,
+    move_gen: MoveGen,
+}
+
+impl Tablebase {
+    /// Finds the tables in `paths`, a list of directories separated like the
+    /// `PATH` environment variable. Only tables with a WDL file are used.
+    pub fn open(paths: impl AsRef<OsStr>) -> Result<Self, LoadTablebaseError> {
+        let mut files: HashMap<String, [Option<PathBuf>; 2]> = HashMap::new();
+        for dir in std::env::split_paths(&paths) {
+            for file in fs::read_dir(dir)? {
+                let path = file?.path();
+                let (Some(stem), Some(ext)) = (path.file_stem(), path.extension()) else {
+                    continue;
+                };
+                let i = match ext.to_str() {
+                    Some("rtbw") => 0,
+                    Some("rtbz") => 1,
+                    _ => continue,
+                };
+                let Some(stem) = stem.to_str() else {
+                    continue;
+                };
+                files.entry(stem.to_string()).or_default()[i].get_or_insert(path);
+            }
+        }
+
+        let mut entries = HashMap::new();
+        let mut max_pieces = 0;
+        for (name, [wdl_path, dtz_path]) in files {
+            let (Some(key), Some(wdl_path)) = (parse_table_name(&name), wdl_path) else {
+                continue;
+            };
+            let entry = Arc::new(TableEntry::new(key, wdl_path, dtz_path));
+            max_pieces = max_pieces.max(entry.piece_count);
+            entries.insert(entry.key2, Arc::clone(&entry));
+            entries.insert(entry.key, entry);
+        }
+
+        Ok(Self {
+            entries,
+            max_pieces,
+            move_gen: MoveGen::init(),
+        })
+    }
+
+    /// Returns the largest number of pieces, including kings, of any table.
+    #[inline]
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Returns the number of tables found.
+    pub fn num_tables(&self) -> usize {
+        // Symmetric tables are only in the map once
+        let symmetric = self.entries.values().filter(|e| e.key == e.key2).count();
+        (self.entries.len() + symmetric) / 2
+    }
+
+    /// Returns whether positions with the pieces and castling rights of
+    /// `position` can be in the tables.
+    #[inline]
+    pub fn can_probe(&self, position: &Position) -> bool {
+        position.pieces.count() <= self.max_pieces && u8::from(position.castling) == 0
+    }
+
+    /// Returns the WDL value of `position` from the perspective of the side to
+    /// move, or `None` if the tables needed are missing. The position is only
+    /// changed temporarily.
+    pub fn probe_wdl(&self, position: &mut Position) -> Option<Wdl> {
+        if !self.can_probe(position) {
+            return None;
+        }
+        self.search(position, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Returns the number of plies to the next capture or pawn move with
+    /// optimal play, signed like the WDL value of `position`. Cursed wins and
+    /// blessed losses have 100 added to the distance, and draws are 0. Returns
+    /// `None` if the tables needed are missing.
+    pub fn probe_dtz(&self, position: &mut Position) -> Option<i32> {
+        if !self.can_probe(position) {
+            return None;
+        }
+
+        let (wdl, zeroing_best) = self.search(position, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        // The table doesn't store the correct value if the best move zeroes
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        match self.probe_table(position, TableKind::Dtz, wdl) {
+            Ok(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                return Some((dtz + 100 * cursed as i32) * wdl.signum());
+            }
+            Err(ProbeFail::Missing) => return None,
+            Err(ProbeFail::WrongSide) => {}
+        }
+
+        // The table stores the other side to move, so find the best value
+        // after a move instead
+        let mut min_dtz = i32::MAX;
+        for mv in self.move_gen.gen_all_moves(position) {
+            let zeroing = self.is_zeroing(position, mv);
+            position.make_move(mv);
+            let dtz = if zeroing {
+                self.search(position, false)
+                    .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+            } else {
+                self.probe_dtz(position).map(|dtz| -dtz)
+            };
+            let is_mate = self.is_mate(position);
+            position.unmake_move();
+
+            let mut dtz = dtz?;
+            if dtz == 1 && is_mate {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+        }
+
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    /// Returns the moves of `moves` that are ranked best by the tables, i.e.
+    /// that keep the result of `position` and, if close to the fifty-move
+    /// rule, zero the counter soonest. DTZ tables are used if available,
+    /// otherwise only WDL tables. Returns `None` if the tables needed are
+    /// missing.
+    pub fn root_moves(&self, position: &mut Position, moves: &[Move]) -> Option<MoveList> {
+        if !self.can_probe(position) || moves.is_empty() {
+            return None;
+        }
+
+        let ranks = self
+            .rank_root_moves_dtz(position, moves)
+            .or_else(|| self.rank_root_moves_wdl(position, moves))?;
+        let best = *ranks.iter().max()?;
+        Some(
+            moves
+                .iter()
+                .zip(ranks)
+                .filter(|&(_, rank)| rank == best)
+                .map(|(&mv, _)| mv)
+                .collect(),
+        )
+    }
+
+    fn rank_root_moves_dtz(&self, position: &mut Position, moves: &[Move]) -> Option<Vec<i32>> {
+        let ply_clock = position.ply_clock() as i32;
+        let mut ranks = Vec::with_capacity(moves.len());
+        for &mv in moves {
+            position.make_move(mv);
+            // The distance counted from the root position
+            let dtz = if position.ply_clock() == 0 {
+                self.probe_wdl(position).map(|wdl| dtz_before_zeroing(-wdl))
+            } else {
+                self.probe_dtz(position)
+                    .map(|dtz| -dtz)
+                    .map(|dtz| dtz + dtz.signum())
+            };
+            let is_mate = self.is_mate(position);
+            position.unmake_move();
+
+            let dtz = match dtz? {
+                2 if is_mate => 1,
+                dtz => dtz,
+            };
+            // Wins are ranked equally, unless the fifty-move rule is in sight
+            let rank = if dtz > 0 {
+                if dtz + ply_clock <= 99 {
+                    1000
+                } else {
+                    1000 - (dtz + ply_clock)
+                }
+            } else if dtz < 0 {
+                if -dtz * 2 + ply_clock < 100 {
+                    -1000
+                } else {
+                    -1000 + (-dtz + ply_clock)
+                }
+            } else {
+                0
+            };
+            ranks.push(rank);
+        }
+        Some(ranks)
+    }
+
+    fn rank_root_moves_wdl(&self, position: &mut Position, moves: &[Move]) -> Option<Vec<i32>> {
+        let mut ranks = Vec::with_capacity(moves.len());
+        for &mv in moves {
+            position.make_move(mv);
+            let wdl = self.probe_wdl(position);
+            position.unmake_move();
+
+            ranks.push(match -wdl? {
+                Wdl::Loss => -1000,
+                Wdl::BlessedLoss => -899,
+                Wdl::Draw => 0,
+                Wdl::CursedWin => 899,
+                Wdl::Win => 1000,
+            });
+        }
+        Some(ranks)
+    }
+
+    /// Returns the WDL value of `position` and whether the best move is a
+    /// capture (or pawn move, if `zeroing_moves`). The tables don't store the
+    /// correct value for positions where a capture is best, so these have to
+    /// be searched.
+    fn search(&self, position: &mut Position, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = self.move_gen.gen_all_moves(position);
+        let mut best = Wdl::Loss;
+        let mut move_count = 0;
+        for &mv in &moves {
+            let searched = mv.capture() || (zeroing_moves && self.is_zeroing(position, mv));
+            if !searched {
+                continue;
+            }
+
+            move_count += 1;
+            position.make_move(mv);
+            let res = self.search(position, false);
+            position.unmake_move();
+            let wdl = -res?.0;
+
+            if wdl > best {
+                best = wdl;
+                if wdl >= Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // If all moves were searched the table value is not needed, and
+        // might be wrong, e.g. if en passant is possible
+        let all_searched = move_count > 0 && move_count == moves.len();
+        let wdl = if all_searched {
+            best
+        } else {
+            let value = self.probe_table(position, TableKind::Wdl, Wdl::Draw).ok()?;
+            Wdl::from_value(value)?
+        };
+
+        if best >= wdl {
+            Some((best, best > Wdl::Draw || all_searched))
+        } else {
+            Some((wdl, false))
+        }
+    }
+
+    /// Looks up `position` in the table of the given kind.
+    fn probe_table(
+        &self,
+        position: &Position,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Result<i32, ProbeFail> {
+        // KvK is not stored
+        if position.pieces.count() == 2 {
+            return Ok(0);
+        }
+
+        let key = material_key(&position.pieces);
+        let entry = self.entries.get(&key).ok_or(ProbeFail::Missing)?;
+        let table = entry.table(kind).ok_or(ProbeFail::Missing)?;
+        let board = TableBoard::new(position, key, entry);
+        table.probe(entry, &board, wdl)
+    }
+
+    /// Returns whether `mv` is a capture or pawn move.
+    #[inline]
+    fn is_zeroing(&self, position: &Position, mv: Move) -> bool {
+        mv.capture() || position.pieces.get(mv.from()).unwrap().kind() == PieceKind::Pawn
+    }
+
+    #[inline]
+    fn is_mate(&self, position: &Position) -> bool {
+        let (moves, check) = self.move_gen.gen_all_moves_and_check(position);
+        check && moves.is_empty()
+    }
+}
+
+/// Returns the distance to zeroing of the move before a zeroing move, given
+/// the WDL value of the position before it.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::Draw => 0,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+    }
+}
+
+fn material_key(pieces: &Pieces) -> MaterialKey {
+    let mut key = [[0; 6]; 2];
+    for pce in Piece::iter() {
+        key[pce.color() as usize][pce.kind() as usize] = pieces.get_bb(pce).len() as u8;
+    }
+    key
+}
+
+/// Parses the material of a table name like `KRPvKR`.
+fn parse_table_name(name: &str) -> Option<MaterialKey> {
+    let (white, black) = name.split_once('v')?;
+    let mut key = [[0; 6]; 2];
+    for (color, side) in [(Color::White, white), (Color::Black, black)] {
+        for ch in side.chars() {
+            let kind = PieceKind::try_from(ch)
+                .ok()
+                .filter(|_| ch.is_ascii_uppercase())?;
+            key[color as usize][kind as usize] += 1;
+        }
+        if key[color as usize][PieceKind::King as usize] != 1 {
+            return None;
+        }
+    }
+    let piece_count: usize = key.iter().flatten().map(|&n| n as usize).sum();
+    (piece_count <= MAX_PIECES).then_some(key)
+}
+
+/// An error that can occur when opening a [`Tablebase`].
+#[derive(thiserror::Error, Debug)]
+pub enum LoadTablebaseError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
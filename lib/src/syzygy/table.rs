@@ -0,0 +1,827 @@
+//! Parsing and decompression of the Syzygy table files.
+//!
+//! The layout of the files and the indexing scheme follow the reference
+//! probing code by Ronald de Man. Squares are handled as raw indices from A1
+//! and pieces as the codes used in the files: 1 to 6 for the white pawn,
+//! knight, bishop, rook, queen and king, and the same plus 8 for black.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use memmap2::Mmap;
+
+use crate::types::{Color, Piece, PieceKind};
+use crate::Position;
+
+use super::{MaterialKey, ProbeFail, TableEntry, Wdl};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/// Flags of a [`PairsData`].
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// The maximum number of pieces in a table.
+pub const MAX_PIECES: usize = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+/// Lookup tables used for computing the index of a position.
+struct Encoding {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+lazy_static! {
+    static ref ENCODING: Encoding = Encoding::new();
+}
+
+impl Encoding {
+    fn new() -> Self {
+        let mut enc = Self {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // Squares below the A1-H8 diagonal
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                enc.map_b1h1h7[sq as usize] = code;
+                code += 1;
+            }
+        }
+
+        // The A1-D1-D4 triangle, with the diagonal squares last
+        let mut diagonal = Vec::new();
+        code = 0;
+        for sq in 0..=27 {
+            if off_a1h8(sq) < 0 && file(sq) <= 3 {
+                enc.map_a1d1d4[sq as usize] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 && file(sq) <= 3 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            enc.map_a1d1d4[sq as usize] = code;
+            code += 1;
+        }
+
+        // The 462 legal placements of two kings with the first king in the
+        // A1-D1-D4 triangle, with both kings on the diagonal last
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for idx in 0..10 {
+            for sq1 in 0..=27 {
+                if enc.map_a1d1d4[sq1 as usize] != idx || (idx == 0 && sq1 != 1) {
+                    continue;
+                }
+                for sq2 in 0..64 {
+                    // Skip adjacent kings, and the second king above the
+                    // diagonal if the first is on it
+                    if king_dist(sq1, sq2) <= 1 || (off_a1h8(sq1) == 0 && off_a1h8(sq2) > 0) {
+                        continue;
+                    } else if off_a1h8(sq1) == 0 && off_a1h8(sq2) == 0 {
+                        both_on_diagonal.push((idx, sq2));
+                    } else {
+                        enc.map_kk[idx][sq2 as usize] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, sq2) in both_on_diagonal {
+            enc.map_kk[idx][sq2 as usize] = code;
+            code += 1;
+        }
+
+        enc.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                enc.binomial[k][n] = if k > 0 { enc.binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { enc.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // The leading pawn is the one with the highest value, i.e. the one
+        // closest to the edge and with the lowest rank
+        let mut available = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut idx = 0;
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        enc.map_pawns[sq] = available;
+                        enc.map_pawns[sq ^ 7] = available - 1;
+                        available = available.saturating_sub(2);
+                    }
+                    enc.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += enc.binomial[lead_pawns - 1][enc.map_pawns[sq]];
+                }
+                enc.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+
+        enc
+    }
+}
+
+/// Returns the number of squares `sq` is above the A1-H8 diagonal.
+#[inline]
+fn off_a1h8(sq: u8) -> i8 {
+    rank(sq) as i8 - file(sq) as i8
+}
+
+#[inline]
+fn file(sq: u8) -> u8 {
+    sq & 7
+}
+
+#[inline]
+fn rank(sq: u8) -> u8 {
+    sq >> 3
+}
+
+#[inline]
+fn king_dist(a: u8, b: u8) -> u8 {
+    file(a).abs_diff(file(b)).max(rank(a).abs_diff(rank(b)))
+}
+
+/// Returns the code of `pce` used in the table files.
+#[inline]
+pub fn piece_code(pce: Piece) -> u8 {
+    let kind = match pce.kind() {
+        PieceKind::Pawn => 1,
+        PieceKind::Knight => 2,
+        PieceKind::Bishop => 3,
+        PieceKind::Rook => 4,
+        PieceKind::Queen => 5,
+        PieceKind::King => 6,
+    };
+    match pce.color() {
+        Color::White => kind,
+        Color::Black => kind + 8,
+    }
+}
+
+/// Indexing and compression information for one side to move and, for
+/// tables with pawns, one file of the leading pawn.
+#[derive(Clone, Default, Debug)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+    /// The minimum length in bits of the Huffman symbols, or the value of
+    /// every position if the [`FLAG_SINGLE_VALUE`] flag is set.
+    min_sym_len: u8,
+    /// Offset of the lowest symbol of each length.
+    lowest_sym: usize,
+    /// Offset of the pairs of symbols each symbol expands to.
+    btree: usize,
+    /// Offset of the number of values (minus one) in each block.
+    block_length: usize,
+    block_length_size: usize,
+    /// Offset of the entries pointing into the blocks.
+    sparse_index: usize,
+    sparse_index_size: usize,
+    /// Offset of the compressed data.
+    data: usize,
+    /// The lowest symbol of each length, left-aligned to 64 bits.
+    base64: Vec<u64>,
+    /// The number of values (minus one) each symbol represents.
+    sym_len: Vec<u8>,
+    /// The pieces in the order they are encoded.
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    /// The sizes of the groups of pieces encoded together, zero-terminated.
+    group_len: [usize; MAX_PIECES + 1],
+    /// Offsets into the DTZ value map for each WDL value.
+    map_idx: [usize; 4],
+}
+
+/// A memory mapped WDL or DTZ table file.
+pub struct Table {
+    mmap: Mmap,
+    kind: TableKind,
+    sides: usize,
+    files: usize,
+    /// Indexed by file and then side to move.
+    pairs: Vec<PairsData>,
+    /// Offset of the DTZ value map.
+    dtz_map: usize,
+}
+
+impl Table {
+    /// Memory maps and parses the table at `path` for the material in
+    /// `entry`. Returns an error if the file is not a valid table.
+    pub fn open(path: &Path, kind: TableKind, entry: &TableEntry) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: Table files are not expected to be modified while in use
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        if mmap.len() < 5 || mmap[..4] != magic {
+            return Err(invalid("invalid magic bytes"));
+        }
+        let split = entry.key != entry.key2;
+        if (mmap[4] & 1 != 0) != split || (mmap[4] & 2 != 0) != entry.has_pawns {
+            return Err(invalid("table does not match the file name"));
+        }
+
+        let sides = if kind == TableKind::Wdl && split {
+            2
+        } else {
+            1
+        };
+        let files = if entry.has_pawns { 4 } else { 1 };
+        let mut table = Self {
+            mmap,
+            kind,
+            sides,
+            files,
+            pairs: vec![PairsData::default(); sides * files],
+            dtz_map: 0,
+        };
+        table
+            .parse(entry)
+            .ok_or_else(|| invalid("table is truncated"))?;
+        Ok(table)
+    }
+
+    fn parse(&mut self, entry: &TableEntry) -> Option<()> {
+        let data = &self.mmap[..];
+        let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut p = 5;
+
+        for f in 0..self.files {
+            let byte = |i: usize| data.get(i).copied();
+            let order = [
+                [
+                    byte(p)? & 0xF,
+                    if both_pawns { byte(p + 1)? & 0xF } else { 0xF },
+                ],
+                [
+                    byte(p)? >> 4,
+                    if both_pawns { byte(p + 1)? >> 4 } else { 0xF },
+                ],
+            ];
+            p += 1 + both_pawns as usize;
+
+            for k in 0..entry.piece_count {
+                let pieces = byte(p)?;
+                for i in 0..self.sides {
+                    self.pairs[f * self.sides + i].pieces[k] =
+                        if i == 1 { pieces >> 4 } else { pieces & 0xF };
+                }
+                p += 1;
+            }
+
+            for (i, order) in order.into_iter().take(self.sides).enumerate() {
+                set_groups(&mut self.pairs[f * self.sides + i], entry, order, f);
+            }
+        }
+        p += p & 1;
+
+        for d in &mut self.pairs {
+            p = set_sizes(d, data, p)?;
+        }
+
+        if self.kind == TableKind::Dtz {
+            self.dtz_map = p;
+            for f in 0..self.files {
+                let d = &mut self.pairs[f * self.sides];
+                if d.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if d.flags & FLAG_WIDE != 0 {
+                    p += p & 1;
+                    for i in 0..4 {
+                        d.map_idx[i] = (p - self.dtz_map) / 2 + 1;
+                        p += 2 * read_u16_le(data, p)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = p - self.dtz_map + 1;
+                        p += *data.get(p)? as usize + 1;
+                    }
+                }
+            }
+            p += p & 1;
+        }
+
+        for d in &mut self.pairs {
+            d.sparse_index = p;
+            p += d.sparse_index_size * 6;
+        }
+        for d in &mut self.pairs {
+            d.block_length = p;
+            p += d.block_length_size * 2;
+        }
+        for d in &mut self.pairs {
+            p = (p + 0x3F) & !0x3F;
+            d.data = p;
+            p += d.num_blocks * d.block_size;
+        }
+
+        (p <= data.len()).then_some(())
+    }
+
+    #[inline]
+    fn pairs(&self, stm: usize, file: usize) -> &PairsData {
+        &self.pairs[file * self.sides + stm % self.sides]
+    }
+
+    /// Looks up the position given by the pieces and squares in `board`,
+    /// which should be from the perspective of the stronger side as stored in
+    /// the table. For DTZ tables, `wdl` is the WDL value of the position.
+    /// Returns the WDL value for WDL tables, and the unsigned distance to
+    /// zeroing in plies for DTZ tables.
+    pub fn probe(
+        &self,
+        entry: &TableEntry,
+        board: &TableBoard,
+        wdl: Wdl,
+    ) -> Result<i32, ProbeFail> {
+        let enc = &*ENCODING;
+        let mut squares = [0u8; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_cnt = 0;
+        let mut tb_file = 0;
+
+        // Tables with pawns are split by the file of the leading pawn
+        let lead = entry.has_pawns.then(|| self.pairs(0, 0).pieces[0]);
+        if let Some(lead) = lead {
+            for &(pce, sq) in board.pieces.iter().filter(|&&(pce, _)| pce == lead) {
+                squares[size] = sq;
+                pieces[size] = pce;
+                size += 1;
+            }
+            lead_pawns_cnt = size;
+            let lead_idx = (0..lead_pawns_cnt)
+                .max_by_key(|&i| enc.map_pawns[squares[i] as usize])
+                .unwrap();
+            squares.swap(0, lead_idx);
+            tb_file = match file(squares[0]) {
+                f @ 0..=3 => f as usize,
+                f => 7 - f as usize,
+            };
+        }
+
+        // DTZ tables only store one side to move
+        if self.kind == TableKind::Dtz {
+            let flags = self.pairs(0, tb_file).flags;
+            let symmetric = entry.key == entry.key2 && !entry.has_pawns;
+            if (flags & FLAG_STM) as usize != board.stm && !symmetric {
+                return Err(ProbeFail::WrongSide);
+            }
+        }
+
+        for &(pce, sq) in &board.pieces {
+            if Some(pce) == lead {
+                continue;
+            }
+            squares[size] = sq;
+            pieces[size] = pce;
+            size += 1;
+        }
+
+        let d = self.pairs(board.stm, tb_file);
+
+        // Order the pieces like in the table
+        for i in lead_pawns_cnt..size - 1 {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror the leading piece to the A1-D1-D4 triangle
+        if file(squares[0]) > 3 {
+            squares[..size].iter_mut().for_each(|sq| *sq ^= 7);
+        }
+
+        let mut idx;
+        if entry.has_pawns {
+            idx = enc.lead_pawn_idx[lead_pawns_cnt][squares[0] as usize];
+            squares[1..lead_pawns_cnt].sort_by_key(|&sq| enc.map_pawns[sq as usize]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns_cnt).skip(1) {
+                idx += enc.binomial[i][enc.map_pawns[sq as usize]];
+            }
+        } else {
+            if rank(squares[0]) > 3 {
+                squares[..size].iter_mut().for_each(|sq| *sq ^= 56);
+            }
+
+            // Mirror the first piece of the leading group not on the
+            // diagonal to below it
+            for i in 0..d.group_len[0] {
+                match off_a1h8(squares[i]) {
+                    0 => continue,
+                    off if off > 0 => squares[i..size]
+                        .iter_mut()
+                        .for_each(|sq| *sq = ((*sq >> 3) | (*sq << 3)) & 63),
+                    _ => {}
+                }
+                break;
+            }
+
+            if entry.has_unique_pieces {
+                let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                let (s0, s1, s2) = (s0 as u64, s1 as u64, s2 as u64);
+                let rank = |sq: u64| sq >> 3;
+
+                idx = if off_a1h8(squares[0]) != 0 {
+                    (enc.map_a1d1d4[s0 as usize] as u64 * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + rank(s0) * 28 + enc.map_b1h1h7[s1 as usize] as u64) * 62 + s2
+                        - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(s0) * 7 * 28
+                        + (rank(s1) - adjust1) * 28
+                        + enc.map_b1h1h7[s2 as usize] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(s0) * 7 * 6
+                        + (rank(s1) - adjust1) * 6
+                        + (rank(s2) - adjust2)
+                };
+            } else {
+                idx = enc.map_kk[enc.map_a1d1d4[squares[0] as usize]][squares[1] as usize];
+            }
+        }
+
+        // Encode the remaining groups
+        idx *= d.group_idx[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let sq = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| sq > s).count();
+                n += enc.binomial[i + 1][sq as usize - adjust - 8 * remaining_pawns as usize];
+            }
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        let value = self.decompress(d, idx).ok_or(ProbeFail::Missing)?;
+        Ok(match self.kind {
+            TableKind::Wdl => value - 2,
+            TableKind::Dtz => self.map_dtz(tb_file, value, wdl),
+        })
+    }
+
+    /// Maps a value stored in a DTZ table to the distance to zeroing in plies.
+    fn map_dtz(&self, file: usize, mut value: i32, wdl: Wdl) -> i32 {
+        let d = self.pairs(0, file);
+        let map_idx = match wdl {
+            Wdl::Win => d.map_idx[0],
+            Wdl::Loss => d.map_idx[1],
+            Wdl::CursedWin => d.map_idx[2],
+            Wdl::BlessedLoss => d.map_idx[3],
+            Wdl::Draw => d.map_idx[0],
+        };
+        if d.flags & FLAG_MAPPED != 0 {
+            let data = &self.mmap[..];
+            value = if d.flags & FLAG_WIDE != 0 {
+                let offset = self.dtz_map + 2 * (map_idx + value as usize);
+                read_u16_le(data, offset).unwrap_or(0) as i32
+            } else {
+                data.get(self.dtz_map + map_idx + value as usize)
+                    .copied()
+                    .unwrap_or(0) as i32
+            };
+        }
+
+        // Convert moves to plies
+        if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss
+        {
+            value *= 2;
+        }
+        value + 1
+    }
+
+    /// Returns the value stored at `idx`. The values are Huffman coded symbols
+    /// each expanding to a sequence of values by recursive pairing.
+    fn decompress(&self, d: &PairsData, idx: u64) -> Option<i32> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(d.min_sym_len as i32);
+        }
+        let data = &self.mmap[..];
+        let block_length = |block: usize| read_u16_le(data, d.block_length + 2 * block);
+
+        // Find the block containing idx using the sparse index, which points
+        // to the middle of every span of values
+        let k = (idx / d.span as u64) as usize;
+        let entry = d.sparse_index + 6 * k;
+        let mut block = read_u32_le(data, entry)? as usize;
+        let mut offset = read_u16_le(data, entry + 4)? as i64;
+        offset += (idx % d.span as u64) as i64 - (d.span / 2) as i64;
+
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? as i64 + 1;
+        }
+        while offset > block_length(block)? as i64 {
+            offset -= block_length(block)? as i64 + 1;
+            block += 1;
+        }
+
+        // Read symbols until reaching the one containing the value
+        let mut ptr = d.data + block * d.block_size;
+        let mut buf = read_u64_be(data, ptr)?;
+        ptr += 8;
+        let mut buf_size = 64;
+        let min_len = d.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buf < *d.base64.get(len)? {
+                len += 1;
+            }
+            sym = ((buf - d.base64[len]) >> (64 - len - min_len)) as u16;
+            sym = sym.wrapping_add(read_u16_le(data, d.lowest_sym + 2 * len)?);
+            let sym_len = *d.sym_len.get(sym as usize)? as i64;
+            if offset < sym_len + 1 {
+                break;
+            }
+            offset -= sym_len + 1;
+            len += min_len;
+            buf <<= len;
+            buf_size -= len;
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (read_u32_be(data, ptr).unwrap_or(0) as u64) << (64 - buf_size);
+                ptr += 4;
+            }
+        }
+
+        // Expand the symbol until reaching a single value
+        while d.sym_len[sym as usize] != 0 {
+            let (left, right) = read_pair(data, d.btree, sym)?;
+            let left_len = *d.sym_len.get(left as usize)? as i64;
+            if offset < left_len + 1 {
+                sym = left;
+            } else {
+                offset -= left_len + 1;
+                sym = right;
+            }
+        }
+        Some(read_pair(data, d.btree, sym)?.0 as i32)
+    }
+}
+
+/// The pieces of a position mapped to the perspective stored in a table.
+pub struct TableBoard {
+    /// The piece codes and squares.
+    pub pieces: Vec<(u8, u8)>,
+    /// The side to move, 0 for the side stored first in the table.
+    pub stm: usize,
+}
+
+impl TableBoard {
+    pub fn new(position: &Position, key: MaterialKey, entry: &TableEntry) -> Self {
+        // Tables store positions with the first side of the file name as
+        // white, and symmetric tables only store white to move
+        let symmetric_black = entry.key == entry.key2 && position.to_move == Color::Black;
+        let flip = symmetric_black || key != entry.key;
+        let pieces = position
+            .pieces
+            .occupied()
+            .into_iter()
+            .map(|sq| {
+                let code = piece_code(position.pieces.get(sq).unwrap());
+                if flip {
+                    (code ^ 8, sq as u8 ^ 56)
+                } else {
+                    (code, sq as u8)
+                }
+            })
+            .collect();
+        Self {
+            pieces,
+            stm: flip as usize ^ (position.to_move == Color::Black) as usize,
+        }
+    }
+}
+
+/// Splits the pieces into the groups that are encoded together and computes
+/// the factor each group's index is multiplied by.
+fn set_groups(d: &mut PairsData, entry: &TableEntry, order: [u8; 2], file: usize) {
+    let enc = &*ENCODING;
+    let mut n = 0;
+    let mut first_len: i32 = if entry.has_pawns {
+        0
+    } else if entry.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    d.group_len[0] = 1;
+    for i in 1..entry.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let both_pawns = entry.has_pawns && entry.pawn_count[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+    let mut idx = 1u64;
+    let mut k = 0u8;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if entry.has_pawns {
+                enc.lead_pawns_size[d.group_len[0]][file]
+            } else if entry.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= enc.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= enc.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+/// Reads the compression parameters starting at `p` and returns the offset
+/// after them.
+fn set_sizes(d: &mut PairsData, data: &[u8], mut p: usize) -> Option<usize> {
+    d.flags = *data.get(p)?;
+    p += 1;
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = *data.get(p)?;
+        return Some(p + 1);
+    }
+
+    let groups = d.group_len.iter().position(|&len| len == 0).unwrap();
+    let tb_size = d.group_idx[groups];
+
+    d.block_size = 1 << *data.get(p)?;
+    d.span = 1 << *data.get(p + 1)?;
+    d.sparse_index_size = tb_size.div_ceil(d.span as u64) as usize;
+    let padding = *data.get(p + 2)? as usize;
+    d.num_blocks = read_u32_le(data, p + 3)? as usize;
+    d.block_length_size = d.num_blocks + padding;
+    let max_sym_len = *data.get(p + 7)?;
+    d.min_sym_len = *data.get(p + 8)?;
+    p += 9;
+    if max_sym_len < d.min_sym_len || d.min_sym_len == 0 {
+        return None;
+    }
+    d.lowest_sym = p;
+
+    // Longer symbols have lower values, so base64[i] >= base64[i + 1]
+    let lengths = (max_sym_len - d.min_sym_len) as usize + 1;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        let lowest = read_u16_le(data, d.lowest_sym + 2 * i)? as u64;
+        let next_lowest = read_u16_le(data, d.lowest_sym + 2 * (i + 1))? as u64;
+        d.base64[i] = (d.base64[i + 1]
+            .wrapping_add(lowest)
+            .wrapping_sub(next_lowest))
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base
+            .checked_shl((64 - i - d.min_sym_len as usize) as u32)
+            .unwrap_or(0);
+    }
+    p += lengths * 2;
+
+    let num_syms = read_u16_le(data, p)? as usize;
+    p += 2;
+    d.btree = p;
+    if p + 3 * num_syms > data.len() {
+        return None;
+    }
+    d.sym_len = vec![0; num_syms];
+    let mut visited = vec![false; num_syms];
+    for sym in 0..num_syms {
+        if !visited[sym] {
+            d.sym_len[sym] = sym_len(d, data, sym as u16, &mut visited)?;
+        }
+    }
+    Some(p + 3 * num_syms + (num_syms & 1))
+}
+
+/// Computes the number of values (minus one) that `sym` expands to.
+fn sym_len(d: &mut PairsData, data: &[u8], sym: u16, visited: &mut [bool]) -> Option<u8> {
+    visited[sym as usize] = true;
+    let (left, right) = read_pair(data, d.btree, sym)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+    for child in [left, right] {
+        if !*visited.get(child as usize)? {
+            d.sym_len[child as usize] = sym_len(d, data, child, visited)?;
+        }
+    }
+    Some(
+        d.sym_len[left as usize]
+            .wrapping_add(d.sym_len[right as usize])
+            .wrapping_add(1),
+    )
+}
+
+/// Reads the 12-bit left and right symbols `sym` expands to. For symbols
+/// representing a single value, the left symbol is the value.
+#[inline]
+fn read_pair(data: &[u8], btree: usize, sym: u16) -> Option<(u16, u16)> {
+    let p = btree + 3 * sym as usize;
+    let lr = data.get(p..p + 3)?;
+    let left = ((lr[1] as u16 & 0xF) << 8) | lr[0] as u16;
+    let right = ((lr[2] as u16) << 4) | (lr[1] as u16 >> 4);
+    Some((left, right))
+}
+
+#[inline]
+fn read_u16_le(data: &[u8], p: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(p..p + 2)?.try_into().unwrap()))
+}
+
+#[inline]
+fn read_u32_le(data: &[u8], p: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(p..p + 4)?.try_into().unwrap()))
+}
+
+#[inline]
+fn read_u32_be(data: &[u8], p: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(p..p + 4)?.try_into().unwrap()))
+}
+
+#[inline]
+fn read_u64_be(data: &[u8], p: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(p..p + 8)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+pub(super) fn encoding_sizes() -> (u64, usize, usize) {
+    let enc = &*ENCODING;
+    let kings = enc.map_kk.iter().flatten().copied().max().unwrap() + 1;
+    let triangle = enc.map_a1d1d4.iter().copied().max().unwrap() + 1;
+    let below_diagonal = enc.map_b1h1h7.iter().copied().max().unwrap() + 1;
+    (kings, triangle, below_diagonal)
+}
@@ -0,0 +1,437 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::types::{Color, PieceKind, Square};
+use crate::{MoveGen, Position};
+
+use super::{parse_table_name, table, TableEntry, Tablebase, Wdl};
+
+/// Creates an empty directory for the test with the given files.
+fn test_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kingly-syzygy-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join(file), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn encoding_tables_have_expected_sizes() {
+    let (kings, triangle, below_diagonal) = table::encoding_sizes();
+    assert_eq!(kings, 462);
+    assert_eq!(triangle, 10);
+    assert_eq!(below_diagonal, 28);
+}
+
+#[test]
+fn table_names_are_parsed() {
+    let key = parse_table_name("KRPvKR").unwrap();
+    let white = key[Color::White as usize];
+    let black = key[Color::Black as usize];
+    assert_eq!(white[PieceKind::King as usize], 1);
+    assert_eq!(white[PieceKind::Rook as usize], 1);
+    assert_eq!(white[PieceKind::Pawn as usize], 1);
+    assert_eq!(black[PieceKind::Rook as usize], 1);
+    assert_eq!(black[PieceKind::Pawn as usize], 0);
+
+    assert!(parse_table_name("KvK").is_some());
+    assert!(parse_table_name("KRvKvK").is_none());
+    assert!(parse_table_name("krvk").is_none());
+    assert!(parse_table_name("KRvR").is_none());
+    assert!(parse_table_name("KQQQQQQvK").is_none());
+}
+
+#[test]
+fn table_entries_find_leading_color() {
+    let entry = |name| TableEntry::new(parse_table_name(name).unwrap(), PathBuf::new(), None);
+
+    let krk = entry("KRvK");
+    assert!(krk.has_unique_pieces);
+    assert!(!krk.has_pawns);
+    assert_ne!(krk.key, krk.key2);
+
+    let krrk = entry("KRRvK");
+    assert!(!krrk.has_unique_pieces);
+
+    let kppkp = entry("KPPvKP");
+    assert!(kppkp.has_pawns);
+    assert_eq!(kppkp.pawn_count, [1, 2]);
+
+    let kpk = entry("KPvK");
+    assert_eq!(kpk.pawn_count, [1, 0]);
+    assert_eq!(entry("KPvKP").key, entry("KPvKP").key2);
+}
+
+#[test]
+fn missing_directory_is_an_error() {
+    let dir = test_dir("missing", &[]);
+    assert!(Tablebase::open(dir.join("missing")).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn invalid_tables_are_not_probed() {
+    let dir = test_dir(
+        "invalid",
+        &[
+            ("KQvK.rtbw", b"not a table"),
+            ("KQvK.rtbz", b"not a table"),
+            ("notes.txt", b""),
+        ],
+    );
+    let tablebase = Tablebase::open(&dir).unwrap();
+    assert_eq!(tablebase.num_tables(), 1);
+    assert_eq!(tablebase.max_pieces(), 3);
+
+    let mut position = Position::from_fen("8/8/8/4k3/8/8/8/3QK3 w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&mut position), None);
+    assert_eq!(tablebase.probe_dtz(&mut position), None);
+
+    // KvK is known without a table
+    let mut position = Position::from_fen("8/8/8/4k3/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&mut position), Some(Wdl::Draw));
+    assert_eq!(tablebase.probe_dtz(&mut position), Some(0));
+
+    // If the only legal move is a capture, the table is not needed
+    let mut position = Position::from_fen("8/8/8/4k3/4q3/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&mut position), None);
+    let mut position = Position::from_fen("8/8/8/4k3/8/8/4q3/4K3 w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&mut position), Some(Wdl::Draw));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn positions_with_castling_rights_are_not_probed() {
+    let dir = test_dir("castling", &[("KRvK.rtbw", b"")]);
+    let tablebase = Tablebase::open(&dir).unwrap();
+    let position = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    assert!(!tablebase.can_probe(&position));
+    let position = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+    assert!(tablebase.can_probe(&position));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wdl_negation() {
+    assert_eq!(-Wdl::Win, Wdl::Loss);
+    assert_eq!(-Wdl::CursedWin, Wdl::BlessedLoss);
+    assert_eq!(-Wdl::Draw, Wdl::Draw);
+    assert!(Wdl::BlessedLoss > Wdl::Loss);
+}
+
+/// Opens the tables in the directories of the `SYZYGY_PATH` environment
+/// variable, which should hold the complete 3 and 4-piece tables. The tests
+/// against real tables are skipped if it isn't set.
+fn real_tablebase() -> Option<Tablebase> {
+    let Some(path) = std::env::var_os("SYZYGY_PATH") else {
+        eprintln!("SYZYGY_PATH is not set, skipping the test against real tables");
+        return None;
+    };
+    let tablebase = Tablebase::open(path).unwrap();
+    assert!(
+        tablebase.max_pieces() >= 4,
+        "the 4-piece tables are missing"
+    );
+    Some(tablebase)
+}
+
+/// Returns the FEN of a board given by the piece on each square, from a1 to h8.
+fn board_fen(board: &[Option<char>; 64], white_to_move: bool, en_passant: &str) -> String {
+    let ranks: Vec<String> = (0..8)
+        .rev()
+        .map(|rank| {
+            let mut fen = String::new();
+            let mut empty = 0;
+            for pce in &board[rank * 8..rank * 8 + 8] {
+                match pce {
+                    Some(pce) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(*pce);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            fen
+        })
+        .collect();
+    let to_move = if white_to_move { "w" } else { "b" };
+    format!("{} {to_move} - {en_passant} 0 1", ranks.join("/"))
+}
+
+/// Returns whether the side that is not to move is in check.
+fn is_illegal(position: &Position) -> bool {
+    let fen = position.to_fen();
+    let mut fields: Vec<&str> = fen.split(' ').collect();
+    fields[1] = if fields[1] == "w" { "b" } else { "w" };
+    // The en passant square is only valid for the side to move
+    fields[3] = "-";
+    let other_side = Position::from_fen(&fields.join(" ")).unwrap();
+    MoveGen::init().is_check(&other_side)
+}
+
+/// Places `pieces`, given as FEN characters, on random squares, with a random
+/// side to move.
+fn random_position(rng: &mut ChaCha20Rng, pieces: &str) -> Position {
+    loop {
+        let mut board = [None; 64];
+        for pce in pieces.chars() {
+            let sq = loop {
+                let sq = rng.gen_range(0..64);
+                let back_rank = !(8..56).contains(&sq);
+                if board[sq].is_none() && !(pce.eq_ignore_ascii_case(&'p') && back_rank) {
+                    break sq;
+                }
+            };
+            board[sq] = Some(pce);
+        }
+        let fen = board_fen(&board, rng.gen(), "-");
+        let position = Position::from_fen(&fen).unwrap();
+        if !is_illegal(&position) {
+            return position;
+        }
+    }
+}
+
+/// Returns the position with the board mirrored horizontally.
+fn mirrored(position: &Position) -> Position {
+    let fen = position.to_fen();
+    let mut fields: Vec<String> = fen.split(' ').map(str::to_string).collect();
+    fields[0] = fields[0]
+        .split('/')
+        .map(|rank| rank.chars().rev().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("/");
+    if fields[3] != "-" {
+        let mut chars = fields[3].chars();
+        let file = chars.next().unwrap();
+        let mirrored_file = (b'h' - (file as u8 - b'a')) as char;
+        fields[3] = format!("{mirrored_file}{}", chars.as_str());
+    }
+    Position::from_fen(&fields.join(" ")).unwrap()
+}
+
+/// Returns the sign of the WDL value of `position`, finding the value of
+/// positions without legal moves from the board.
+fn wdl_sign(tablebase: &Tablebase, position: &mut Position) -> i32 {
+    let (moves, check) = MoveGen::init().gen_all_moves_and_check(position);
+    if moves.is_empty() {
+        return -(check as i32);
+    }
+    tablebase.probe_wdl(position).unwrap().signum()
+}
+
+/// Returns the sign of the WDL value of `position`, ignoring the fifty-move
+/// rule, from the values of the positions after each legal move.
+fn wdl_sign_after_moves(tablebase: &Tablebase, position: &mut Position) -> i32 {
+    let moves = MoveGen::init().gen_all_moves(position);
+    moves
+        .iter()
+        .map(|&mv| {
+            position.make_move(mv);
+            let sign = -wdl_sign(tablebase, position);
+            position.unmake_move();
+            sign
+        })
+        .max()
+        .unwrap()
+}
+
+#[test]
+fn real_tables_have_known_wdl_values() {
+    let Some(tablebase) = real_tablebase() else {
+        return;
+    };
+    let positions = [
+        ("8/8/8/8/8/2Q5/8/K6k w - - 0 1", Wdl::Win),
+        ("8/8/8/8/8/2Q5/8/K6k b - - 0 1", Wdl::Loss),
+        // The king takes the queen
+        ("8/8/8/8/8/8/6Q1/K6k b - - 0 1", Wdl::Draw),
+        // The king in front of the pawn on the sixth rank wins
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+        ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+        // Only the side to move decides the opposition
+        ("4k3/8/4P3/4K3/8/8/8/8 w - - 0 1", Wdl::Draw),
+        ("4k3/8/4P3/4K3/8/8/8/8 b - - 0 1", Wdl::Loss),
+        // The defending king holds the corner against a rook pawn
+        ("k7/8/8/8/8/8/P7/7K w - - 0 1", Wdl::Draw),
+        ("k7/8/8/8/8/8/P7/7K b - - 0 1", Wdl::Draw),
+    ];
+    for (fen, wdl) in positions {
+        let mut position = Position::from_fen(fen).unwrap();
+        assert_eq!(tablebase.probe_wdl(&mut position), Some(wdl), "{fen}");
+        assert_eq!(
+            tablebase.probe_wdl(&mut mirrored(&position)),
+            Some(wdl),
+            "{fen}"
+        );
+        assert_eq!(
+            tablebase.probe_wdl(&mut position.flipped()),
+            Some(wdl),
+            "{fen}"
+        );
+    }
+
+    // The pawn can't be caught, so pushing it zeroes the counter and wins
+    let mut position = Position::from_fen("8/8/8/8/8/8/4P3/K6k w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&mut position), Some(Wdl::Win));
+    assert_eq!(tablebase.probe_dtz(&mut position), Some(1));
+    assert_eq!(tablebase.probe_dtz(&mut mirrored(&position)), Some(1));
+    assert_eq!(tablebase.probe_dtz(&mut position.flipped()), Some(1));
+}
+
+#[test]
+fn real_tables_agree_with_the_positions_after_each_move() {
+    let Some(tablebase) = real_tablebase() else {
+        return;
+    };
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    for pieces in ["KQk", "KRk", "KPk", "Kkp", "KBNk", "KPkp", "KRkp", "KQkr"] {
+        for _ in 0..200 {
+            let mut position = random_position(&mut rng, pieces);
+            let fen = position.to_fen();
+            let wdl = wdl_sign(&tablebase, &mut position);
+            if MoveGen::init().gen_all_moves(&position).is_empty() {
+                continue;
+            }
+            assert_eq!(
+                wdl,
+                wdl_sign_after_moves(&tablebase, &mut position),
+                "{fen}"
+            );
+
+            let wdl = tablebase.probe_wdl(&mut position);
+            let dtz = tablebase.probe_dtz(&mut position).unwrap();
+            assert_eq!(dtz.signum(), wdl.unwrap().signum(), "{fen}");
+            assert_eq!(tablebase.probe_wdl(&mut mirrored(&position)), wdl, "{fen}");
+            assert_eq!(tablebase.probe_wdl(&mut position.flipped()), wdl, "{fen}");
+            assert_eq!(
+                tablebase.probe_dtz(&mut mirrored(&position)),
+                Some(dtz),
+                "{fen}"
+            );
+            assert_eq!(
+                tablebase.probe_dtz(&mut position.flipped()),
+                Some(dtz),
+                "{fen}"
+            );
+        }
+    }
+}
+
+#[test]
+fn real_tables_handle_en_passant() {
+    let Some(tablebase) = real_tablebase() else {
+        return;
+    };
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    let mut count = 0;
+    while count < 200 {
+        // A black pawn has just moved two squares, next to a white pawn
+        let file = rng.gen_range(0..8);
+        let capturing_file = match file {
+            0 => 1,
+            7 => 6,
+            _ if rng.gen() => file - 1,
+            _ => file + 1,
+        };
+        let mut board = [None; 64];
+        board[4 * 8 + file] = Some('p');
+        board[4 * 8 + capturing_file] = Some('P');
+        for king in ['K', 'k'] {
+            let sq = loop {
+                let sq = rng.gen_range(0..64);
+                // The squares the pawn moved over are empty
+                if board[sq].is_none() && sq != 5 * 8 + file && sq != 6 * 8 + file {
+                    break sq;
+                }
+            };
+            board[sq] = Some(king);
+        }
+        let en_passant = format!("{}6", (b'a' + file as u8) as char);
+        let fen = board_fen(&board, true, &en_passant);
+        let mut position = Position::from_fen(&fen).unwrap();
+        if is_illegal(&position) || MoveGen::init().gen_all_moves(&position).is_empty() {
+            continue;
+        }
+        count += 1;
+
+        let wdl = wdl_sign(&tablebase, &mut position);
+        assert_eq!(
+            wdl,
+            wdl_sign_after_moves(&tablebase, &mut position),
+            "{fen}"
+        );
+        let wdl = tablebase.probe_wdl(&mut position);
+        assert_eq!(tablebase.probe_wdl(&mut mirrored(&position)), wdl, "{fen}");
+        assert_eq!(tablebase.probe_wdl(&mut position.flipped()), wdl, "{fen}");
+    }
+}
+
+#[test]
+fn real_tables_keep_result_preserving_root_moves() {
+    let Some(tablebase) = real_tablebase() else {
+        return;
+    };
+    let move_gen = MoveGen::init();
+    let child_wdl = |position: &mut Position, mv| {
+        position.make_move(mv);
+        let wdl = -tablebase.probe_wdl(position).unwrap();
+        position.unmake_move();
+        wdl
+    };
+
+    let mut rng = ChaCha20Rng::seed_from_u64(42);
+    for pieces in ["KQk", "KRk", "KPk", "KPkp", "KRkp"] {
+        for _ in 0..100 {
+            let mut position = random_position(&mut rng, pieces);
+            let moves = move_gen.gen_all_moves(&position);
+            if moves.is_empty() {
+                continue;
+            }
+            let fen = position.to_fen();
+            // Losses and cursed wins are ranked by how long they delay the
+            // result or how soon they zero, which this doesn't check
+            let wdl = tablebase.probe_wdl(&mut position).unwrap();
+            if !matches!(wdl, Wdl::Win | Wdl::Draw) {
+                continue;
+            }
+            let expected: Vec<_> = moves
+                .iter()
+                .copied()
+                .filter(|&mv| child_wdl(&mut position, mv) == wdl)
+                .collect();
+            let root_moves = tablebase.root_moves(&mut position, &moves).unwrap();
+            assert_eq!(
+                root_moves.iter().copied().collect::<Vec<_>>(),
+                expected,
+                "{fen}"
+            );
+        }
+    }
+
+    // Close to the fifty-move rule, only the pawn moves win in time
+    let fen = "8/8/8/8/8/8/4P3/K6k w - - 98 60";
+    let mut position = Position::from_fen(fen).unwrap();
+    let moves = move_gen.gen_all_moves(&position);
+    let expected: Vec<_> = moves
+        .iter()
+        .copied()
+        .filter(|&mv| mv.from() == Square::E2 && child_wdl(&mut position, mv) == Wdl::Win)
+        .collect();
+    assert!(!expected.is_empty());
+    let root_moves = tablebase.root_moves(&mut position, &moves).unwrap();
+    assert_eq!(root_moves.iter().copied().collect::<Vec<_>>(), expected);
+}
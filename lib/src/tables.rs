@@ -12,8 +12,15 @@ use rand_chacha::ChaCha20Rng;
 
 use crate::collections::SquareMap;
 use crate::types::{Bitboard, BoardVector, Color, File, Piece, PieceKind, Rank, Square};
+use crate::zobrist::CuckooTable;
 use crate::{bb, Position};
 
+/// Whether sliding piece attacks are looked up using PEXT. Otherwise, fancy
+/// magic bitboards are used, which is faster without BMI2, where PEXT is
+/// emulated, and on AMD CPUs before Zen 3, where it is microcoded. Magic
+/// bitboards can be forced with the `magic` feature.
+pub const USE_PEXT: bool = cfg!(all(target_feature = "bmi2", not(feature = "magic")));
+
 lazy_static! {
     static ref TABLES: Tables = {
         log::info!("Initializing tables...");
@@ -37,6 +44,7 @@ pub struct Tables {
     /// empty bb if not on a line
     pub ray_to: SquareMap<SquareMap<Bitboard>>,
     pub zobrist_randoms: ZobristRandoms,
+    pub cuckoo: CuckooTable,
 }
 
 impl Tables {
@@ -51,17 +59,31 @@ impl Tables {
     fn new() -> Self {
         let bishop_masks = Self::init_bishop_masks();
         let rook_masks = Self::init_rook_masks();
+        let knight_attacks = Self::init_knight_attacks();
+        let king_attacks = Self::init_king_attacks();
+        let zobrist_randoms = ZobristRandoms::init();
+        let cuckoo = CuckooTable::new(&zobrist_randoms, |pce, sq| match pce.kind() {
+            PieceKind::Pawn => bb!(),
+            PieceKind::Knight => knight_attacks[sq],
+            PieceKind::Bishop => gen_bishop_attacks_slow(sq, bb!()),
+            PieceKind::Rook => gen_rook_attacks_slow(sq, bb!()),
+            PieceKind::Queen => {
+                gen_bishop_attacks_slow(sq, bb!()) | gen_rook_attacks_slow(sq, bb!())
+            }
+            PieceKind::King => king_attacks[sq],
+        });
         Self {
             white_pawn_attacks: Self::init_white_pawn_attacks(),
             black_pawn_attacks: Self::init_black_pawn_attacks(),
-            knight_attacks: Self::init_knight_attacks(),
-            king_attacks: Self::init_king_attacks(),
+            knight_attacks,
+            king_attacks,
             bishop_masks,
             rook_masks,
-            slider_attacks: SliderAttacks::init(&bishop_masks, &rook_masks),
+            slider_attacks: SliderAttacks::init(&bishop_masks, &rook_masks, USE_PEXT),
             line_through: Self::init_line_through(),
             ray_to: Self::init_ray_to(),
-            zobrist_randoms: ZobristRandoms::init(),
+            zobrist_randoms,
+            cuckoo,
         }
     }
 
@@ -204,15 +226,20 @@ impl Tables {
 
     pub fn gen_bishop_attacks(&self, occ: Bitboard, sq: Square) -> Bitboard {
         let bishop_attacks = self.slider_attacks.bishop_attacks(sq);
-        let occ: u64 = occ.into();
-        let key = occ.pext(self.bishop_masks[sq].into()) as usize;
+        let key = slider_index(
+            occ,
+            self.bishop_masks[sq],
+            BISHOP_MAGICS[sq as usize],
+            USE_PEXT,
+        );
+        // Safety: The key is less than 2^(bits in the mask), the size of the slice
         unsafe { *bishop_attacks.get_unchecked(key) }
     }
 
     pub fn gen_rook_attacks(&self, occ: Bitboard, sq: Square) -> Bitboard {
         let rook_attacks = self.slider_attacks.rook_attacks(sq);
-        let occ: u64 = occ.into();
-        let key = occ.pext(self.rook_masks[sq].into()) as usize;
+        let key = slider_index(occ, self.rook_masks[sq], ROOK_MAGICS[sq as usize], USE_PEXT);
+        // Safety: The key is less than 2^(bits in the mask), the size of the slice
         unsafe { *rook_attacks.get_unchecked(key) }
     }
 
@@ -281,7 +308,13 @@ pub struct SliderAttacks {
 }
 
 impl SliderAttacks {
-    fn init(bishop_masks: &SquareMap<Bitboard>, rook_masks: &SquareMap<Bitboard>) -> Self {
+    /// Initializes the attack tables for lookups with [`slider_index`] using
+    /// PEXT or magic bitboards.
+    fn init(
+        bishop_masks: &SquareMap<Bitboard>,
+        rook_masks: &SquareMap<Bitboard>,
+        use_pext: bool,
+    ) -> Self {
         // We do not want to allocate the big arrays on the stack, so we allocate them
         // on the heap
         let layout = Layout::new::<SliderAttacksTables>();
@@ -300,13 +333,19 @@ impl SliderAttacks {
                 // bishop on `sq`
                 let occ_bb = key.pdep(bishop_masks[sq].into()).into();
                 let atk_bb = gen_bishop_attacks_slow(sq, occ_bb);
-                let idx = num_bishop_init + key as usize;
+                let idx = num_bishop_init
+                    + slider_index(
+                        occ_bb,
+                        bishop_masks[sq],
+                        BISHOP_MAGICS[sq as usize],
+                        use_pext,
+                    );
                 // Safety: The pointer is valid
                 unsafe { (*ptr).bishop[idx] = atk_bb };
             }
             // Safety: The pointer is valid
             bishop_attacks[sq] =
-                unsafe { (*ptr).bishop[num_bishop_init..num_bishop_init + count as usize].into() };
+                unsafe { (&(*ptr).bishop)[num_bishop_init..num_bishop_init + count].into() };
             num_bishop_init += count;
 
             let count = 1 << rook_masks[sq].len();
@@ -316,13 +355,14 @@ impl SliderAttacks {
                 // on `sq`
                 let occ_bb = key.pdep(rook_masks[sq].into()).into();
                 let atk_bb = gen_rook_attacks_slow(sq, occ_bb);
-                let idx = num_rook_init + key as usize;
+                let idx = num_rook_init
+                    + slider_index(occ_bb, rook_masks[sq], ROOK_MAGICS[sq as usize], use_pext);
                 // Safety: The pointer is valid
                 unsafe { (*ptr).rook[idx] = atk_bb };
             }
             // Safety: The pointer is valid
             rook_attacks[sq] =
-                unsafe { (*ptr).rook[num_rook_init..num_rook_init + count as usize].into() };
+                unsafe { (&(*ptr).rook)[num_rook_init..num_rook_init + count].into() };
             num_rook_init += count;
         }
         assert_eq!(num_bishop_init, NUM_BISHOP_ATTACKS);
@@ -385,8 +425,75 @@ impl ZobristRandoms {
             en_passant: rng.gen(),
         }
     }
+
+    /// Returns the random for `pce` on `sq`.
+    #[inline]
+    pub fn piece(&self, pce: Piece, sq: Square) -> u64 {
+        let color_index = match pce.color() {
+            Color::White => 0,
+            Color::Black => 1,
+        };
+        self.pieces[color_index * 6 + pce.kind() as usize][sq as usize]
+    }
+}
+
+/// Returns the index of the attacks of a slider with relevant occupancy
+/// squares `mask` among the attacks for its square. With `use_pext`, the
+/// index is the occupancy of `mask` with the bits packed together, otherwise
+/// it is computed from the fancy magic number `magic`.
+#[inline]
+fn slider_index(occ: Bitboard, mask: Bitboard, magic: u64, use_pext: bool) -> usize {
+    let occ = u64::from(occ);
+    let mask = u64::from(mask);
+    if use_pext {
+        occ.pext(mask) as usize
+    } else {
+        ((occ & mask).wrapping_mul(magic) >> (64 - mask.count_ones())) as usize
+    }
 }
 
+/// Magic numbers for bishop attacks, see [`slider_index`].
+#[rustfmt::skip]
+const BISHOP_MAGICS: [u64; 64] = [
+    0x10102002004A1420, 0x8020040400584008, 0x10510800811201C8, 0x5204042080000088,
+    0x2204106880000002, 0x1401042004000000, 0x0400880410042004, 0x0028208200A02020,
+    0x1500241990010E00, 0x8001200182020A40, 0x40004101030B0000, 0x8002041042000100,
+    0x4010011041020038, 0x0000010421044000, 0x1500210808020A00, 0x8000088400880520,
+    0x0405004010040100, 0x1005823210040108, 0x2708008102040011, 0x4048200404009100,
+    0x0018104101400024, 0x0003000601190101, 0x8004803108491000, 0x8014241200820800,
+    0x0006E080100C3040, 0x0501044A11041800, 0x9020300008004045, 0x0894080000220040,
+    0x1001010083104000, 0x5004030040900080, 0x000400422C012400, 0x0002128698404812,
+    0x1010108404900440, 0x0928021182084100, 0x2006080409020024, 0x1010202020180080,
+    0xA010008200202200, 0x2098015100019004, 0x0002041440810811, 0x802A02020000B098,
+    0x0009015090004060, 0x4000821082081001, 0x0100210040420800, 0x0800004010488A00,
+    0x2000081104004040, 0x4C8E029015000082, 0x0420340322224842, 0x1298260043400210,
+    0x0000822802400008, 0x00008A0101600000, 0x3040003412080021, 0x3040290220884800,
+    0x4A1500401041004A, 0x8010200282020781, 0x0020203142209091, 0x0070300600902110,
+    0x0040808800B62048, 0x0000810400C44420, 0x00080400440C0441, 0x8340080020840411,
+    0x0000000104208200, 0x0000800810D00080, 0x0400530411080200, 0x4040702400932244,
+];
+
+/// Magic numbers for rook attacks, see [`slider_index`].
+#[rustfmt::skip]
+const ROOK_MAGICS: [u64; 64] = [
+    0x1080004008801020, 0x0840092002C03000, 0x1900200010400900, 0x0880100008000480,
+    0x4200100420080200, 0x8100020100080400, 0x0200040110886200, 0x0200008040220411,
+    0x0404800084400220, 0x0000401000402000, 0x0086001081220440, 0x0408800800100280,
+    0x000A001201040820, 0x8848800200840080, 0x4001000100040200, 0x0442000102105084,
+    0x9080010020804100, 0x0040404000201009, 0x0000808010002009, 0x2200090021D00100,
+    0x0008008008040080, 0x0004004002010040, 0x0011040008015042, 0x00000A0001768104,
+    0x0000800080204009, 0x2010004140002001, 0x9800200280100080, 0x1000100080080080,
+    0x0442000A00049020, 0x2100040080020080, 0x0800120400900148, 0x0010040A00128541,
+    0x2800804000800030, 0x1010002000400041, 0x4000200011004100, 0x0610008410800800,
+    0x0400802402800800, 0xC100020080800400, 0x0002000802000401, 0x0182085882000401,
+    0x0220204000808000, 0x2860100040024022, 0x0001002004110040, 0x99101042000A0020,
+    0x0004080004008080, 0x0010040002008080, 0x2012004881020004, 0x8300842444820011,
+    0x0088403882010200, 0x0820400080210100, 0x0110910040A00300, 0x0801100280080480,
+    0x0242009008200600, 0x1002000489500200, 0x0040800200010080, 0x0091800041000080,
+    0x0000209300488001, 0x04C1002414824001, 0x020020000B001041, 0x7000100004200901,
+    0x8002002004100802, 0x30010002084C0007, 0x0888221800813004, 0x4000002840840112,
+];
+
 fn gen_bishop_attacks_slow(sq: Square, occ: Bitboard) -> Bitboard {
     let sq_not_occ = |sq: &Square| !occ.contains(*sq);
 
@@ -447,10 +554,71 @@ fn gen_rook_attacks_slow(sq: Square, occ: Bitboard) -> Bitboard {
 
 #[cfg(test)]
 mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
     use crate::bb;
-    use crate::types::Square;
+    use crate::types::{Bitboard, Square};
 
-    use super::Tables;
+    use super::{
+        gen_bishop_attacks_slow, gen_rook_attacks_slow, slider_index, SliderAttacks, Tables,
+        BISHOP_MAGICS, ROOK_MAGICS,
+    };
+
+    /// Checks the slider attacks using PEXT or magics against the slow
+    /// generation for every occupancy of the relevant squares, so a magic
+    /// mapping two occupancies with different attacks to the same index is
+    /// caught. Random pieces are added outside the relevant squares.
+    fn check_slider_attacks(use_pext: bool) {
+        let tables = Tables::get_or_init();
+        let attacks = SliderAttacks::init(&tables.bishop_masks, &tables.rook_masks, use_pext);
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        for sq in Square::iter() {
+            let mask = tables.bishop_masks[sq];
+            for_each_subset(mask, |sub| {
+                let occ = Bitboard::from(sub | (rng.gen::<u64>() & !u64::from(mask)));
+                let key = slider_index(occ, mask, BISHOP_MAGICS[sq as usize], use_pext);
+                assert_eq!(
+                    attacks.bishop_attacks(sq)[key],
+                    gen_bishop_attacks_slow(sq, occ)
+                );
+            });
+
+            let mask = tables.rook_masks[sq];
+            for_each_subset(mask, |sub| {
+                let occ = Bitboard::from(sub | (rng.gen::<u64>() & !u64::from(mask)));
+                let key = slider_index(occ, mask, ROOK_MAGICS[sq as usize], use_pext);
+                assert_eq!(
+                    attacks.rook_attacks(sq)[key],
+                    gen_rook_attacks_slow(sq, occ)
+                );
+            });
+        }
+    }
+
+    /// Calls `f` with every subset of the squares in `mask`, using the
+    /// carry-rippler trick.
+    fn for_each_subset(mask: Bitboard, mut f: impl FnMut(u64)) {
+        let mask = u64::from(mask);
+        let mut sub = 0;
+        loop {
+            f(sub);
+            sub = sub.wrapping_sub(mask) & mask;
+            if sub == 0 {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn pext_slider_attacks_are_correct() {
+        check_slider_attacks(true);
+    }
+
+    #[test]
+    fn magic_slider_attacks_are_correct() {
+        check_slider_attacks(false);
+    }
 
     #[test]
     fn bishop_masks_initialized_correctly() {
@@ -19,6 +19,9 @@ macro_rules! bb {
 pub struct Bitboard(u64);
 
 impl Bitboard {
+    /// The light squares, i.e. B1, D1 and so on.
+    pub const LIGHT_SQUARES: Self = Bitboard(0x55AA_55AA_55AA_55AA);
+
     /// Ranks from 1 to 8.
     pub const RANKS: [Self; 8] = [
         Bitboard(0x0000_0000_0000_00FF),
@@ -2,7 +2,7 @@ use std::fmt::{self, Debug, Display, Formatter};
 use std::mem;
 use std::str::FromStr;
 
-use super::{ParseSquareError, PieceFromCharError, PieceKind, Square};
+use super::{File, ParseSquareError, PieceFromCharError, PieceKind, Side, Square};
 
 /// Used to create a [`Move`].
 ///
@@ -13,8 +13,8 @@ use super::{ParseSquareError, PieceFromCharError, PieceKind, Square};
 ///
 /// assert_eq!(mv!(E2 -> E4), Move::new_regular(Square::E2, Square::E4, false));
 /// assert_eq!(mv!(E4 x D5), Move::new_regular(Square::E4, Square::D5, true));
-/// assert_eq!(mv!(O-O w), Move::new_castling(Square::E1, Square::G1));
-/// assert_eq!(mv!(O-O-O b), Move::new_castling(Square::E8, Square::C8));
+/// assert_eq!(mv!(O-O w), Move::new_castling(Square::E1, Square::H1));
+/// assert_eq!(mv!(O-O-O b), Move::new_castling(Square::E8, Square::A8));
 /// assert_eq!(mv!(E7 -> E8 q), Move::new_promotion(Square::E7, Square::E8, PieceKind::Queen, false));
 /// assert_eq!(mv!(B2 x A1 n), Move::new_promotion(Square::B2, Square::A1, PieceKind::Knight, true));
 /// assert_eq!(mv!(E5 ep D6), Move::new_en_passant(Square::E5, Square::D6));
@@ -34,19 +34,19 @@ macro_rules! mv {
     }};
     ( O-O w ) => {{
         use $crate::types::Square::*;
-        $crate::types::Move::new_castling(E1, G1)
+        $crate::types::Move::new_castling(E1, H1)
     }};
     ( O-O-O w ) => {{
         use $crate::types::Square::*;
-        $crate::types::Move::new_castling(E1, C1)
+        $crate::types::Move::new_castling(E1, A1)
     }};
     ( O-O b ) => {{
         use $crate::types::Square::*;
-        $crate::types::Move::new_castling(E8, G8)
+        $crate::types::Move::new_castling(E8, H8)
     }};
     ( O-O-O b ) => {{
         use $crate::types::Square::*;
-        $crate::types::Move::new_castling(E8, C8)
+        $crate::types::Move::new_castling(E8, A8)
     }};
     ( $from:tt ep $to:tt ) => {{
         #[allow(unused_imports)]
@@ -92,6 +92,8 @@ macro_rules! mv {
 // 14-15: promotion (0: knight, 1: bishop, 2: Rook, 3: Queen)
 // The all-0s move is not a valid move, as from sq and to sq cannot be the same, so it is
 // used to represent a null move.
+// Castling moves are encoded as the king capturing its own rook, which also works for
+// Chess960, where the king may not move two squares or may not move at all.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Move(u16);
 
@@ -111,7 +113,8 @@ impl Move {
         Self(encoding)
     }
 
-    /// Creates a new castling move.
+    /// Creates a new castling move, where `from` is the square of the king
+    /// and `to` is the square of the rook.
     #[inline]
     pub const fn new_castling(from: Square, to: Square) -> Self {
         let mut encoding = 1 << 14;
@@ -170,16 +173,16 @@ impl Move {
     #[inline]
     pub const fn kind(self) -> MoveKind {
         // Kind could either be a move kind or a promotion kind
-        let kind = ((self.0 >> 14) & 0b11) as u16;
+        let kind = (self.0 >> 14) & 0b11;
         if self.0 & (1 << 13) != 0 {
             // Safety: since kind is 2 bits, it's safe to transmute to a PieceKind
-            MoveKind::Promotion(unsafe { mem::transmute(kind as u8) })
+            MoveKind::Promotion(unsafe { mem::transmute::<u8, PieceKind>(kind as u8) })
         } else {
             // Safety: MoveKind is repr(C, u8), which means it is represented as a
             // repr(C) struct where the first field is a u8 tag, which is then
             // placed at the first 8 bits of the representation of the struct.
             // This means that it is safe to transmute kind to a MoveKind.
-            unsafe { mem::transmute(kind) }
+            unsafe { mem::transmute::<u16, MoveKind>(kind) }
         }
     }
 
@@ -188,12 +191,41 @@ impl Move {
     pub const fn promotion(self) -> Option<PieceKind> {
         if self.0 & (1 << 13) != 0 {
             let kind = ((self.0 >> 14) & 0b11) as u8;
-            Some(unsafe { mem::transmute(kind) })
+            Some(unsafe { mem::transmute::<u8, PieceKind>(kind) })
         } else {
             None
         }
     }
 
+    /// Returns the side of a castling move.
+    #[inline]
+    pub fn castling_side(self) -> Side {
+        debug_assert!(matches!(self.kind(), MoveKind::Castling));
+        if self.to() as u8 > self.from() as u8 {
+            Side::KingSide
+        } else {
+            Side::QueenSide
+        }
+    }
+
+    /// Returns the square the king ends up on after a castling move.
+    #[inline]
+    pub fn castling_king_dest(self) -> Square {
+        let file = match self.castling_side() {
+            Side::KingSide => File::G,
+            Side::QueenSide => File::C,
+        };
+        Square::from_rank_file(self.from().rank(), file)
+    }
+
+    /// Returns a value displaying the move in the UCI format. Castling is
+    /// written as the king capturing its own rook in Chess960 and as the king
+    /// moving two squares otherwise.
+    #[inline]
+    pub fn display(self, chess960: bool) -> MoveDisplay {
+        MoveDisplay { mv: self, chess960 }
+    }
+
     /// Parses a move from a string in the UCI format, e.g. `e2e4` and `g2g1q`,
     /// given a list of legal moves.
     pub fn parse_from_legal(value: &str, legal_moves: &[Move]) -> Result<Self, TryFromLegalError> {
@@ -233,14 +265,30 @@ impl From<Move> for u16 {
 
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        if self.is_null() {
+        write!(f, "{}", self.display(false))
+    }
+}
+
+/// Displays a [`Move`] in the UCI format, see [`Move::display`].
+pub struct MoveDisplay {
+    mv: Move,
+    chess960: bool,
+}
+
+impl Display for MoveDisplay {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mv = self.mv;
+        if mv.is_null() {
             return write!(f, "NULL");
         }
-        match self.kind() {
+        match mv.kind() {
+            MoveKind::Castling if !self.chess960 => {
+                write!(f, "{}{}", mv.from(), mv.castling_king_dest())
+            }
             MoveKind::Regular | MoveKind::Castling | MoveKind::EnPassant => {
-                write!(f, "{}{}", self.from(), self.to())
+                write!(f, "{}{}", mv.from(), mv.to())
             }
-            MoveKind::Promotion(kind) => write!(f, "{}{}{}", self.from(), self.to(), kind),
+            MoveKind::Promotion(kind) => write!(f, "{}{}{}", mv.from(), mv.to(), kind),
         }
     }
 }
@@ -299,14 +347,14 @@ impl PseudoMove {
         }
     }
 
+    /// Finds the legal move matching this move. Castling can be given both as
+    /// the king capturing its own rook and as the king moving two squares.
     pub fn into_move(self, legal_moves: &[Move]) -> Result<Move, IllegalMoveError> {
         legal_moves
             .iter()
             .copied()
-            .find(|&mv| {
-                mv.from() == self.from && mv.to() == self.to && mv.promotion() == self.promotion
-            })
-            .ok_or_else(|| IllegalMoveError(self))
+            .find(|&mv| self == mv)
+            .ok_or(IllegalMoveError(self))
     }
 }
 
@@ -322,7 +370,14 @@ impl From<Move> for PseudoMove {
 
 impl PartialEq<Move> for PseudoMove {
     fn eq(&self, other: &Move) -> bool {
-        self.from == other.from() && self.to == other.to() && self.promotion == other.promotion()
+        if self.from != other.from() || self.promotion != other.promotion() {
+            return false;
+        }
+        // A king moving two squares is never a regular move
+        self.to == other.to()
+            || matches!(other.kind(), MoveKind::Castling)
+                && self.to == other.castling_king_dest()
+                && self.from.dist(self.to) == 2
     }
 }
 
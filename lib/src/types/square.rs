@@ -52,6 +52,13 @@ impl Square {
         mem::transmute(index)
     }
 
+    /// Returns the square mirrored vertically, e.g. A2 becomes A7.
+    #[inline]
+    pub const fn flip_rank(self) -> Self {
+        // Safety: Flipping the rank bits keeps the index in [0; 63].
+        unsafe { Self::from_unchecked(self as u8 ^ 56) }
+    }
+
     #[inline]
     pub fn iter() -> impl Iterator<Item = Self> {
         (0..64).map(|i| unsafe { Self::from_unchecked(i) })
@@ -1,6 +1,6 @@
 use std::ops::BitXor;
 
-use crate::tables::Tables;
+use crate::tables::{Tables, ZobristRandoms};
 use crate::types::{Bitboard, CastlingRights, Color, Piece, Square};
 
 pub trait ZobristKey {
@@ -11,12 +11,7 @@ pub trait ZobristKey {
 impl ZobristKey for (Piece, Square) {
     fn key(&self, tables: &Tables) -> u64 {
         let (pce, sq) = *self;
-        let pce_index = match pce.color() {
-            Color::White => 0,
-            Color::Black => 1,
-        } * 6
-            + pce.kind() as usize;
-        tables.zobrist_randoms.pieces[pce_index][sq as usize]
+        tables.zobrist_randoms.piece(pce, sq)
     }
 }
 
@@ -57,13 +52,88 @@ impl ZobristKey for Option<Square> {
     }
 }
 
+/// The number of entries in the [`CuckooTable`]. It must be a power of two,
+/// and large enough to hold all 3668 reversible moves.
+const CUCKOO_SIZE: usize = 0x2000;
+
+/// A cuckoo hash table of the zobrist key differences made by reversible
+/// moves, i.e. a piece other than a pawn moving between two squares. If the
+/// difference between the keys of two positions is in the table, the piece can
+/// move back and forth between them, as long as nothing is in the way.
+pub struct CuckooTable {
+    keys: Box<[u64]>,
+    moves: Box<[(Square, Square)]>,
+}
+
+impl CuckooTable {
+    /// Creates the table from the zobrist `randoms`, where `attacks` gives the
+    /// attacks of a piece on an empty board.
+    pub(crate) fn new(
+        randoms: &ZobristRandoms,
+        attacks: impl Fn(Piece, Square) -> Bitboard,
+    ) -> Self {
+        let mut keys = vec![0; CUCKOO_SIZE].into_boxed_slice();
+        let mut moves = vec![(Square::A1, Square::A1); CUCKOO_SIZE].into_boxed_slice();
+        for pce in Piece::iter() {
+            for from in Square::iter() {
+                // Each move is added once, in the direction of increasing
+                // squares
+                for to in attacks(pce, from)
+                    .into_iter()
+                    .filter(|&to| to as u8 > from as u8)
+                {
+                    let mut key =
+                        randoms.piece(pce, from) ^ randoms.piece(pce, to) ^ randoms.to_move;
+                    let mut mv = (from, to);
+                    // Insert the move, kicking out any move in its place to
+                    // its other slot, until an empty slot is found
+                    let mut i = cuckoo_h1(key);
+                    loop {
+                        std::mem::swap(&mut keys[i], &mut key);
+                        std::mem::swap(&mut moves[i], &mut mv);
+                        if key == 0 {
+                            break;
+                        }
+                        i = if i == cuckoo_h1(key) {
+                            cuckoo_h2(key)
+                        } else {
+                            cuckoo_h1(key)
+                        };
+                    }
+                }
+            }
+        }
+        Self { keys, moves }
+    }
+
+    /// Returns the squares of the reversible move that changes the zobrist
+    /// key by `key`, if there is one.
+    #[inline]
+    pub fn get(&self, key: u64) -> Option<(Square, Square)> {
+        [cuckoo_h1(key), cuckoo_h2(key)]
+            .into_iter()
+            .find(|&i| self.keys[i] == key)
+            .map(|i| self.moves[i])
+    }
+}
+
+#[inline]
+fn cuckoo_h1(key: u64) -> usize {
+    key as usize & (CUCKOO_SIZE - 1)
+}
+
+#[inline]
+fn cuckoo_h2(key: u64) -> usize {
+    (key >> 16) as usize & (CUCKOO_SIZE - 1)
+}
+
 #[cfg(test)]
 mod tests {
     use itertools::{iproduct, Itertools};
 
     use super::ZobristKey;
     use crate::tables::Tables;
-    use crate::types::{CastlingRights, Color, Piece, Square};
+    use crate::types::{CastlingRights, Color, Piece, PieceKind, Square};
 
     #[test]
     fn all_zobrist_keys_different() {
@@ -94,4 +164,27 @@ mod tests {
 
         assert!(z_keys.into_iter().duplicates().count() == 0);
     }
+
+    #[test]
+    fn cuckoo_table_finds_reversible_moves() {
+        use Square::*;
+
+        let tables = Tables::get_or_init();
+        assert_eq!(
+            tables.cuckoo.keys.iter().filter(|&&key| key != 0).count(),
+            3668
+        );
+
+        let knight = Piece(PieceKind::Knight, Color::Black);
+        let key = (knight, G8).key(tables) ^ (knight, F6).key(tables) ^ Color::White.key(tables);
+        assert_eq!(tables.cuckoo.get(key), Some((F6, G8)));
+        let queen = Piece(PieceKind::Queen, Color::White);
+        let key = (queen, A1).key(tables) ^ (queen, H8).key(tables) ^ Color::White.key(tables);
+        assert_eq!(tables.cuckoo.get(key), Some((A1, H8)));
+
+        // Pawns can't move back
+        let pawn = Piece(PieceKind::Pawn, Color::White);
+        let key = (pawn, E2).key(tables) ^ (pawn, E3).key(tables) ^ Color::White.key(tables);
+        assert_eq!(tables.cuckoo.get(key), None);
+    }
 }
@@ -1,12 +1,16 @@
-use std::io;
+use std::path::PathBuf;
 use std::time::Instant;
+use std::{fs, io};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use kingly_lib::move_gen::{Perft, PerftStats, DEFAULT_PERFT_HASH_SIZE};
 use kingly_lib::position::ParseFenError;
 use kingly_lib::search::{SearchInfo, SearchJob, ThreadPool};
-use kingly_lib::{MoveGen, Position};
+use kingly_lib::Position;
+use perft_suite::ParseSuiteError;
 use uci::Uci;
 
+mod perft_suite;
 mod uci;
 
 #[derive(Parser)]
@@ -18,21 +22,70 @@ struct App {
 #[derive(Debug, Subcommand)]
 enum Command {
     #[command(arg_required_else_help = true)]
-    Perft { fen: String, depth: i8 },
+    Perft {
+        fen: String,
+        depth: i8,
+        #[command(flatten)]
+        options: PerftOptions,
+        /// Also count captures, en passants, castles, promotions, checks and mates
+        #[arg(long)]
+        detailed: bool,
+    },
     #[command(arg_required_else_help = true)]
-    Divide { fen: String, depth: i8 },
+    Divide {
+        fen: String,
+        depth: i8,
+        #[command(flatten)]
+        options: PerftOptions,
+        /// Also count captures, en passants, castles, promotions, checks and mates
+        #[arg(long)]
+        detailed: bool,
+    },
+    /// Runs a suite of perft tests, either in the JSON format of
+    /// resources/test/perft_positions.json or the EPD format of perftsuite.epd
+    #[command(name = "perftsuite")]
+    PerftSuite {
+        #[arg(default_value = "resources/test/perft_positions.json")]
+        file: PathBuf,
+        #[command(flatten)]
+        options: PerftOptions,
+    },
     /// Required for OpenBench - tests the search performance of the system
     Bench,
 }
 
+#[derive(Debug, Args)]
+struct PerftOptions {
+    /// The number of threads to split the root moves across [default: all cores]
+    #[arg(long)]
+    threads: Option<usize>,
+    /// The size of the hash table in MB, 0 disables hashing
+    #[arg(long, default_value_t = DEFAULT_PERFT_HASH_SIZE)]
+    hash: usize,
+}
+
+impl PerftOptions {
+    fn perft(&self) -> Perft {
+        let perft = Perft::new().hash_size(self.hash);
+        match self.threads {
+            Some(threads) => perft.threads(threads),
+            None => perft,
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum Error {
     #[error("{0}")]
     Io(#[from] io::Error),
     #[error("{0}")]
     ParseFen(#[from] ParseFenError),
+    #[error("{0}")]
+    ParseSuite(#[from] ParseSuiteError),
     #[error("invalid divide depth")]
     InvalidDivideDepth,
+    #[error("{0} perft tests failed")]
+    PerftSuiteFailed(usize),
 }
 
 fn main() -> Result<(), Error> {
@@ -40,33 +93,65 @@ fn main() -> Result<(), Error> {
 
     let app = App::parse();
     match app.command {
-        Some(Command::Perft { fen, depth }) => {
+        Some(Command::Perft {
+            fen,
+            depth,
+            options,
+            detailed,
+        }) => {
             let position = fen.parse()?;
-            let move_gen = MoveGen::init();
             let start = Instant::now();
-            let res = move_gen.perft(position, depth);
+            let stats = options.perft().detailed(detailed).run(&position, depth);
             let elapsed = start.elapsed();
-            println!("Nodes:\t\t{res}");
+            println!("Nodes:\t\t{}", stats.nodes);
+            if detailed {
+                print_breakdown(&stats);
+            }
             println!("Elapsed:\t{} ms", elapsed.as_millis());
-            println!("NPS:\t\t{} kn/s", res / elapsed.as_millis() as u64);
+            println!(
+                "NPS:\t\t{} kn/s",
+                (stats.nodes as f64 / elapsed.as_secs_f64() / 1000.) as u64
+            );
         }
-        Some(Command::Divide { fen, depth }) => {
-            if depth == 0 {
+        Some(Command::Divide {
+            fen,
+            depth,
+            options,
+            detailed,
+        }) => {
+            if depth <= 0 {
                 return Err(Error::InvalidDivideDepth);
             }
-            let mut position = fen.parse()?;
-            let move_gen = MoveGen::init();
-            let moves = move_gen.gen_all_moves(&position);
-            let mut total = 0;
-            for &mv in &moves {
-                position.make_move(mv);
-                let count = move_gen.perft(position.clone(), depth - 1);
-                position.unmake_move();
-                total += count;
-                println!("{mv}: {count}",);
+            let position = fen.parse()?;
+            let results = options.perft().detailed(detailed).divide(&position, depth);
+            let mut total = PerftStats::default();
+            for &(mv, stats) in &results {
+                total += stats;
+                println!("{mv}: {}", stats.nodes);
+            }
+            println!("Moves: {}", results.len());
+            println!("Total: {}", total.nodes);
+            if detailed {
+                print_breakdown(&total);
+            }
+        }
+        Some(Command::PerftSuite { file, options }) => {
+            let entries = perft_suite::parse_suite(&fs::read_to_string(file)?)?;
+            let report = perft_suite::run_suite(&entries, &options.perft(), &mut io::stdout())?;
+            println!(
+                "Passed:\t\t{}/{}",
+                report.passed,
+                report.passed + report.failed
+            );
+            println!("Nodes:\t\t{}", report.nodes);
+            println!("Elapsed:\t{} ms", report.elapsed.as_millis());
+            println!(
+                "NPS:\t\t{} kn/s",
+                (report.nodes as f64 / report.elapsed.as_secs_f64() / 1000.) as u64
+            );
+            if report.failed > 0 {
+                return Err(Error::PerftSuiteFailed(report.failed));
             }
-            println!("Moves: {}", moves.len());
-            println!("Total: {total}");
         }
         Some(Command::Bench) => {
             let mut thread_pool = ThreadPool::new();
@@ -93,3 +178,12 @@ fn main() -> Result<(), Error> {
     }
     Ok(())
 }
+
+fn print_breakdown(stats: &PerftStats) {
+    println!("Captures:\t{}", stats.captures);
+    println!("E.p.:\t\t{}", stats.en_passants);
+    println!("Castles:\t{}", stats.castles);
+    println!("Promotions:\t{}", stats.promotions);
+    println!("Checks:\t\t{}", stats.checks);
+    println!("Checkmates:\t{}", stats.mates);
+}
@@ -0,0 +1,191 @@
+//! Suites of perft tests, either in the JSON format of
+//! `resources/test/perft_positions.json` or the EPD format of `perftsuite.epd`,
+//! where each line is a FEN followed by expected counts like `;D1 20 ;D2 400`.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use kingly_lib::move_gen::{Perft, PerftStats};
+use kingly_lib::position::ParseFenError;
+use kingly_lib::types::Move;
+use kingly_lib::Position;
+use serde::Deserialize;
+
+#[cfg(test)]
+mod tests;
+
+/// A position with the expected perft results.
+#[derive(Debug, PartialEq)]
+pub struct SuiteEntry {
+    pub fen: String,
+    /// The expected node counts at each depth.
+    pub expected: Vec<(i8, u64)>,
+    /// The expected node counts of each root move, keyed by the move in UCI
+    /// format. Only used for the deepest depth.
+    pub divide: Option<HashMap<String, u64>>,
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    depth: i8,
+    nodes: u64,
+    fen: String,
+    #[serde(default)]
+    divide: Option<HashMap<String, u64>>,
+}
+
+/// Parses a perft suite. Files starting with `[` are parsed as JSON, and
+/// other files as EPD.
+pub fn parse_suite(contents: &str) -> Result<Vec<SuiteEntry>, ParseSuiteError> {
+    let entries: Vec<_> = if contents.trim_start().starts_with('[') {
+        let entries: Vec<JsonEntry> = serde_json::from_str(contents)?;
+        entries
+            .into_iter()
+            .map(|entry| SuiteEntry {
+                fen: entry.fen,
+                expected: vec![(entry.depth, entry.nodes)],
+                divide: entry.divide,
+            })
+            .collect()
+    } else {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| parse_epd_line(line).ok_or(ParseSuiteError::InvalidLine(i + 1)))
+            .collect::<Result<_, _>>()?
+    };
+
+    for entry in &entries {
+        Position::from_fen(&entry.fen).map_err(|err| ParseSuiteError::InvalidFen {
+            fen: entry.fen.clone(),
+            source: err,
+        })?;
+    }
+    Ok(entries)
+}
+
+fn parse_epd_line(line: &str) -> Option<SuiteEntry> {
+    let mut fields = line.split(';');
+    let fen = fields.next()?.trim().to_string();
+    let expected = fields
+        .map(|field| {
+            let (depth, nodes) = field.trim().split_once(char::is_whitespace)?;
+            let depth = depth.strip_prefix('D')?.parse().ok()?;
+            Some((depth, nodes.trim().parse().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(SuiteEntry {
+        fen,
+        expected,
+        divide: None,
+    })
+}
+
+/// The results of running a perft suite.
+#[derive(Debug, Default)]
+pub struct SuiteReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+/// Runs every depth of every entry in the suite, writing the results to `out`.
+/// Mismatches are reported with the divide of the position, compared to the
+/// expected divide if the entry has one.
+pub fn run_suite(
+    entries: &[SuiteEntry],
+    perft: &Perft,
+    out: &mut impl Write,
+) -> io::Result<SuiteReport> {
+    let mut report = SuiteReport::default();
+    let max_depth = |entry: &SuiteEntry| entry.expected.iter().map(|&(depth, _)| depth).max();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let position =
+            Position::from_fen(&entry.fen).expect("FEN should be validated when parsing");
+        for &(depth, expected) in &entry.expected {
+            let start = Instant::now();
+            let divide = perft.divide(&position, depth);
+            report.elapsed += start.elapsed();
+            let nodes = if depth > 0 {
+                divide.iter().map(|(_, stats)| stats.nodes).sum()
+            } else {
+                1
+            };
+            report.nodes += nodes;
+
+            if nodes == expected {
+                report.passed += 1;
+                writeln!(out, "ok   {}: depth {depth}, {nodes} nodes", i + 1)?;
+                continue;
+            }
+
+            report.failed += 1;
+            writeln!(
+                out,
+                "FAIL {}: {} depth {depth}, expected {expected} nodes, got {nodes}",
+                i + 1,
+                entry.fen
+            )?;
+            let expected_divide = entry
+                .divide
+                .as_ref()
+                .filter(|_| Some(depth) == max_depth(entry));
+            for line in divide_diff(&divide, expected_divide) {
+                writeln!(out, "  {line}")?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns the lines of a divide. If `expected` is given, only moves with
+/// counts differing from it are included.
+pub fn divide_diff(
+    divide: &[(Move, PerftStats)],
+    expected: Option<&HashMap<String, u64>>,
+) -> Vec<String> {
+    let Some(expected) = expected else {
+        return divide
+            .iter()
+            .map(|(mv, stats)| format!("{mv}: {}", stats.nodes))
+            .collect();
+    };
+
+    let mut lines = Vec::new();
+    for (mv, stats) in divide {
+        let mv = mv.to_string();
+        match expected.get(&mv) {
+            Some(&nodes) if nodes == stats.nodes => {}
+            Some(&nodes) => lines.push(format!("{mv}: expected {nodes}, got {}", stats.nodes)),
+            None => lines.push(format!("{mv}: not expected, got {}", stats.nodes)),
+        }
+    }
+
+    let mut missing: Vec<_> = expected
+        .iter()
+        .filter(|(mv, _)| !divide.iter().any(|(m, _)| m.to_string() == **mv))
+        .collect();
+    missing.sort();
+    for (mv, nodes) in missing {
+        lines.push(format!("{mv}: expected {nodes}, not generated"));
+    }
+
+    lines
+}
+
+/// An error that can occur when parsing a perft suite.
+#[derive(thiserror::Error, Debug)]
+pub enum ParseSuiteError {
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid EPD on line {0}")]
+    InvalidLine(usize),
+    #[error("invalid FEN '{fen}': {source}")]
+    InvalidFen { fen: String, source: ParseFenError },
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use kingly_lib::move_gen::Perft;
+use kingly_lib::Position;
+
+use super::*;
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+#[test]
+fn parses_epd_suites() {
+    let suite = "\
+# Comment
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400
+
+4k3/8/8/8/8/8/8/4K2R w K - 0 1 ;D1 15
+";
+    let entries = parse_suite(suite).unwrap();
+    assert_eq!(
+        entries,
+        [
+            SuiteEntry {
+                fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                expected: vec![(1, 20), (2, 400)],
+                divide: None,
+            },
+            SuiteEntry {
+                fen: "4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string(),
+                expected: vec![(1, 15)],
+                divide: None,
+            },
+        ]
+    );
+
+    assert!(matches!(
+        parse_suite("8/8/8/8/8/8/8/8 w - - 0 1 ;D1 x"),
+        Err(ParseSuiteError::InvalidLine(1))
+    ));
+    assert!(matches!(
+        parse_suite("8/8/8 w - - 0 1 ;D1 0"),
+        Err(ParseSuiteError::InvalidFen { .. })
+    ));
+}
+
+#[test]
+fn parses_json_suites() {
+    let suite = format!(
+        r#"[
+            {{"depth": 1, "nodes": 20, "fen": "{}"}},
+            {{"depth": 1, "nodes": 48, "fen": "{KIWIPETE}", "divide": {{"e1g1": 1}}}}
+        ]"#,
+        Position::new().to_fen()
+    );
+    let entries = parse_suite(&suite).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].expected, [(1, 20)]);
+    assert_eq!(entries[0].divide, None);
+    assert_eq!(entries[1].fen, KIWIPETE);
+    assert_eq!(
+        entries[1].divide,
+        Some(HashMap::from([("e1g1".to_string(), 1)]))
+    );
+
+    assert!(matches!(parse_suite("[{}]"), Err(ParseSuiteError::Json(_))));
+}
+
+#[test]
+fn runs_suites_and_reports_mismatches() {
+    let entries = [
+        SuiteEntry {
+            fen: Position::new().to_fen(),
+            expected: vec![(1, 20), (2, 400)],
+            divide: None,
+        },
+        SuiteEntry {
+            fen: KIWIPETE.to_string(),
+            expected: vec![(1, 47)],
+            divide: None,
+        },
+    ];
+    let mut out = Vec::new();
+    let report = run_suite(&entries, &Perft::new().threads(1), &mut out).unwrap();
+    assert_eq!(report.passed, 2);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.nodes, 20 + 400 + 48);
+
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(&format!(
+        "FAIL 2: {KIWIPETE} depth 1, expected 47 nodes, got 48"
+    )));
+    assert!(out.contains("  e1g1: 1"));
+}
+
+#[test]
+fn divide_diff_lists_differing_moves() {
+    let position: Position = KIWIPETE.parse().unwrap();
+    let divide = Perft::new().threads(1).divide(&position, 2);
+    let mut expected: HashMap<_, _> = divide
+        .iter()
+        .map(|(mv, stats)| (mv.to_string(), stats.nodes))
+        .collect();
+    assert_eq!(divide_diff(&divide, None).len(), 48);
+    assert!(divide_diff(&divide, Some(&expected)).is_empty());
+
+    *expected.get_mut("e1g1").unwrap() += 1;
+    expected.remove("a2a3");
+    expected.insert("e1e3".to_string(), 5);
+    assert_eq!(
+        divide_diff(&divide, Some(&expected)),
+        [
+            "a2a3: not expected, got 44",
+            "e1g1: expected 44, got 43",
+            "e1e3: expected 5, not generated",
+        ]
+    );
+}
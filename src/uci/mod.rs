@@ -1,19 +1,27 @@
 use std::fmt::{self, Display, Formatter};
 use std::io::{self, BufRead, StdoutLock, Write};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use std::{panic, process, thread};
 
 use crossbeam::channel::{self, Receiver, Sender};
+use kingly_lib::book::{Book, LoadBookError};
+use kingly_lib::eval::nnue::LoadNetworkError;
+use kingly_lib::eval::{wdl_material, NnueEval, RuntimeEval, StandardEval, WDL_MODEL};
+use kingly_lib::move_gen::Perft;
 use kingly_lib::position::{ParseFenError, STARTING_FEN};
 use kingly_lib::search::{
-    info_channel, InfoSender, SearchInfo, SearchJob, ThreadPool, DEFAULT_HASH_SIZE, DEFAULT_THREADS,
+    info_channel, InfoSender, SearchInfo, SearchJob, Skill, ThreadPool, DEFAULT_HASH_SIZE,
+    DEFAULT_THREADS, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO,
 };
+use kingly_lib::syzygy::{LoadTablebaseError, Tablebase};
 use kingly_lib::tables::Tables;
 use kingly_lib::time_mananger::TimeControl;
 use kingly_lib::types::{Color, IllegalMoveError, PseudoMove};
 use kingly_lib::{MoveGen, Position};
 use once_cell::unsync::Lazy;
+use rand::thread_rng;
 
 #[cfg(test)]
 mod tests;
@@ -24,7 +32,17 @@ pub struct Uci<W> {
     // TODO: Switch to LazyCell, once DerefMut is stabilized
     position: Lazy<Position>,
     debug_mode: bool,
-    thread_pool: Lazy<ThreadPool>,
+    thread_pool: Lazy<ThreadPool<RuntimeEval>>,
+    eval: RuntimeEval,
+    tablebase: Option<Arc<Tablebase>>,
+    own_book: bool,
+    book: Option<Book>,
+    chess960: bool,
+    show_wdl: bool,
+    skill_level: u8,
+    skill_seed: u64,
+    limit_strength: bool,
+    elo: u32,
 }
 
 impl Uci<StdoutLock<'_>> {
@@ -40,6 +58,16 @@ impl Uci<StdoutLock<'_>> {
             position: Lazy::new(Position::new),
             debug_mode: false,
             thread_pool: Lazy::new(ThreadPool::new),
+            eval: RuntimeEval::default(),
+            tablebase: None,
+            own_book: false,
+            book: None,
+            chess960: false,
+            show_wdl: false,
+            skill_level: MAX_SKILL_LEVEL,
+            skill_seed: SKILL_SEED_RANDOM,
+            limit_strength: false,
+            elo: DEFAULT_ELO,
         }
     }
 }
@@ -94,6 +122,47 @@ impl<W: Write> Uci<W> {
             "option name Threads type spin default {} min 1 max 64",
             DEFAULT_THREADS
         )?;
+        writeln!(
+            self.write_handle,
+            "option name EvalFile type string default {EVAL_FILE_NONE}"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name SyzygyPath type string default {SYZYGY_PATH_EMPTY}"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name OwnBook type check default false"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name BookFile type string default {BOOK_FILE_EMPTY}"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name UCI_Chess960 type check default false"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name UCI_ShowWDL type check default false"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name Skill Level type spin default {MAX_SKILL_LEVEL} min 0 max {MAX_SKILL_LEVEL}"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name Skill Seed type spin default {SKILL_SEED_RANDOM} min 0 max {}",
+            i64::MAX
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name UCI_LimitStrength type check default false"
+        )?;
+        writeln!(
+            self.write_handle,
+            "option name UCI_Elo type spin default {DEFAULT_ELO} min {MIN_ELO} max {MAX_ELO}"
+        )?;
         writeln!(self.write_handle, "uciok")?;
         self.write_handle.flush()
     }
@@ -119,16 +188,33 @@ impl<W: Write> Uci<W> {
             } => {
                 write!(
                     self.write_handle,
-                    "info depth {} seldepth {} score {} nodes {} nps {} hashfull {} pv",
-                    depth, stats.sel_depth, evaluation.score, stats.nodes, nps, hash_full,
+                    "info depth {} seldepth {} score {}",
+                    depth, stats.sel_depth, evaluation.score,
+                )?;
+                if self.show_wdl {
+                    let wdl = WDL_MODEL.wdl(evaluation.score, wdl_material(&self.position));
+                    write!(
+                        self.write_handle,
+                        " wdl {} {} {}",
+                        wdl.win, wdl.draw, wdl.loss
+                    )?;
+                }
+                write!(
+                    self.write_handle,
+                    " nodes {} nps {} hashfull {} tbhits {} pv",
+                    stats.nodes, nps, hash_full, stats.tb_hits,
                 )?;
                 for mv in &evaluation.pv {
-                    write!(self.write_handle, " {}", mv)?;
+                    write!(self.write_handle, " {}", mv.display(self.chess960))?;
                 }
                 writeln!(self.write_handle, " time {}", total_duration.as_millis())?;
             }
             SearchInfo::Finished(best_mv) => {
-                writeln!(self.write_handle, "bestmove {}", best_mv)?;
+                writeln!(
+                    self.write_handle,
+                    "bestmove {}",
+                    best_mv.display(self.chess960)
+                )?;
             }
         }
         self.write_handle.flush()
@@ -164,9 +250,44 @@ impl<W: Write> Uci<W> {
                         self.print_debug("Cannot set threads while search is running")?;
                     }
                 }
+                UciOption::EvalFile(path) => {
+                    self.eval = match path.as_str() {
+                        EVAL_FILE_NONE => RuntimeEval::Standard(StandardEval),
+                        EVAL_FILE_EMBEDDED => RuntimeEval::Nnue(NnueEval::default()),
+                        _ => RuntimeEval::Nnue(NnueEval::load(&path)?),
+                    };
+                }
+                UciOption::SyzygyPath(path) => {
+                    self.tablebase = None;
+                    if path != SYZYGY_PATH_EMPTY {
+                        let tablebase = Tablebase::open(&path)?;
+                        writeln!(
+                            self.write_handle,
+                            "info string Found {} tablebases",
+                            tablebase.num_tables()
+                        )?;
+                        self.write_handle.flush()?;
+                        self.tablebase = Some(Arc::new(tablebase));
+                    }
+                }
+                UciOption::OwnBook(value) => self.own_book = value,
+                UciOption::Chess960(value) => self.chess960 = value,
+                UciOption::ShowWdl(value) => self.show_wdl = value,
+                UciOption::SkillLevel(value) => self.skill_level = value,
+                UciOption::SkillSeed(value) => self.skill_seed = value,
+                UciOption::LimitStrength(value) => self.limit_strength = value,
+                UciOption::Elo(value) => self.elo = value,
+                UciOption::BookFile(path) => {
+                    self.book = None;
+                    if path != BOOK_FILE_EMPTY {
+                        self.book = Some(Book::open(&path)?);
+                    }
+                }
             },
             Command::UciNewGame => {
-                if self.thread_pool.clear_t_table().is_err() {
+                if self.thread_pool.clear_t_table().is_err()
+                    || self.thread_pool.clear_history().is_err()
+                {
                     self.print_debug("Search is running")?;
                 }
                 self.position.set_fen(STARTING_FEN)?;
@@ -180,7 +301,34 @@ impl<W: Write> Uci<W> {
                 }
             }
             Command::Go(options) => {
-                let mut builder = SearchJob::default_builder().position(self.position.clone());
+                // Book moves are only played when the GUI expects a move
+                let use_book = self.own_book
+                    && !options
+                        .iter()
+                        .any(|opt| matches!(opt, GoOption::Infinite | GoOption::SearchMoves(_)));
+                if let Some(book) = self.book.as_ref().filter(|_| use_book) {
+                    if let Some(mv) = book.pick_move(&self.position, &mut thread_rng()) {
+                        self.print_info(&SearchInfo::Finished(mv))?;
+                        return Ok(());
+                    }
+                }
+
+                let mut builder =
+                    SearchJob::builder(self.eval.clone()).position(self.position.clone());
+                if let Some(tablebase) = &self.tablebase {
+                    builder = builder.tablebase(Arc::clone(tablebase));
+                }
+                let skill = if self.limit_strength {
+                    Skill::from_elo(self.elo)
+                } else {
+                    Skill::new(self.skill_level)
+                };
+                if let Some(skill) = skill {
+                    builder = builder.skill(skill);
+                }
+                if self.skill_seed != SKILL_SEED_RANDOM {
+                    builder = builder.seed(self.skill_seed);
+                }
                 let mut white_tc = None;
                 let mut black_tc = None;
                 let mut move_time = None;
@@ -245,6 +393,27 @@ impl<W: Write> Uci<W> {
                 self.print_debug("Unsupported command: ponderhit")?;
             }
             Command::Quit => process::exit(0),
+            Command::Perft(depth) => {
+                let divide = Perft::new().divide(&self.position, depth);
+                for (mv, stats) in &divide {
+                    writeln!(
+                        self.write_handle,
+                        "{}: {}",
+                        mv.display(self.chess960),
+                        stats.nodes
+                    )?;
+                }
+                let nodes: u64 = divide.iter().map(|(_, stats)| stats.nodes).sum();
+                writeln!(self.write_handle, "\nNodes searched: {nodes}")?;
+                self.write_handle.flush()?;
+            }
+            Command::Display => {
+                writeln!(self.write_handle, "{}", *self.position)?;
+                writeln!(self.write_handle, "Fen: {}", self.position.to_fen())?;
+                writeln!(self.write_handle, "Key: {:016X}", self.position.zobrist)?;
+                self.write_handle.flush()?;
+            }
+            Command::Flip => *self.position = self.position.flipped(),
         }
         Ok(())
     }
@@ -267,8 +436,28 @@ enum UciError {
     ParseCommand(#[from] ParseCommandError),
     #[error("illegal move: {0}")]
     IllegalMove(#[from] IllegalMoveError),
+    #[error("failed to load network: {0}")]
+    LoadNetwork(#[from] LoadNetworkError),
+    #[error("failed to load tablebases: {0}")]
+    LoadTablebase(#[from] LoadTablebaseError),
+    #[error("failed to load opening book: {0}")]
+    LoadBook(#[from] LoadBookError),
 }
 
+/// The `EvalFile` value selecting the standard piece-square table evaluation.
+const EVAL_FILE_NONE: &str = "<none>";
+/// The `EvalFile` value selecting the network embedded in the engine.
+const EVAL_FILE_EMBEDDED: &str = "<embedded>";
+/// The `SyzygyPath` value disabling tablebases.
+const SYZYGY_PATH_EMPTY: &str = "<empty>";
+/// The `BookFile` value disabling the opening book.
+const BOOK_FILE_EMPTY: &str = "<empty>";
+/// The default value of `UCI_Elo`.
+const DEFAULT_ELO: u32 = 1500;
+/// The `Skill Seed` value picking moves with entropy from the system instead
+/// of a fixed seed.
+const SKILL_SEED_RANDOM: u64 = 0;
+
 #[derive(Debug, PartialEq)]
 enum Command {
     Uci,
@@ -276,17 +465,36 @@ enum Command {
     IsReady,
     SetOption(UciOption),
     UciNewGame,
-    Position { fen: String, moves: Vec<PseudoMove> },
+    Position {
+        fen: String,
+        moves: Vec<PseudoMove>,
+    },
     Go(Vec<GoOption>),
     Stop,
     PonderHit,
     Quit,
+    /// `go perft <depth>`, which prints the node count of each move.
+    Perft(i8),
+    /// `d`, which prints the board.
+    Display,
+    /// `flip`, which mirrors the position and swaps the colors.
+    Flip,
 }
 
 #[derive(Debug, PartialEq)]
 enum UciOption {
     Hash(usize),
     Threads(usize),
+    EvalFile(String),
+    SyzygyPath(String),
+    OwnBook(bool),
+    BookFile(String),
+    Chess960(bool),
+    ShowWdl(bool),
+    SkillLevel(u8),
+    SkillSeed(u64),
+    LimitStrength(bool),
+    Elo(u32),
 }
 
 impl Display for UciOption {
@@ -294,6 +502,16 @@ impl Display for UciOption {
         match self {
             UciOption::Hash(value) => write!(f, "Hash value {value}"),
             UciOption::Threads(value) => write!(f, "Threads value {value}"),
+            UciOption::EvalFile(value) => write!(f, "EvalFile value {value}"),
+            UciOption::SyzygyPath(value) => write!(f, "SyzygyPath value {value}"),
+            UciOption::OwnBook(value) => write!(f, "OwnBook value {value}"),
+            UciOption::BookFile(value) => write!(f, "BookFile value {value}"),
+            UciOption::Chess960(value) => write!(f, "UCI_Chess960 value {value}"),
+            UciOption::ShowWdl(value) => write!(f, "UCI_ShowWDL value {value}"),
+            UciOption::SkillLevel(value) => write!(f, "Skill Level value {value}"),
+            UciOption::SkillSeed(value) => write!(f, "Skill Seed value {value}"),
+            UciOption::LimitStrength(value) => write!(f, "UCI_LimitStrength value {value}"),
+            UciOption::Elo(value) => write!(f, "UCI_Elo value {value}"),
         }
     }
 }
@@ -353,6 +571,104 @@ impl FromStr for Command {
                         let value = parse_next_option(&mut opts)?;
                         Ok(Self::SetOption(UciOption::Threads(value)))
                     }
+                    Some("EvalFile") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword("EvalFile".into()));
+                        }
+                        // Paths may contain spaces
+                        let value = opts.collect::<Vec<_>>().join(" ");
+                        if value.is_empty() {
+                            return Err(ParseCommandError::MissingOption);
+                        }
+                        Ok(Self::SetOption(UciOption::EvalFile(value)))
+                    }
+                    Some("SyzygyPath") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword(
+                                "SyzygyPath".into(),
+                            ));
+                        }
+                        let value = opts.collect::<Vec<_>>().join(" ");
+                        if value.is_empty() {
+                            return Err(ParseCommandError::MissingOption);
+                        }
+                        Ok(Self::SetOption(UciOption::SyzygyPath(value)))
+                    }
+                    Some("OwnBook") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword("OwnBook".into()));
+                        }
+                        let value = parse_next_option(&mut opts)?;
+                        Ok(Self::SetOption(UciOption::OwnBook(value)))
+                    }
+                    Some("BookFile") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword("BookFile".into()));
+                        }
+                        let value = opts.collect::<Vec<_>>().join(" ");
+                        if value.is_empty() {
+                            return Err(ParseCommandError::MissingOption);
+                        }
+                        Ok(Self::SetOption(UciOption::BookFile(value)))
+                    }
+                    Some("UCI_Chess960") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword(
+                                "UCI_Chess960".into(),
+                            ));
+                        }
+                        let value = parse_next_option(&mut opts)?;
+                        Ok(Self::SetOption(UciOption::Chess960(value)))
+                    }
+                    Some("UCI_ShowWDL") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword(
+                                "UCI_ShowWDL".into(),
+                            ));
+                        }
+                        let value = parse_next_option(&mut opts)?;
+                        Ok(Self::SetOption(UciOption::ShowWdl(value)))
+                    }
+                    Some("Skill") => match opts.next() {
+                        Some("Level") => {
+                            if opts.next() != Some("value") {
+                                return Err(ParseCommandError::MissingValueKeyword(
+                                    "Skill Level".into(),
+                                ));
+                            }
+                            let value: u8 = parse_next_option(&mut opts)?;
+                            if value > MAX_SKILL_LEVEL {
+                                return Err(ParseCommandError::InvalidOption(value.to_string()));
+                            }
+                            Ok(Self::SetOption(UciOption::SkillLevel(value)))
+                        }
+                        Some("Seed") => {
+                            if opts.next() != Some("value") {
+                                return Err(ParseCommandError::MissingValueKeyword(
+                                    "Skill Seed".into(),
+                                ));
+                            }
+                            let value = parse_next_option(&mut opts)?;
+                            Ok(Self::SetOption(UciOption::SkillSeed(value)))
+                        }
+                        _ => Err(ParseCommandError::UsupportedOption("Skill".into())),
+                    },
+                    Some("UCI_LimitStrength") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword(
+                                "UCI_LimitStrength".into(),
+                            ));
+                        }
+                        let value = parse_next_option(&mut opts)?;
+                        Ok(Self::SetOption(UciOption::LimitStrength(value)))
+                    }
+                    Some("UCI_Elo") => {
+                        if opts.next() != Some("value") {
+                            return Err(ParseCommandError::MissingValueKeyword("UCI_Elo".into()));
+                        }
+                        let value = parse_next_option(&mut opts)?;
+                        Ok(Self::SetOption(UciOption::Elo(value)))
+                    }
                     Some(name) => Err(ParseCommandError::UsupportedOption(name.into())),
                     None => Err(ParseCommandError::MissingOption),
                 }
@@ -376,6 +692,10 @@ impl FromStr for Command {
                     .collect();
                 Ok(Self::Position { fen, moves })
             }
+            "go" if opts.split_whitespace().next() == Some("perft") => {
+                let mut opts = opts.split_whitespace().skip(1);
+                Ok(Self::Perft(parse_next_option(&mut opts)?))
+            }
             "go" => {
                 let mut options = Vec::new();
                 let mut opts = opts.split_whitespace().peekable();
@@ -440,6 +760,8 @@ impl FromStr for Command {
             "stop" => Ok(Self::Stop),
             "ponderhit" => Ok(Self::PonderHit),
             "quit" => Ok(Self::Quit),
+            "d" => Ok(Self::Display),
+            "flip" => Ok(Self::Flip),
             _ => Err(ParseCommandError::UnsupportedCommand(s.into())),
         }
     }
@@ -477,6 +799,9 @@ impl Display for Command {
             Command::Stop => write!(f, "stop"),
             Command::PonderHit => write!(f, "ponderhit"),
             Command::Quit => write!(f, "quit"),
+            Command::Perft(depth) => write!(f, "go perft {depth}"),
+            Command::Display => write!(f, "d"),
+            Command::Flip => write!(f, "flip"),
         }
     }
 }
@@ -42,6 +42,104 @@ fn test_parse_setoption_hash() {
     assert_eq!(input.parse::<Command>(), Ok(expected));
 }
 
+#[test]
+fn test_parse_setoption_eval_file() {
+    let input = "setoption name EvalFile value nets/my net.bin";
+    let expected = Command::SetOption(UciOption::EvalFile("nets/my net.bin".into()));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_eval_file_missing_value() {
+    let input = "setoption name EvalFile value";
+    let expected = ParseCommandError::MissingOption;
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_setoption_syzygy_path() {
+    let input = "setoption name SyzygyPath value /tb/wdl:/tb/dtz";
+    let expected = Command::SetOption(UciOption::SyzygyPath("/tb/wdl:/tb/dtz".into()));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_syzygy_path_missing_value() {
+    let input = "setoption name SyzygyPath";
+    let expected = ParseCommandError::MissingValueKeyword("SyzygyPath".into());
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_setoption_own_book() {
+    let input = "setoption name OwnBook value true";
+    let expected = Command::SetOption(UciOption::OwnBook(true));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_own_book_invalid_value() {
+    let input = "setoption name OwnBook value yes";
+    let expected = ParseCommandError::InvalidOption("yes".into());
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_setoption_book_file() {
+    let input = "setoption name BookFile value books/my book.bin";
+    let expected = Command::SetOption(UciOption::BookFile("books/my book.bin".into()));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_chess960() {
+    let input = "setoption name UCI_Chess960 value true";
+    let expected = Command::SetOption(UciOption::Chess960(true));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_show_wdl() {
+    let input = "setoption name UCI_ShowWDL value true";
+    let expected = Command::SetOption(UciOption::ShowWdl(true));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_skill_level() {
+    let input = "setoption name Skill Level value 5";
+    let expected = Command::SetOption(UciOption::SkillLevel(5));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_skill_level_out_of_range() {
+    let input = "setoption name Skill Level value 21";
+    let expected = ParseCommandError::InvalidOption("21".into());
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_setoption_skill_seed() {
+    let input = "setoption name Skill Seed value 42";
+    let expected = Command::SetOption(UciOption::SkillSeed(42));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_limit_strength() {
+    let input = "setoption name UCI_LimitStrength value true";
+    let expected = Command::SetOption(UciOption::LimitStrength(true));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_setoption_elo() {
+    let input = "setoption name UCI_Elo value 1800";
+    let expected = Command::SetOption(UciOption::Elo(1800));
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
 #[test]
 fn test_parse_setoption_unsupported() {
     let input = "setoption name Unsupported";
@@ -224,6 +322,42 @@ fn test_parse_quit() {
     assert_eq!(input.parse::<Command>(), Ok(expected));
 }
 
+#[test]
+fn test_parse_go_perft() {
+    let input = "go perft 5";
+    let expected = Command::Perft(5);
+    assert_eq!(expected.to_string(), input);
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_go_perft_missing_depth() {
+    let input = "go perft";
+    let expected = ParseCommandError::MissingOption;
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_go_perft_invalid_depth() {
+    let input = "go perft x";
+    let expected = ParseCommandError::InvalidOption("x".into());
+    assert_eq!(input.parse::<Command>(), Err(expected));
+}
+
+#[test]
+fn test_parse_d() {
+    let input = "d";
+    let expected = Command::Display;
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
+#[test]
+fn test_parse_flip() {
+    let input = "flip";
+    let expected = Command::Flip;
+    assert_eq!(input.parse::<Command>(), Ok(expected));
+}
+
 #[test]
 fn test_parse_unsupported_command() {
     let input = "unsupported";
@@ -0,0 +1,42 @@
+//! The Adam optimizer used by the tuning binaries.
+
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// Gradient descent with Adam, which keeps moving averages of the gradient
+/// and its square for every parameter.
+pub struct Adam {
+    learning_rate: f64,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    steps: i32,
+}
+
+impl Adam {
+    /// Creates an optimizer for `num_params` parameters.
+    pub fn new(num_params: usize, learning_rate: f64) -> Self {
+        Self {
+            learning_rate,
+            m: vec![0.; num_params],
+            v: vec![0.; num_params],
+            steps: 0,
+        }
+    }
+
+    /// Moves the parameters against the gradient, which must have an entry
+    /// for each parameter in the same order.
+    pub fn step<'a>(&mut self, params: impl IntoIterator<Item = &'a mut f64>, gradient: &[f64]) {
+        assert_eq!(gradient.len(), self.m.len(), "wrong number of gradients");
+        self.steps += 1;
+        let m_correction = 1. - BETA1.powi(self.steps);
+        let v_correction = 1. - BETA2.powi(self.steps);
+        for (i, param) in params.into_iter().enumerate() {
+            self.m[i] = BETA1 * self.m[i] + (1. - BETA1) * gradient[i];
+            self.v[i] = BETA2 * self.v[i] + (1. - BETA2) * gradient[i] * gradient[i];
+            let m_hat = self.m[i] / m_correction;
+            let v_hat = self.v[i] / v_correction;
+            *param -= self.learning_rate * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+    }
+}
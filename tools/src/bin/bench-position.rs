@@ -0,0 +1,100 @@
+//! Benchmarks the position operations that depend on the move history.
+//!
+//! Times making and unmaking a move with 80 plies of history, cloning a
+//! position with and without history, and perft 4 of kiwipete. Compare two
+//! builds by running each a few times with
+//! `cargo run --release -p kingly-tools --bin bench-position`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use clap::Parser;
+use kingly_lib::types::PseudoMove;
+use kingly_lib::{MoveGen, Position};
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+#[derive(Parser)]
+struct App {
+    /// Number of iterations of the make, unmake and clone benchmarks
+    #[clap(short, long, default_value_t = 10_000_000)]
+    iterations: u32,
+}
+
+fn main() -> anyhow::Result<()> {
+    let app = App::parse();
+    let move_gen = MoveGen::init();
+
+    // Shuffling the knights back and forth never resets the ply clock, so
+    // all of the history is scanned for repetitions
+    let mut position = Position::new();
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    for mv in shuffle.iter().cycle().take(80) {
+        let legal_moves = move_gen.gen_all_moves(&position);
+        let mv = mv
+            .parse::<PseudoMove>()?
+            .into_move(&legal_moves)
+            .context("shuffle move is illegal")?;
+        position.make_move(mv);
+    }
+
+    let legal_moves = move_gen.gen_all_moves(&position);
+    let mut moves = legal_moves.iter().cycle();
+    let elapsed = time(app.iterations, || {
+        position.make_move(black_box(*moves.next().unwrap()));
+        position.unmake_move();
+    });
+    report(
+        "make + unmake, 80 plies of history",
+        elapsed,
+        app.iterations,
+    );
+
+    let elapsed = time(app.iterations, || {
+        black_box(black_box(&position).clone());
+    });
+    report("clone, 80 plies of history", elapsed, app.iterations);
+
+    let mut kiwipete = Position::from_fen(KIWIPETE)?;
+    let elapsed = time(app.iterations, || {
+        black_box(black_box(&kiwipete).clone());
+    });
+    report("clone, no history", elapsed, app.iterations);
+
+    // Unlike `MoveGen::perft`, every leaf is made and unmade
+    let start = Instant::now();
+    let leaves = perft(&move_gen, &mut kiwipete, 4);
+    let elapsed = start.elapsed();
+    anyhow::ensure!(leaves == 4_085_603, "wrong perft result {leaves}");
+    report("perft 4 of kiwipete (per leaf)", elapsed, leaves as u32);
+
+    Ok(())
+}
+
+fn perft(move_gen: &MoveGen, position: &mut Position, depth: i8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut count = 0;
+    for mv in move_gen.gen_all_moves(position) {
+        position.make_move(mv);
+        count += perft(move_gen, position, depth - 1);
+        position.unmake_move();
+    }
+    count
+}
+
+fn time(iterations: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration, iterations: u32) {
+    let ns = elapsed.as_nanos() as f64 / iterations as f64;
+    println!("{name:<36} {ns:>8.1} ns");
+}
@@ -73,8 +73,8 @@ fn main() -> anyhow::Result<()> {
 
     thread::sleep(Duration::from_secs(2));
 
-    engines.new_proc.close();
-    engines.old_proc.close();
+    engines.new_proc.close().wait()?;
+    engines.old_proc.close().wait()?;
 
     Ok(())
 }
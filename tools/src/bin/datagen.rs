@@ -0,0 +1,165 @@
+//! Generates training data by letting the engine play against itself.
+//!
+//! Each game starts with a number of random plies and is then played with a
+//! fixed node limit per move on a single thread, clearing the transposition
+//! table between games, so the output only depends on the seed and options.
+//! Quiet positions are written as `<fen> | <score> | <result>` lines, where
+//! the score (in centipawns) and the result (1.0, 0.5 or 0.0) are from
+//! white's perspective.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use kingly_lib::search::{SearchEvaluation, SearchJob, ThreadPool};
+use kingly_lib::types::{Color, Move};
+use kingly_lib::{MoveGen, Position};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+#[derive(Parser)]
+struct App {
+    /// Where to write the positions
+    output: PathBuf,
+    #[clap(short, long, default_value_t = 100)]
+    games: u64,
+    /// Nodes to search per move
+    #[clap(short, long, default_value_t = 5000)]
+    nodes: u64,
+    #[clap(short, long, default_value_t = 0)]
+    seed: u64,
+    /// Number of random plies at the start of each game
+    #[clap(long = "random-plies", default_value_t = 8)]
+    random_plies: usize,
+    /// Score in centipawns at which a game is adjudicated as won
+    #[clap(long = "win-score", default_value_t = 2000)]
+    win_score: i16,
+    /// Number of plies after which a game is adjudicated as drawn
+    #[clap(long = "max-plies", default_value_t = 400)]
+    max_plies: usize,
+}
+
+/// Number of consecutive plies the score should be above the win score for
+/// a game to be adjudicated.
+const WIN_PLIES: usize = 4;
+
+fn main() -> anyhow::Result<()> {
+    let app = App::parse();
+
+    let mut thread_pool = ThreadPool::new();
+    thread_pool
+        .set_num_threads(1)
+        .expect("search is not running");
+    let move_gen = MoveGen::init();
+    let mut output = BufWriter::new(File::create(&app.output)?);
+
+    let mut total_positions = 0;
+    for game in 0..app.games {
+        // Seed each game separately, so games can be reproduced individually
+        let mut rng = ChaCha8Rng::seed_from_u64(app.seed);
+        rng.set_stream(game);
+        let Some(position) = random_opening(&mut rng, &move_gen, app.random_plies) else {
+            continue;
+        };
+
+        thread_pool.clear_t_table().expect("search is not running");
+        thread_pool.clear_history().expect("search is not running");
+        let (positions, result) = play_game(&app, position, &mut thread_pool, &move_gen);
+        for (fen, score) in &positions {
+            writeln!(output, "{fen} | {score} | {result:.1}")?;
+        }
+        total_positions += positions.len();
+        println!(
+            "Game {}/{}: {result:.1}, {} positions ({total_positions} total)",
+            game + 1,
+            app.games,
+            positions.len()
+        );
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Plays random moves from the starting position. Returns `None` if the game
+/// ended during the opening.
+fn random_opening(rng: &mut ChaCha8Rng, move_gen: &MoveGen, plies: usize) -> Option<Position> {
+    let mut position = Position::new();
+    for _ in 0..plies {
+        let moves = move_gen.gen_all_moves(&position);
+        if moves.is_empty() {
+            return None;
+        }
+        position.make_move(moves[rng.gen_range(0..moves.len())]);
+    }
+    (!move_gen.gen_all_moves(&position).is_empty()).then_some(position)
+}
+
+/// Plays a game from `position`, returning the quiet positions with their
+/// white relative scores, and the result of the game.
+fn play_game(
+    app: &App,
+    mut position: Position,
+    thread_pool: &mut ThreadPool,
+    move_gen: &MoveGen,
+) -> (Vec<(String, i16)>, f64) {
+    let mut positions = Vec::new();
+    let mut win_plies = 0;
+    for _ in 0..app.max_plies {
+        let (moves, check) = move_gen.gen_all_moves_and_check(&position);
+        if moves.is_empty() {
+            let result = match (check, position.to_move) {
+                (false, _) => 0.5,
+                (true, Color::White) => 0.,
+                (true, Color::Black) => 1.,
+            };
+            return (positions, result);
+        }
+        if position.is_rule_draw() {
+            return (positions, 0.5);
+        }
+
+        let job = SearchJob::default_builder()
+            .position(position.clone())
+            .nodes(app.nodes)
+            .build();
+        // The node limit is only checked every 2048 nodes, which is far more
+        // than the first iteration needs
+        let evaluation = search(thread_pool, job).expect("the first iteration should finish");
+        let best_move = evaluation.pv[0];
+        let sign = position.to_move.sign() as i16;
+
+        if evaluation.score.is_centipawn() {
+            let score = sign * evaluation.score.into_inner();
+            if !check && !is_noisy(best_move) {
+                positions.push((position.to_fen(), score));
+            }
+
+            win_plies = if score.abs() >= app.win_score {
+                win_plies + 1
+            } else {
+                0
+            };
+            if win_plies >= WIN_PLIES {
+                return (positions, if score > 0 { 1. } else { 0. });
+            }
+        } else {
+            win_plies = 0;
+        }
+
+        position.make_move(best_move);
+    }
+    (positions, 0.5)
+}
+
+fn search(thread_pool: &mut ThreadPool, job: SearchJob) -> Option<SearchEvaluation> {
+    let _info_rx = thread_pool.run(job).expect("search is not running");
+    thread_pool.wait().and_then(|res| res.evaluation)
+}
+
+/// Returns whether the move is a capture or promotion, in which case the
+/// static evaluation of the position is unreliable.
+fn is_noisy(mv: Move) -> bool {
+    mv.capture() || mv.promotion().is_some()
+}
@@ -0,0 +1,162 @@
+//! Fits the win/draw/loss model to self-play results.
+//!
+//! Loads positions written by `datagen` as `<fen> | <score> | <result>` lines
+//! and maximizes the likelihood of the game results under the model using
+//! gradient descent (Adam). The fitted model is printed as Rust source for
+//! `WDL_MODEL`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use kingly_lib::eval::{wdl_material, WdlModel, WDL_MODEL};
+use kingly_lib::Position;
+use kingly_tools::adam::Adam;
+
+#[derive(Parser)]
+struct App {
+    /// File with positions written by datagen
+    input: PathBuf,
+    #[clap(short, long, default_value_t = 2000)]
+    epochs: usize,
+    #[clap(long = "learning-rate", default_value_t = 1.0)]
+    learning_rate: f64,
+}
+
+struct Entry {
+    /// The score in centipawns from white's perspective.
+    score: f64,
+    material: u32,
+    /// The index of the result in [`probabilities`].
+    outcome: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let app = App::parse();
+
+    let entries = load_entries(&app)?;
+    anyhow::ensure!(!entries.is_empty(), "no positions loaded");
+    println!("Loaded {} positions", entries.len());
+
+    let mut model = WDL_MODEL;
+    println!("Initial loss = {:.6}", loss(&entries, &model));
+
+    let mut adam = Adam::new(8, app.learning_rate);
+    for epoch in 1..=app.epochs {
+        let gradient = gradient(&entries, &model);
+        adam.step(model.a.iter_mut().chain(&mut model.b), &gradient);
+
+        if epoch % 100 == 0 || epoch == app.epochs {
+            println!("Epoch {epoch}: loss = {:.6}", loss(&entries, &model));
+        }
+    }
+
+    let coefficients = |c: &[f64; 4]| c.map(|c| format!("{c:.3}")).join(", ");
+    println!(
+        "pub const WDL_MODEL: WdlModel = WdlModel {{\n    a: [{}],\n    b: [{}],\n}};",
+        coefficients(&model.a),
+        coefficients(&model.b)
+    );
+    for material in [78, 58, 38, 18] {
+        let (a, b) = model.params(material);
+        println!("Material {material}: a = {a:.1}, b = {b:.1}");
+    }
+
+    Ok(())
+}
+
+fn load_entries(app: &App) -> anyhow::Result<Vec<Entry>> {
+    let file = File::open(&app.input).context("failed to open input")?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_line(&line).with_context(|| format!("line {}", i + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn parse_line(line: &str) -> anyhow::Result<Entry> {
+    let fields: Vec<_> = line.split('|').map(str::trim).collect();
+    let [fen, score, result] = fields[..] else {
+        bail!("expected '<fen> | <score> | <result>', got '{line}'");
+    };
+    let position = Position::from_fen(fen)?;
+    let outcome = match result {
+        "1.0" => 0,
+        "0.5" => 1,
+        "0.0" => 2,
+        res => bail!("invalid result '{res}'"),
+    };
+    Ok(Entry {
+        score: score.parse()?,
+        material: wdl_material(&position),
+        outcome,
+    })
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1. / (1. + (-x).exp())
+}
+
+/// Returns the probabilities of a white win, draw and black win.
+fn probabilities(entry: &Entry, a: f64, b: f64) -> [f64; 3] {
+    let win = sigmoid((entry.score - a) / b);
+    let loss = sigmoid((-entry.score - a) / b);
+    [win, (1. - win - loss).max(1e-9), loss]
+}
+
+/// The mean negative log-likelihood of the results.
+fn loss(entries: &[Entry], model: &WdlModel) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| {
+            let (a, b) = model.params(entry.material);
+            -probabilities(entry, a, b)[entry.outcome].ln()
+        })
+        .sum();
+    total / entries.len() as f64
+}
+
+fn gradient(entries: &[Entry], model: &WdlModel) -> [f64; 8] {
+    let mut gradient = [0.; 8];
+    for entry in entries {
+        let (a, b) = model.params(entry.material);
+        let [win, draw, loss] = probabilities(entry, a, b);
+        let x_win = (entry.score - a) / b;
+        let x_loss = (-entry.score - a) / b;
+
+        // Derivatives of the loss with respect to a and b, where x / b is the
+        // derivative of x with respect to b up to sign. The derivative of
+        // -ln(sigmoid(x)) is written as sigmoid(x) - 1 to avoid dividing by
+        // probabilities that underflow
+        let (da, db) = match entry.outcome {
+            0 => ((1. - win) / b, (1. - win) * x_win / b),
+            1 => {
+                let d_win = win * (1. - win);
+                let d_loss = loss * (1. - loss);
+                (
+                    -(d_win + d_loss) / b / draw,
+                    -(d_win * x_win + d_loss * x_loss) / b / draw,
+                )
+            }
+            _ => ((1. - loss) / b, (1. - loss) * x_loss / b),
+        };
+
+        // a and b are cubic in the normalized material
+        let x = WdlModel::normalize_material(entry.material);
+        let powers = [x.powi(3), x.powi(2), x, 1.];
+        for i in 0..4 {
+            gradient[i] += da * powers[i];
+            gradient[4 + i] += db * powers[i];
+        }
+    }
+    let n = entries.len() as f64;
+    gradient.iter_mut().for_each(|g| *g /= n);
+    gradient
+}
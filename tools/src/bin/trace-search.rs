@@ -91,6 +91,14 @@ fn show_forest(ui: &mut egui::Ui, forest: &mut Forest) {
     egui::ScrollArea::both()
         .auto_shrink([false, false])
         .show(ui, |ui| {
+            ui.label(format!(
+                "Pruned moves: {} by futility pruning, {} by late move pruning",
+                forest.futility_pruned, forest.late_move_pruned
+            ));
+            ui.label(format!(
+                "Cutoffs: {} by razoring, {} by ProbCut",
+                forest.razored, forest.probcut
+            ));
             let roots = forest.roots.clone();
             for root in roots {
                 show_node(ui, forest, root);
@@ -107,7 +115,7 @@ fn show_node(ui: &mut egui::Ui, forest: &mut Forest, index: usize) {
                 forest.expanded[index] = !forest.expanded[index];
             }
         }
-        ui.label(&format!("{node_data}"));
+        ui.label(format!("{node_data}"));
     });
     if forest.expanded[index] {
         ui.indent(index, |ui| {
@@ -126,6 +134,10 @@ struct Forest {
     children: Vec<Vec<usize>>,
     node_stack: Vec<usize>,
     expanded: Vec<bool>,
+    futility_pruned: usize,
+    late_move_pruned: usize,
+    razored: usize,
+    probcut: usize,
 }
 
 impl SearchObserver for Forest {
@@ -174,6 +186,13 @@ impl SearchObserver for Forest {
         ret: Self::ReturnKind,
         score: Option<Value>,
     ) {
+        match ret {
+            ReturnKind::FutilityPruning => self.futility_pruned += 1,
+            ReturnKind::LateMovePruning => self.late_move_pruned += 1,
+            ReturnKind::Razoring => self.razored += 1,
+            ReturnKind::ProbCut(_) => self.probcut += 1,
+            _ => {}
+        }
         let node = self.node_stack.pop().unwrap();
         let node_data = &mut self.nodes[node];
         let NodeData::PartialNode {
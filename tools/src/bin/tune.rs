@@ -0,0 +1,266 @@
+//! Texel tuning of the piece-square table evaluation.
+//!
+//! Loads quiet positions labelled with the game result and minimizes the mean
+//! squared error between the result and the sigmoid of the evaluation using
+//! gradient descent (Adam). The evaluation is linear in the parameters, so the
+//! gradient is computed exactly from the pieces on the board. Positions whose
+//! evaluation is scaled or replaced using endgame knowledge are not linear in
+//! the parameters, so they are skipped.
+//!
+//! Supported input lines (results are from white's perspective):
+//! - `<fen> | <score> | <result>`, as written by `datagen`
+//! - `<fen> [<result>]`
+//! - EPD with a `c9 "<result>";` opcode
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use kingly_lib::eval::{endgame, EvalParams};
+use kingly_lib::types::{Color, Square};
+use kingly_lib::{MoveGen, Position};
+use kingly_tools::adam::Adam;
+
+const NUM_PARAMS: usize = 2 * 6 * 64 + 1;
+const TEMPO: usize = NUM_PARAMS - 1;
+
+#[derive(Parser)]
+struct App {
+    /// File with labelled positions
+    input: PathBuf,
+    /// Weights file to start from. Defaults to the built-in tables
+    #[clap(short, long)]
+    params: Option<PathBuf>,
+    /// Where to write the tuned weights file
+    #[clap(short, long, default_value = "tuned-params.txt")]
+    output: PathBuf,
+    /// Also write the tuned weights as Rust source to this file
+    #[clap(long)]
+    rust: Option<PathBuf>,
+    #[clap(short, long, default_value_t = 1000)]
+    epochs: usize,
+    #[clap(long = "learning-rate", default_value_t = 1.0)]
+    learning_rate: f64,
+    /// Scaling constant of the sigmoid. Fitted to the data if not given
+    #[clap(short)]
+    k: Option<f64>,
+}
+
+/// A position reduced to the parameters it uses.
+struct Entry {
+    /// The parameter indices and their coefficients in the white relative
+    /// evaluation.
+    coefficients: Vec<(usize, f64)>,
+    result: f64,
+}
+
+impl Entry {
+    fn new(position: &Position, result: f64) -> Self {
+        let game_phase = position.game_phase() as f64;
+        let early_weight = game_phase / 26.;
+        let endgame_weight = (26. - game_phase) / 26.;
+
+        let mut coefficients = Vec::with_capacity(2 * position.pieces.count() + 1);
+        for sq in Square::iter() {
+            let Some(pce) = position.pieces.get(sq) else {
+                continue;
+            };
+            // Black pieces use the negated table mirrored like in EvalParams
+            let (sq, sign) = match pce.color() {
+                Color::White => (sq as usize, 1.),
+                Color::Black => (63 - sq as usize, -1.),
+            };
+            let index = pce.kind() as usize * 64 + sq;
+            coefficients.push((index, sign * early_weight));
+            coefficients.push((6 * 64 + index, sign * endgame_weight));
+        }
+        coefficients.push((TEMPO, position.to_move.sign() as f64));
+
+        Self {
+            coefficients,
+            result,
+        }
+    }
+
+    fn eval(&self, params: &[f64]) -> f64 {
+        self.coefficients.iter().map(|&(i, c)| c * params[i]).sum()
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let app = App::parse();
+
+    let params = match &app.params {
+        Some(path) => EvalParams::load(path)?,
+        None => EvalParams::default(),
+    };
+    let mut params = to_vec(&params);
+
+    let entries = load_entries(&app)?;
+    anyhow::ensure!(!entries.is_empty(), "no positions loaded");
+    println!("Loaded {} positions", entries.len());
+
+    let k = match app.k {
+        Some(k) => k,
+        None => fit_k(&entries, &params),
+    };
+    println!(
+        "K = {k:.4}, initial error = {:.6}",
+        error(&entries, &params, k)
+    );
+
+    let mut adam = Adam::new(NUM_PARAMS, app.learning_rate);
+    for epoch in 1..=app.epochs {
+        let gradient = gradient(&entries, &params, k);
+        adam.step(&mut params, &gradient);
+
+        if epoch % 50 == 0 || epoch == app.epochs {
+            println!("Epoch {epoch}: error = {:.6}", error(&entries, &params, k));
+        }
+    }
+
+    let params = from_vec(&params);
+    fs::write(&app.output, params.to_string())?;
+    println!("Wrote weights to {}", app.output.display());
+    if let Some(path) = &app.rust {
+        let source = format!(
+            "// Generated by the tune tool in kingly-tools\npub const TUNED_PARAMS: EvalParams = {};\n",
+            params.to_rust()
+        );
+        fs::write(path, source)?;
+        println!("Wrote Rust source to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn load_entries(app: &App) -> anyhow::Result<Vec<Entry>> {
+    let file = File::open(&app.input).context("failed to open input")?;
+    let move_gen = MoveGen::init();
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    let mut adjusted = 0;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (fen, result) = parse_line(&line).with_context(|| format!("line {}", i + 1))?;
+        let position = Position::from_fen(&fen).with_context(|| format!("line {}", i + 1))?;
+        // The static evaluation is meaningless when in check
+        if move_gen.gen_all_moves_and_check(&position).1 {
+            skipped += 1;
+            continue;
+        }
+        if endgame::adjusts_eval(&position) {
+            adjusted += 1;
+            continue;
+        }
+        entries.push(Entry::new(&position, result));
+    }
+    if skipped > 0 {
+        println!("Skipped {skipped} positions in check");
+    }
+    if adjusted > 0 {
+        println!("Skipped {adjusted} positions with endgame adjustments");
+    }
+    Ok(entries)
+}
+
+/// Parses a line into a full FEN and a result from white's perspective.
+fn parse_line(line: &str) -> anyhow::Result<(String, f64)> {
+    let (fen, result) = if let Some((fen, rest)) = line.split_once('|') {
+        (fen, rest.rsplit('|').next().unwrap_or(rest))
+    } else if let Some((fen, rest)) = line.split_once('[') {
+        (fen, rest.trim_end().trim_end_matches(']'))
+    } else if let Some((fen, rest)) = line.split_once("c9") {
+        (fen, rest.trim().trim_end_matches(';').trim_matches('"'))
+    } else {
+        bail!("no result found in '{line}'");
+    };
+
+    let result = match result.trim() {
+        "1-0" | "1" | "1.0" => 1.,
+        "0-1" | "0" | "0.0" => 0.,
+        "1/2-1/2" | "0.5" => 0.5,
+        res => bail!("invalid result '{res}'"),
+    };
+
+    // EPD positions lack the move counters, but may have other operations
+    let fields: Vec<_> = fen.split_whitespace().collect();
+    anyhow::ensure!(fields.len() >= 4, "invalid position '{}'", fen.trim());
+    let fen = match fields.get(4).map(|clock| clock.parse::<u8>()) {
+        Some(Ok(_)) if fields.len() >= 6 => fields[..6].join(" "),
+        _ => format!("{} 0 1", fields[..4].join(" ")),
+    };
+    Ok((fen, result))
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1. / (1. + 10f64.powf(-k * eval / 400.))
+}
+
+fn error(entries: &[Entry], params: &[f64], k: f64) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| (entry.result - sigmoid(entry.eval(params), k)).powi(2))
+        .sum();
+    total / entries.len() as f64
+}
+
+fn gradient(entries: &[Entry], params: &[f64], k: f64) -> Vec<f64> {
+    let mut gradient = vec![0.; NUM_PARAMS];
+    for entry in entries {
+        let s = sigmoid(entry.eval(params), k);
+        // Derivative of the squared error with respect to the evaluation
+        let d = -2. * (entry.result - s) * s * (1. - s) * k * 10f64.ln() / 400.;
+        for &(i, c) in &entry.coefficients {
+            gradient[i] += d * c;
+        }
+    }
+    let n = entries.len() as f64;
+    gradient.iter_mut().for_each(|g| *g /= n);
+    gradient
+}
+
+/// Finds the K minimizing the error of the initial parameters.
+fn fit_k(entries: &[Entry], params: &[f64]) -> f64 {
+    // Golden-section search, the error is unimodal in K
+    let ratio = (5f64.sqrt() - 1.) / 2.;
+    let (mut lo, mut hi) = (0.01, 10.);
+    while hi - lo > 1e-4 {
+        let a = hi - ratio * (hi - lo);
+        let b = lo + ratio * (hi - lo);
+        if error(entries, params, a) < error(entries, params, b) {
+            hi = b;
+        } else {
+            lo = a;
+        }
+    }
+    (lo + hi) / 2.
+}
+
+fn to_vec(params: &EvalParams) -> Vec<f64> {
+    params
+        .early
+        .iter()
+        .chain(&params.endgame)
+        .flatten()
+        .map(|&v| v as f64)
+        .chain([params.tempo as f64])
+        .collect()
+}
+
+fn from_vec(params: &[f64]) -> EvalParams {
+    let value = |i: usize| params[i].round() as i16;
+    let table = |offset: usize| {
+        [0, 1, 2, 3, 4, 5].map(|kind| std::array::from_fn(|sq| value(offset + kind * 64 + sq)))
+    };
+    EvalParams {
+        early: table(0),
+        endgame: table(6 * 64),
+        tempo: value(TEMPO),
+    }
+}
@@ -0,0 +1,3 @@
+//! Code shared between the kingly-tools binaries.
+
+pub mod adam;